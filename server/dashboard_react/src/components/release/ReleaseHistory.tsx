@@ -1,6 +1,6 @@
 import React, { useEffect, useState } from "react";
 import axios from "../../api/axios";
-import { Calendar, User, Package, Eye, AlertCircle, Loader2 } from "lucide-react";
+import { Calendar, User, Package, Eye, AlertCircle, Loader2, Smartphone } from "lucide-react";
 
 interface ReleaseHistoryEntry {
   id: string;
@@ -9,6 +9,14 @@ interface ReleaseHistoryEntry {
   created_at: string;
   created_by: string;
   metadata: Record<string, any>;
+  rollout_percentage: number;
+  status: string;
+}
+
+interface VersionDevices {
+  package_version: number;
+  active_devices: number;
+  avg_download_duration_ms: number | null;
 }
 
 interface ReleaseHistoryProps {
@@ -23,6 +31,10 @@ const ReleaseHistory: React.FC<ReleaseHistoryProps> = ({
   onSelectRelease,
 }) => {
   const [releases, setReleases] = useState<ReleaseHistoryEntry[]>([]);
+  const [nextCursor, setNextCursor] = useState<string | null>(null);
+  const [loadingMore, setLoadingMore] = useState(false);
+  const [activeDevices, setActiveDevices] = useState<Record<number, number>>({});
+  const [totalDevices, setTotalDevices] = useState(0);
   const [loading, setLoading] = useState(true);
   const [error, setError] = useState<string | null>(null);
 
@@ -40,22 +52,74 @@ const ReleaseHistory: React.FC<ReleaseHistoryProps> = ({
           }
         );
         setReleases(data.releases);
+        setNextCursor(data.next_cursor ?? null);
         setError(null);
       } catch (err: any) {
         setError(
           err.response?.data?.message || "Failed to load release history"
         );
         setReleases([]);
+        setNextCursor(null);
       } finally {
         setLoading(false);
       }
     };
 
+    // Adoption is best effort; the history is still shown without it
+    const fetchActiveDevices = async () => {
+      try {
+        const { data } = await axios.get(
+          `/organisations/applications/telemetry/devices`,
+          {
+            headers: {
+              "x-organisation": organisation,
+              "x-application": application,
+            },
+          }
+        );
+        const counts: Record<number, number> = {};
+        data.versions.forEach((version: VersionDevices) => {
+          counts[version.package_version] = version.active_devices;
+        });
+        setActiveDevices(counts);
+        setTotalDevices(data.total_devices);
+      } catch {
+        setActiveDevices({});
+        setTotalDevices(0);
+      }
+    };
+
     if (organisation && application) {
       fetchReleaseHistory();
+      fetchActiveDevices();
     }
   }, [organisation, application]);
 
+  const loadMore = async () => {
+    if (!nextCursor) return;
+    try {
+      setLoadingMore(true);
+      const { data } = await axios.get(
+        `/organisations/applications/release/history`,
+        {
+          params: { cursor: nextCursor },
+          headers: {
+            "x-organisation": organisation,
+            "x-application": application,
+          },
+        }
+      );
+      setReleases((current) => [...current, ...data.releases]);
+      setNextCursor(data.next_cursor ?? null);
+    } catch (err: any) {
+      setError(
+        err.response?.data?.message || "Failed to load release history"
+      );
+    } finally {
+      setLoadingMore(false);
+    }
+  };
+
   // Helper function to format dates
   const formatDate = (dateString: string) => {
     const date = new Date(dateString);
@@ -137,13 +201,26 @@ const ReleaseHistory: React.FC<ReleaseHistoryProps> = ({
                       </span>
                     )}
                   </div>
-                  <p className="text-white/60 text-sm">Config v{release.config_version}</p>
+                  <p className="text-white/60 text-sm">
+                    Config v{release.config_version} · {release.status} at {release.rollout_percentage}%
+                  </p>
                 </div>
               </div>
             </div>
 
             {/* Release Info */}
             <div className="flex items-center space-x-6">
+              {totalDevices > 0 && (
+                <div className="text-right">
+                  <div className="flex items-center text-white text-sm font-medium mb-1">
+                    <Smartphone size={14} className="mr-1" />
+                    <span>{activeDevices[release.package_version] ?? 0} devices</span>
+                  </div>
+                  <div className="text-white/60 text-xs">
+                    {Math.round(((activeDevices[release.package_version] ?? 0) / totalDevices) * 100)}% of active
+                  </div>
+                </div>
+              )}
               <div className="text-right">
                 <div className="flex items-center text-white/60 text-sm mb-1">
                   <User size={14} className="mr-1" />
@@ -171,6 +248,18 @@ const ReleaseHistory: React.FC<ReleaseHistoryProps> = ({
           </div>
         </div>
       ))}
+      {nextCursor && (
+        <div className="flex justify-center pt-2">
+          <button
+            onClick={loadMore}
+            disabled={loadingMore}
+            className="flex items-center px-6 py-3 bg-white/10 hover:bg-white/20 text-white rounded-xl transition-all duration-200 disabled:opacity-50"
+          >
+            {loadingMore && <Loader2 size={16} className="mr-2 animate-spin" />}
+            Load more
+          </button>
+        </div>
+      )}
     </div>
   );
 };
@@ -0,0 +1,8 @@
+-- Per-application settings used while serving release configs
+CREATE TABLE IF NOT EXISTS hyperotaserver.application_settings (
+    org_id TEXT NOT NULL,
+    app_id TEXT NOT NULL,
+    cache_control TEXT NOT NULL DEFAULT 'no-cache',
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (org_id, app_id)
+);
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS hyperotaserver.last_known_good_releases;
@@ -0,0 +1,8 @@
+-- Last release config successfully resolved for an application, served while Superposition is unavailable
+CREATE TABLE IF NOT EXISTS hyperotaserver.last_known_good_releases (
+    org_id TEXT NOT NULL,
+    app_id TEXT NOT NULL,
+    release_config JSONB NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (org_id, app_id)
+);
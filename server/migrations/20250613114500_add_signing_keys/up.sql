@@ -0,0 +1,11 @@
+-- Per-application Ed25519 keys used to sign served release configs
+CREATE TABLE IF NOT EXISTS hyperotaserver.signing_keys (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    org_id TEXT NOT NULL,
+    app_id TEXT NOT NULL,
+    public_key TEXT NOT NULL,
+    encrypted_private_key TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_signing_keys_org_app ON hyperotaserver.signing_keys (org_id, app_id);
@@ -0,0 +1,2 @@
+ALTER TABLE hyperotaserver.packages DROP COLUMN IF EXISTS index_size;
+ALTER TABLE hyperotaserver.packages DROP COLUMN IF EXISTS index_sha256;
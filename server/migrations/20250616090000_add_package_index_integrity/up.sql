@@ -0,0 +1,3 @@
+-- Integrity metadata of index files uploaded to S3 by the server
+ALTER TABLE hyperotaserver.packages ADD COLUMN IF NOT EXISTS index_sha256 TEXT;
+ALTER TABLE hyperotaserver.packages ADD COLUMN IF NOT EXISTS index_size BIGINT;
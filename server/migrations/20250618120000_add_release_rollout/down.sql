@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS hyperotaserver.idx_releases_org_app_package;
+ALTER TABLE hyperotaserver.releases DROP COLUMN IF EXISTS previous_package_version;
+ALTER TABLE hyperotaserver.releases DROP COLUMN IF EXISTS rollout_percentage;
@@ -0,0 +1,6 @@
+-- Share of devices, bucketed by device id, that receive a release instead of the previous package
+ALTER TABLE hyperotaserver.releases ADD COLUMN IF NOT EXISTS rollout_percentage INTEGER NOT NULL DEFAULT 100
+    CHECK (rollout_percentage BETWEEN 0 AND 100);
+ALTER TABLE hyperotaserver.releases ADD COLUMN IF NOT EXISTS previous_package_version INTEGER;
+
+CREATE INDEX IF NOT EXISTS idx_releases_org_app_package ON hyperotaserver.releases (org_id, app_id, package_version);
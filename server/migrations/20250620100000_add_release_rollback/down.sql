@@ -0,0 +1,3 @@
+ALTER TABLE hyperotaserver.releases DROP COLUMN IF EXISTS rolled_back_from;
+ALTER TABLE hyperotaserver.releases DROP COLUMN IF EXISTS context;
+ALTER TABLE hyperotaserver.releases DROP COLUMN IF EXISTS experiment_id;
@@ -0,0 +1,5 @@
+-- Superposition experiment and context a release was created with, and the release a rollback reverted
+ALTER TABLE hyperotaserver.releases ADD COLUMN IF NOT EXISTS experiment_id TEXT;
+ALTER TABLE hyperotaserver.releases ADD COLUMN IF NOT EXISTS context JSONB NOT NULL DEFAULT '{}'::jsonb;
+ALTER TABLE hyperotaserver.releases ADD COLUMN IF NOT EXISTS rolled_back_from UUID
+    REFERENCES hyperotaserver.releases (id);
@@ -0,0 +1,6 @@
+ALTER TABLE hyperotaserver.releases DROP COLUMN IF EXISTS aborted_at;
+ALTER TABLE hyperotaserver.releases DROP COLUMN IF EXISTS concluded_at;
+ALTER TABLE hyperotaserver.releases DROP COLUMN IF EXISTS live_at;
+ALTER TABLE hyperotaserver.releases DROP COLUMN IF EXISTS paused_at;
+ALTER TABLE hyperotaserver.releases DROP COLUMN IF EXISTS ramped_at;
+ALTER TABLE hyperotaserver.releases DROP COLUMN IF EXISTS status;
@@ -0,0 +1,17 @@
+-- Lifecycle status of a release and when it last entered each state
+ALTER TABLE hyperotaserver.releases ADD COLUMN IF NOT EXISTS status TEXT NOT NULL DEFAULT 'live'
+    CHECK (status IN ('draft', 'ramping', 'paused', 'live', 'concluded', 'aborted'));
+ALTER TABLE hyperotaserver.releases ADD COLUMN IF NOT EXISTS ramped_at TIMESTAMPTZ;
+ALTER TABLE hyperotaserver.releases ADD COLUMN IF NOT EXISTS paused_at TIMESTAMPTZ;
+ALTER TABLE hyperotaserver.releases ADD COLUMN IF NOT EXISTS live_at TIMESTAMPTZ;
+ALTER TABLE hyperotaserver.releases ADD COLUMN IF NOT EXISTS concluded_at TIMESTAMPTZ;
+ALTER TABLE hyperotaserver.releases ADD COLUMN IF NOT EXISTS aborted_at TIMESTAMPTZ;
+
+-- Existing releases were ramped when they were created; partial rollouts are still ramping
+UPDATE hyperotaserver.releases SET ramped_at = created_at, live_at = created_at
+    WHERE rollout_percentage >= 100;
+UPDATE hyperotaserver.releases SET status = 'ramping', ramped_at = created_at
+    WHERE rollout_percentage < 100;
+
+-- New releases start as drafts until their experiment is ramped
+ALTER TABLE hyperotaserver.releases ALTER COLUMN status SET DEFAULT 'draft';
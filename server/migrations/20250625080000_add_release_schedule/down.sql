@@ -0,0 +1 @@
+DROP TABLE IF EXISTS hyperotaserver.release_schedule_steps;
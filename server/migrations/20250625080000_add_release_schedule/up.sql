@@ -0,0 +1,15 @@
+-- Planned ramp steps of a release, advanced by the release scheduler
+CREATE TABLE IF NOT EXISTS hyperotaserver.release_schedule_steps (
+    release_id UUID NOT NULL REFERENCES hyperotaserver.releases (id),
+    step_index INT NOT NULL,
+    run_at TIMESTAMPTZ NOT NULL,
+    rollout_percentage INT CHECK (rollout_percentage BETWEEN 0 AND 100),
+    conclude BOOLEAN NOT NULL DEFAULT FALSE,
+    applied_at TIMESTAMPTZ,
+    PRIMARY KEY (release_id, step_index),
+    CHECK ((rollout_percentage IS NULL) = conclude)
+);
+
+CREATE INDEX IF NOT EXISTS release_schedule_steps_due_idx
+    ON hyperotaserver.release_schedule_steps (run_at)
+    WHERE applied_at IS NULL;
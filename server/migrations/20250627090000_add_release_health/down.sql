@@ -0,0 +1,4 @@
+ALTER TABLE hyperotaserver.application_settings DROP COLUMN IF EXISTS min_health_samples;
+ALTER TABLE hyperotaserver.application_settings DROP COLUMN IF EXISTS max_package_timeout_rate;
+ALTER TABLE hyperotaserver.application_settings DROP COLUMN IF EXISTS max_boot_failure_rate;
+DROP TABLE IF EXISTS hyperotaserver.release_health;
@@ -0,0 +1,19 @@
+-- Boot and update outcomes reported by devices, aggregated per release
+CREATE TABLE IF NOT EXISTS hyperotaserver.release_health (
+    release_id UUID PRIMARY KEY REFERENCES hyperotaserver.releases (id),
+    boots BIGINT NOT NULL DEFAULT 0,
+    boot_failures BIGINT NOT NULL DEFAULT 0,
+    updates BIGINT NOT NULL DEFAULT 0,
+    package_timeouts BIGINT NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Failure rates above which a rolling out release is rolled back; NULL disables the check
+ALTER TABLE hyperotaserver.application_settings
+    ADD COLUMN IF NOT EXISTS max_boot_failure_rate DOUBLE PRECISION
+        CHECK (max_boot_failure_rate BETWEEN 0 AND 1);
+ALTER TABLE hyperotaserver.application_settings
+    ADD COLUMN IF NOT EXISTS max_package_timeout_rate DOUBLE PRECISION
+        CHECK (max_package_timeout_rate BETWEEN 0 AND 1);
+ALTER TABLE hyperotaserver.application_settings
+    ADD COLUMN IF NOT EXISTS min_health_samples INT NOT NULL DEFAULT 100;
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS hyperotaserver.device_states;
+DROP TABLE IF EXISTS hyperotaserver.telemetry_events;
@@ -0,0 +1,30 @@
+-- Raw lifecycle events reported by devices
+CREATE TABLE IF NOT EXISTS hyperotaserver.telemetry_events (
+    id BIGSERIAL PRIMARY KEY,
+    org_id TEXT NOT NULL,
+    app_id TEXT NOT NULL,
+    device_id TEXT,
+    label TEXT NOT NULL,
+    package_version INT,
+    update_result TEXT,
+    download_duration_ms BIGINT,
+    received_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS telemetry_events_app_received_idx
+    ON hyperotaserver.telemetry_events (org_id, app_id, received_at);
+
+-- Latest known state of each device, for active device counts
+CREATE TABLE IF NOT EXISTS hyperotaserver.device_states (
+    org_id TEXT NOT NULL,
+    app_id TEXT NOT NULL,
+    device_id TEXT NOT NULL,
+    package_version INT,
+    last_update_result TEXT,
+    first_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (org_id, app_id, device_id)
+);
+
+CREATE INDEX IF NOT EXISTS device_states_app_seen_idx
+    ON hyperotaserver.device_states (org_id, app_id, last_seen_at);
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS hyperotaserver.package_patches;
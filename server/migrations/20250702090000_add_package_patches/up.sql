@@ -0,0 +1,13 @@
+-- Binary diffs turning a file of an older package version into the same file of a newer one
+CREATE TABLE IF NOT EXISTS hyperotaserver.package_patches (
+    org_id TEXT NOT NULL,
+    app_id TEXT NOT NULL,
+    from_version INT NOT NULL,
+    to_version INT NOT NULL,
+    file_path TEXT NOT NULL,
+    url TEXT NOT NULL,
+    sha256 TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (org_id, app_id, from_version, to_version, file_path)
+);
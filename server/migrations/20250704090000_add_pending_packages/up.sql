@@ -0,0 +1,11 @@
+-- Package versions reserved for direct-to-S3 uploads that have not been finalized yet
+CREATE TABLE IF NOT EXISTS hyperotaserver.pending_packages (
+    id UUID PRIMARY KEY,
+    org_id TEXT NOT NULL,
+    app_id TEXT NOT NULL,
+    version INT NOT NULL,
+    manifest JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (org_id, app_id, version)
+);
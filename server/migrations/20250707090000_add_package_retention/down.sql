@@ -0,0 +1,3 @@
+ALTER TABLE hyperotaserver.application_settings DROP COLUMN IF EXISTS retain_released_days;
+ALTER TABLE hyperotaserver.application_settings DROP COLUMN IF EXISTS retain_package_versions;
+ALTER TABLE hyperotaserver.packages DROP COLUMN IF EXISTS archived_at;
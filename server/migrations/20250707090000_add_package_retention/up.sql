@@ -0,0 +1,9 @@
+-- Packages removed by garbage collection keep their row, so versions are never reused
+ALTER TABLE hyperotaserver.packages ADD COLUMN IF NOT EXISTS archived_at TIMESTAMPTZ;
+
+-- Retention rules; garbage collection is disabled while retain_package_versions is NULL
+ALTER TABLE hyperotaserver.application_settings
+    ADD COLUMN IF NOT EXISTS retain_package_versions INT CHECK (retain_package_versions >= 1);
+ALTER TABLE hyperotaserver.application_settings
+    ADD COLUMN IF NOT EXISTS retain_released_days INT NOT NULL DEFAULT 90
+        CHECK (retain_released_days >= 0);
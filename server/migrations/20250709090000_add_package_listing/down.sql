@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS hyperotaserver.idx_releases_org_app_created;
+DROP INDEX IF EXISTS hyperotaserver.idx_packages_org_app_version;
+ALTER TABLE hyperotaserver.packages DROP COLUMN IF EXISTS tag;
+ALTER TABLE hyperotaserver.packages DROP COLUMN IF EXISTS created_by;
+ALTER TABLE hyperotaserver.packages DROP COLUMN IF EXISTS created_at;
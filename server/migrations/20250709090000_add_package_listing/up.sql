@@ -0,0 +1,11 @@
+-- Who created each package and when, and the app version it was built as
+ALTER TABLE hyperotaserver.packages
+    ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT now();
+ALTER TABLE hyperotaserver.packages ADD COLUMN IF NOT EXISTS created_by TEXT;
+ALTER TABLE hyperotaserver.packages ADD COLUMN IF NOT EXISTS tag TEXT;
+
+-- Keyset pagination of package and release listings
+CREATE INDEX IF NOT EXISTS idx_packages_org_app_version
+    ON hyperotaserver.packages (org_id, app_id, version);
+CREATE INDEX IF NOT EXISTS idx_releases_org_app_created
+    ON hyperotaserver.releases (org_id, app_id, created_at, id);
@@ -0,0 +1,14 @@
+-- Only the latest config of each package version can be kept
+DELETE FROM hyperotaserver.configs AS c
+WHERE EXISTS (
+    SELECT 1 FROM hyperotaserver.configs AS newer
+    WHERE newer.org_id = c.org_id
+      AND newer.app_id = c.app_id
+      AND newer.version = c.version
+      AND newer.revision > c.revision
+);
+
+DROP INDEX IF EXISTS hyperotaserver.idx_configs_org_app_version;
+ALTER TABLE hyperotaserver.configs DROP CONSTRAINT IF EXISTS configs_org_id_app_id_revision_key;
+ALTER TABLE hyperotaserver.configs ADD CONSTRAINT configs_org_id_app_id_version_key UNIQUE (org_id, app_id, version);
+ALTER TABLE hyperotaserver.configs DROP COLUMN IF EXISTS revision;
@@ -0,0 +1,18 @@
+-- Configs get their own version per application, so a package version can have several
+-- configs over time. `version` remains the package version a config is bound to.
+ALTER TABLE hyperotaserver.configs ADD COLUMN IF NOT EXISTS revision INTEGER;
+
+UPDATE hyperotaserver.configs AS c
+SET revision = numbered.revision
+FROM (
+    SELECT id, ROW_NUMBER() OVER (PARTITION BY org_id, app_id ORDER BY id) AS revision
+    FROM hyperotaserver.configs
+) AS numbered
+WHERE c.id = numbered.id;
+
+ALTER TABLE hyperotaserver.configs ALTER COLUMN revision SET NOT NULL;
+ALTER TABLE hyperotaserver.configs DROP CONSTRAINT IF EXISTS configs_org_id_app_id_version_key;
+ALTER TABLE hyperotaserver.configs
+    ADD CONSTRAINT configs_org_id_app_id_revision_key UNIQUE (org_id, app_id, revision);
+CREATE INDEX IF NOT EXISTS idx_configs_org_app_version
+    ON hyperotaserver.configs (org_id, app_id, version);
@@ -0,0 +1,12 @@
+-- JSON Schemas that configs of an application are validated against. Every change adds
+-- a version; the latest one applies.
+CREATE TABLE IF NOT EXISTS hyperotaserver.config_schemas (
+    org_id TEXT NOT NULL,
+    app_id TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    tenant_info_schema JSONB,
+    properties_schema JSONB,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    created_by TEXT NOT NULL,
+    PRIMARY KEY (org_id, app_id, version)
+);
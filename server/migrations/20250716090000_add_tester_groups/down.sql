@@ -0,0 +1,6 @@
+-- Tester releases would otherwise be served to the whole fleet
+DELETE FROM hyperotaserver.release_health
+WHERE release_id IN (SELECT id FROM hyperotaserver.releases WHERE tester_group_id IS NOT NULL);
+DELETE FROM hyperotaserver.releases WHERE tester_group_id IS NOT NULL;
+ALTER TABLE hyperotaserver.releases DROP COLUMN IF EXISTS tester_group_id;
+DROP TABLE IF EXISTS hyperotaserver.tester_groups;
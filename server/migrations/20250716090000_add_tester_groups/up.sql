@@ -0,0 +1,22 @@
+-- Named groups of devices and users that get releases before the rest of the fleet
+CREATE TABLE IF NOT EXISTS hyperotaserver.tester_groups (
+    id UUID PRIMARY KEY,
+    org_id TEXT NOT NULL,
+    app_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    device_ids TEXT[] NOT NULL DEFAULT '{}',
+    user_ids TEXT[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    created_by TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (org_id, app_id, name)
+);
+
+CREATE INDEX IF NOT EXISTS idx_tester_groups_device_ids
+    ON hyperotaserver.tester_groups USING GIN (device_ids);
+CREATE INDEX IF NOT EXISTS idx_tester_groups_user_ids
+    ON hyperotaserver.tester_groups USING GIN (user_ids);
+
+-- Releases pinned to the members of a tester group instead of served through Superposition
+ALTER TABLE hyperotaserver.releases
+    ADD COLUMN IF NOT EXISTS tester_group_id UUID REFERENCES hyperotaserver.tester_groups (id);
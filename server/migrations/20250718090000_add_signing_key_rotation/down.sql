@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS hyperotaserver.idx_signing_keys_active;
+ALTER TABLE hyperotaserver.signing_keys DROP COLUMN IF EXISTS retired_at;
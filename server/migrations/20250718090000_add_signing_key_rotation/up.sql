@@ -0,0 +1,15 @@
+-- Rotated keys stay listed so signatures made with them can still be verified
+ALTER TABLE hyperotaserver.signing_keys ADD COLUMN IF NOT EXISTS retired_at TIMESTAMPTZ;
+
+-- Concurrent first polls could generate several keys; only the newest stays active
+UPDATE hyperotaserver.signing_keys AS k
+SET retired_at = now()
+WHERE EXISTS (
+    SELECT 1 FROM hyperotaserver.signing_keys AS newer
+    WHERE newer.org_id = k.org_id
+      AND newer.app_id = k.app_id
+      AND (newer.created_at, newer.id) > (k.created_at, k.id)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_signing_keys_active
+    ON hyperotaserver.signing_keys (org_id, app_id) WHERE retired_at IS NULL;
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS hyperotaserver.release_health_reports;
+DROP TABLE IF EXISTS hyperotaserver.telemetry_keys;
@@ -0,0 +1,18 @@
+-- Key devices authenticate telemetry with; only its SHA-256 is stored
+CREATE TABLE IF NOT EXISTS hyperotaserver.telemetry_keys (
+    org_id TEXT NOT NULL,
+    app_id TEXT NOT NULL,
+    key_hash TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    created_by TEXT NOT NULL,
+    PRIMARY KEY (org_id, app_id)
+);
+
+-- Outcomes each device reported per release, so a device counts at most once per outcome
+CREATE TABLE IF NOT EXISTS hyperotaserver.release_health_reports (
+    release_id UUID NOT NULL REFERENCES hyperotaserver.releases (id),
+    device_id TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    reported_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (release_id, device_id, outcome)
+);
@@ -0,0 +1 @@
+ALTER TABLE hyperotaserver.releases DROP COLUMN IF EXISTS config_revision;
@@ -0,0 +1,12 @@
+-- Config revision a release serves, so configs stored later only go out with a new release
+ALTER TABLE hyperotaserver.releases ADD COLUMN IF NOT EXISTS config_revision INTEGER;
+
+-- Existing releases keep serving the newest config of their package
+UPDATE hyperotaserver.releases AS r
+SET config_revision = (
+    SELECT max(c.revision) FROM hyperotaserver.configs AS c
+    WHERE c.org_id = r.org_id
+      AND c.app_id = r.app_id
+      AND c.version = r.package_version
+)
+WHERE r.config_revision IS NULL;
@@ -0,0 +1,4 @@
+ALTER TABLE hyperotaserver.tester_groups ADD COLUMN IF NOT EXISTS user_ids TEXT[] NOT NULL DEFAULT '{}';
+CREATE INDEX IF NOT EXISTS idx_tester_groups_user_ids
+    ON hyperotaserver.tester_groups USING GIN (user_ids);
+ALTER TABLE hyperotaserver.tester_groups DROP COLUMN IF EXISTS token_hash;
@@ -0,0 +1,7 @@
+-- Members must present their group's token; only its SHA-256 is stored. Groups created
+-- before have none and pin nothing until a token is generated for them.
+ALTER TABLE hyperotaserver.tester_groups ADD COLUMN IF NOT EXISTS token_hash TEXT;
+
+-- Groups match device ids only: user ids are usually known to others and not secret
+DROP INDEX IF EXISTS hyperotaserver.idx_tester_groups_user_ids;
+ALTER TABLE hyperotaserver.tester_groups DROP COLUMN IF EXISTS user_ids;
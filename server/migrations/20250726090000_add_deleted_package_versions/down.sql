@@ -0,0 +1 @@
+DROP TABLE IF EXISTS hyperotaserver.deleted_package_versions;
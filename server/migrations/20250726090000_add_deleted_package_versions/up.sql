@@ -0,0 +1,10 @@
+-- Versions of deleted packages. They are never handed out again, as clients and patch keys
+-- may still refer to them.
+CREATE TABLE IF NOT EXISTS hyperotaserver.deleted_package_versions (
+    org_id TEXT NOT NULL,
+    app_id TEXT NOT NULL,
+    version INT NOT NULL,
+    deleted_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    deleted_by TEXT NOT NULL,
+    PRIMARY KEY (org_id, app_id, version)
+);
@@ -0,0 +1,4 @@
+DELETE FROM hyperotaserver.last_known_good_releases WHERE context_key <> '{}';
+ALTER TABLE hyperotaserver.last_known_good_releases DROP CONSTRAINT last_known_good_releases_pkey;
+ALTER TABLE hyperotaserver.last_known_good_releases DROP COLUMN context_key;
+ALTER TABLE hyperotaserver.last_known_good_releases ADD PRIMARY KEY (org_id, app_id);
@@ -0,0 +1,6 @@
+-- Keep one fallback per resolution context, so a device is never served another context's release.
+-- Rows saved before this were resolved for an unknown context and are dropped.
+DELETE FROM hyperotaserver.last_known_good_releases;
+ALTER TABLE hyperotaserver.last_known_good_releases ADD COLUMN context_key TEXT NOT NULL;
+ALTER TABLE hyperotaserver.last_known_good_releases DROP CONSTRAINT last_known_good_releases_pkey;
+ALTER TABLE hyperotaserver.last_known_good_releases ADD PRIMARY KEY (org_id, app_id, context_key);
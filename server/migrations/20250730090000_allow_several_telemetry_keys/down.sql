@@ -0,0 +1,12 @@
+-- Keep only the newest active key of each application
+DELETE FROM hyperotaserver.telemetry_keys WHERE revoked_at IS NOT NULL;
+DELETE FROM hyperotaserver.telemetry_keys k
+USING hyperotaserver.telemetry_keys newer
+WHERE k.org_id = newer.org_id AND k.app_id = newer.app_id
+  AND (k.created_at, k.id) < (newer.created_at, newer.id);
+DROP INDEX IF EXISTS hyperotaserver.telemetry_keys_app_idx;
+ALTER TABLE hyperotaserver.telemetry_keys DROP CONSTRAINT telemetry_keys_pkey;
+ALTER TABLE hyperotaserver.telemetry_keys DROP COLUMN revoked_by;
+ALTER TABLE hyperotaserver.telemetry_keys DROP COLUMN revoked_at;
+ALTER TABLE hyperotaserver.telemetry_keys DROP COLUMN id;
+ALTER TABLE hyperotaserver.telemetry_keys ADD PRIMARY KEY (org_id, app_id);
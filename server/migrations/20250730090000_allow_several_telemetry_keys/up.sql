@@ -0,0 +1,8 @@
+-- Several ingest keys may be active at once, so a new key can be rolled out to devices
+-- before the old one is revoked
+ALTER TABLE hyperotaserver.telemetry_keys DROP CONSTRAINT telemetry_keys_pkey;
+ALTER TABLE hyperotaserver.telemetry_keys ADD COLUMN id UUID NOT NULL DEFAULT gen_random_uuid();
+ALTER TABLE hyperotaserver.telemetry_keys ADD PRIMARY KEY (id);
+ALTER TABLE hyperotaserver.telemetry_keys ADD COLUMN revoked_at TIMESTAMPTZ;
+ALTER TABLE hyperotaserver.telemetry_keys ADD COLUMN revoked_by TEXT;
+CREATE INDEX IF NOT EXISTS telemetry_keys_app_idx ON hyperotaserver.telemetry_keys (org_id, app_id);
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS hyperotaserver.idx_tester_groups_user_ids;
+ALTER TABLE hyperotaserver.tester_groups DROP COLUMN IF EXISTS user_ids;
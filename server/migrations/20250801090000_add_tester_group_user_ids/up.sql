@@ -0,0 +1,5 @@
+-- Groups may also match the user a device is signed in as. Members still need the group's
+-- token, so user ids, which others often know, decide nothing on their own.
+ALTER TABLE hyperotaserver.tester_groups ADD COLUMN IF NOT EXISTS user_ids TEXT[] NOT NULL DEFAULT '{}';
+CREATE INDEX IF NOT EXISTS idx_tester_groups_user_ids
+    ON hyperotaserver.tester_groups USING GIN (user_ids);
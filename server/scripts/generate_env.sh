@@ -39,6 +39,7 @@ CONFIG_KEYS=(
   AWS_SESSION_TOKEN
   AWS_REGION
   PUBLIC_ENDPOINT
+  KMS_KEY_ID
 )
 
 # Indices of sensitive variables (0-based)
@@ -62,6 +63,7 @@ get_default() {
     AWS_ENDPOINT_URL) echo "http://localhost:4566" ;;
     AWS_REGION) echo "us-east-1" ;;
     PUBLIC_ENDPOINT) echo "http://localhost:5000" ;;
+    KMS_KEY_ID) echo "$KMS_KEY_ID" ;;
     *) echo "" ;;
   esac
 }
@@ -12,8 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
+use actix_multipart::form::MultipartFormConfig;
 use actix_web::{web, App, HttpServer};
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 
@@ -28,10 +29,15 @@ mod utils;
 
 use aws_sdk_s3::config::Builder;
 use dotenvy::dotenv;
+use log::info;
 use middleware::auth::Auth;
 use reqwest::Client;
 use superposition_rust_sdk::apis::configuration::Configuration;
-use utils::{db, kms::decrypt_kms, transaction_manager::start_cleanup_job};
+use utils::{
+    cache::TtlCache, db, kms::decrypt_kms, package_gc::start_package_gc,
+    release_health::start_health_watcher,
+    release_scheduler::start_release_scheduler, transaction_manager::start_cleanup_job,
+};
 
 const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
@@ -50,6 +56,19 @@ async fn main() -> std::io::Result<()> {
     let superposition_org_id_env = std::env::var("SUPERPOSITION_ORG_ID").expect("SUPERPOSITION_ORG_ID must be set");
     let bucket_name = std::env::var("AWS_BUCKET").expect("AWS_BUCKET must be set");
     let public_url = std::env::var("PUBLIC_ENDPOINT").expect("PUBLIC_ENDPOINT must be set");
+    let kms_key_id = std::env::var("KMS_KEY_ID").expect("KMS_KEY_ID must be set");
+    let release_cache_ttl: u64 = std::env::var("RELEASE_CACHE_TTL_SECS")
+        .unwrap_or_else(|_| "30".to_string()) // Default to 30 seconds if not set
+        .parse()
+        .expect("RELEASE_CACHE_TTL_SECS must be a valid number");
+    let patch_base_versions: i64 = std::env::var("PATCH_BASE_VERSIONS")
+        .unwrap_or_else(|_| "3".to_string()) // Default to the 3 previous versions if not set
+        .parse()
+        .expect("PATCH_BASE_VERSIONS must be a valid number");
+    let max_upload_size_mb: usize = std::env::var("MAX_UPLOAD_SIZE_MB")
+        .unwrap_or_else(|_| "1024".to_string()) // Default to 1 GiB per multipart request if not set
+        .parse()
+        .expect("MAX_UPLOAD_SIZE_MB must be a valid number");
 
     //Need to check if this ENV exists on pod
     let uses_local_stack = std::env::var("AWS_ENDPOINT_URL");
@@ -82,6 +101,8 @@ async fn main() -> std::io::Result<()> {
         realm,
         bucket_name,
         superposition_org_id: superposition_org_id_env,
+        kms_key_id,
+        patch_base_versions,
     };
 
     // This is required for localStack
@@ -102,6 +123,11 @@ async fn main() -> std::io::Result<()> {
             ..Default::default()
         },
         s3_client: aws_s3_client,
+        kms_client: aws_kms_client,
+        release_cache: TtlCache::new(Duration::from_secs(release_cache_ttl)),
+        // Short enough for rotated keys to be picked up by every instance soon
+        signing_keys: TtlCache::new(Duration::from_secs(300)),
+        dimension_cache: TtlCache::new(Duration::from_secs(60)),
     });
 
     // Start the background cleanup job for transaction reconciliation
@@ -109,9 +135,25 @@ async fn main() -> std::io::Result<()> {
     let _cleanup_handle = start_cleanup_job(app_state_data.clone());
     println!("Started transaction cleanup background job");
 
+    // Start the background job that advances scheduled rollouts
+    let _scheduler_handle = start_release_scheduler(app_state_data.clone());
+    info!("Started release scheduler background job");
+
+    // Start the background job that rolls back releases failing on devices
+    let _health_handle = start_health_watcher(app_state_data.clone());
+    info!("Started release health watcher background job");
+
+    // Start the background job that removes packages outside the retention rules
+    let _gc_handle = start_package_gc(app_state_data.clone());
+    info!("Started package garbage collection background job");
+
     HttpServer::new(move || {
         App::new()
             .app_data(web::Data::from(app_state.clone()))
+            // Package files are streamed to temporary files, so only the total is capped
+            .app_data(
+                MultipartFormConfig::default().total_limit(max_upload_size_mb * 1024 * 1024),
+            )
             .wrap(actix_web::middleware::Logger::default())
             .wrap(actix_web::middleware::Compress::default())
             .service(
@@ -14,29 +14,47 @@
 
 use actix_multipart::form::{text::Text, MultipartForm};
 use actix_web::{
-    error, post,
-    web::{self, Json, ReqData},
-    Result, Scope,
+    error, get, post,
+    web::{self, Json, Path, ReqData},
+    HttpResponse, Result, Scope,
 };
+use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use diesel::ExpressionMethods;
+use diesel::PgConnection;
 use diesel::QueryDsl;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use crate::{
-    middleware::auth::{validate_user, AuthResponse, WRITE},
+    middleware::auth::{validate_user, AuthResponse, READ, WRITE},
+    release::invalidate_release_cache,
     types::AppState,
-    utils::db::{
-        models::ConfigEntry,
-        schema::hyperotaserver::configs::dsl::configs as configs_table,
+    utils::{
+        db::{
+            models::ConfigEntry,
+            schema::hyperotaserver::configs::dsl::{
+                app_id, configs as configs_table, created_at, org_id, revision, version,
+            },
+        },
+        config_schema::{latest_schema, validate_config},
+        json_diff::{diff, JsonChange},
+        pagination::{decode_cursor, page_size, paginate, SortOrder},
     },
 };
 
+mod schema;
+
 pub fn add_routes() -> Scope {
     Scope::new("")
         .service(create_config_json_v1)
         .service(create_config_json_v1_multipart)
+        .service(list_configs)
+        .service(diff_configs)
+        .service(schema::create_schema)
+        .service(schema::get_latest_schema)
+        .service(schema::get_schema)
+        .service(get_config)
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -44,6 +62,10 @@ struct ConfigJsonV1Request {
     config: ConfigV1,
     tenant_info: Option<serde_json::Value>,
     properties: Option<serde_json::Value>,
+    /// Package version to bind the config to; defaults to the latest package
+    package_version: Option<i32>,
+    /// Latest revision the client has seen; the config is rejected if a newer one exists
+    base_revision: Option<i32>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -65,42 +87,93 @@ struct ConfigJsonV1MultipartRequest {
 struct Response {
     version: i32,
     config_version: String,
+    revision: i32,
 }
 
-#[post("/create_json_v1")]
-async fn create_config_json_v1(
-    req: Json<ConfigJsonV1Request>,
-    auth_response: ReqData<AuthResponse>,
-    state: web::Data<AppState>,
-) -> Result<Json<Response>, actix_web::Error> {
-    let auth_response = auth_response.into_inner();
-    let organisation =
-        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
-    let application =
-        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+#[derive(Serialize)]
+struct ConfigDetail {
+    revision: i32,
+    package_version: i32,
+    config_version: String,
+    release_config_timeout: i32,
+    package_timeout: i32,
+    tenant_info: serde_json::Value,
+    properties: serde_json::Value,
+    created_at: NaiveDateTime,
+}
 
-    let mut conn = state
-        .db_pool
-        .get()
-        .map_err(error::ErrorInternalServerError)?;
+impl From<(ConfigEntry, NaiveDateTime)> for ConfigDetail {
+    fn from((config, config_created_at): (ConfigEntry, NaiveDateTime)) -> Self {
+        ConfigDetail {
+            revision: config.revision,
+            package_version: config.version,
+            config_version: config.config_version,
+            release_config_timeout: config.release_config_timeout,
+            package_timeout: config.package_timeout,
+            tenant_info: config.tenant_info,
+            properties: config.properties,
+            created_at: config_created_at,
+        }
+    }
+}
 
-    // Find the package version to associate with the config
-    let latest_version = crate::utils::db::schema::hyperotaserver::packages::dsl::packages
-        .filter(
-            crate::utils::db::schema::hyperotaserver::packages::dsl::org_id
-                .eq(&organisation)
-                .and(
-                    crate::utils::db::schema::hyperotaserver::packages::dsl::app_id
-                        .eq(&application),
-                ),
-        )
-        .select(diesel::dsl::max(
-            crate::utils::db::schema::hyperotaserver::packages::dsl::version,
-        ))
-        .first::<Option<i32>>(&mut conn)
-        .map_err(error::ErrorInternalServerError)?;
+/// Store a new revision of the config of an application
+fn store_config(
+    state: &web::Data<AppState>,
+    conn: &mut PgConnection,
+    organisation: String,
+    application: String,
+    req: ConfigJsonV1Request,
+) -> Result<Json<Response>, actix_web::Error> {
+    use crate::utils::db::schema::hyperotaserver::packages::dsl as packages;
+
+    let ver = match req.package_version {
+        Some(package_version) => {
+            let archived = packages::packages
+                .filter(packages::org_id.eq(&organisation))
+                .filter(packages::app_id.eq(&application))
+                .filter(packages::version.eq(package_version))
+                .select(packages::archived_at.is_not_null())
+                .first::<bool>(conn)
+                .optional()
+                .map_err(error::ErrorInternalServerError)?
+                .ok_or_else(|| {
+                    error::ErrorNotFound(format!("Package version {} not found", package_version))
+                })?;
+            if archived {
+                return Err(error::ErrorConflict(format!(
+                    "Package version {} is archived",
+                    package_version
+                )));
+            }
+            package_version
+        }
+        // Find the package version to associate with the config; archived packages can no
+        // longer be released
+        None => packages::packages
+            .filter(packages::org_id.eq(&organisation))
+            .filter(packages::app_id.eq(&application))
+            .filter(packages::archived_at.is_null())
+            .select(diesel::dsl::max(packages::version))
+            .first::<Option<i32>>(conn)
+            .map_err(error::ErrorInternalServerError)?
+            .unwrap_or(0),
+    };
 
-    let ver = latest_version.unwrap_or(0);
+    let latest_revision = configs_table
+        .filter(org_id.eq(&organisation).and(app_id.eq(&application)))
+        .select(diesel::dsl::max(revision))
+        .first::<Option<i32>>(conn)
+        .map_err(error::ErrorInternalServerError)?
+        .unwrap_or(0);
+    if let Some(base_revision) = req.base_revision {
+        if base_revision != latest_revision {
+            return Err(error::ErrorConflict(format!(
+                "Config has changed since revision {}: the latest revision is {}",
+                base_revision, latest_revision
+            )));
+        }
+    }
 
     // Extract tenant_info, either from specific field or from properties
     let tenant_info = req
@@ -118,27 +191,75 @@ async fn create_config_json_v1(
     // Extract properties
     let properties = req.properties.clone().unwrap_or_else(|| json!({}));
 
+    if let Some(schema) = latest_schema(conn, &organisation, &application)
+        .map_err(error::ErrorInternalServerError)?
+    {
+        let errors = validate_config(&schema, &tenant_info, &properties)
+            .map_err(error::ErrorInternalServerError)?;
+        if !errors.is_empty() {
+            let message = format!(
+                "Config does not match config schema version {}",
+                schema.version
+            );
+            let body = json!({ "message": message, "errors": errors });
+            return Err(
+                error::InternalError::from_response(message, HttpResponse::BadRequest().json(body))
+                    .into(),
+            );
+        }
+    }
+
     // Store config data
     diesel::insert_into(configs_table)
         .values(ConfigEntry {
-            org_id: organisation,
-            app_id: application,
+            org_id: organisation.clone(),
+            app_id: application.clone(),
             version: ver,
             config_version: req.config.version.clone(),
             release_config_timeout: req.config.release_config_timeout,
             package_timeout: req.config.package_timeout,
             tenant_info,
             properties,
+            revision: latest_revision + 1,
         })
-        .execute(&mut conn)
-        .map_err(error::ErrorInternalServerError)?;
+        .execute(conn)
+        .map_err(|e| match e {
+            diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            ) => error::ErrorConflict("Config revision was created concurrently, retry"),
+            e => error::ErrorInternalServerError(e),
+        })?;
+
+    invalidate_release_cache(state, &organisation, &application);
 
     Ok(Json(Response {
         version: ver,
-        config_version: req.config.version.clone(),
+        config_version: req.config.version,
+        revision: latest_revision + 1,
     }))
 }
 
+#[post("/create_json_v1")]
+async fn create_config_json_v1(
+    req: Json<ConfigJsonV1Request>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<Response>, actix_web::Error> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    store_config(&state, &mut conn, organisation, application, req.into_inner())
+}
+
 #[post("/create_json_v1/multipart")]
 async fn create_config_json_v1_multipart(
     MultipartForm(form): MultipartForm<ConfigJsonV1MultipartRequest>,
@@ -160,57 +281,165 @@ async fn create_config_json_v1_multipart(
         .get()
         .map_err(error::ErrorInternalServerError)?;
 
-    // Find the package version to associate with the config
-    let latest_version = crate::utils::db::schema::hyperotaserver::packages::dsl::packages
-        .filter(
-            crate::utils::db::schema::hyperotaserver::packages::dsl::org_id
-                .eq(&organisation)
-                .and(
-                    crate::utils::db::schema::hyperotaserver::packages::dsl::app_id
-                        .eq(&application),
-                ),
-        )
-        .select(diesel::dsl::max(
-            crate::utils::db::schema::hyperotaserver::packages::dsl::version,
-        ))
-        .first::<Option<i32>>(&mut conn)
+    store_config(&state, &mut conn, organisation, application, req)
+}
+
+fn find_config(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    config_revision: i32,
+) -> Result<ConfigDetail> {
+    configs_table
+        .filter(org_id.eq(organisation).and(app_id.eq(application)))
+        .filter(revision.eq(config_revision))
+        .select((ConfigEntry::as_select(), created_at))
+        .first::<(ConfigEntry, NaiveDateTime)>(conn)
+        .optional()
+        .map_err(error::ErrorInternalServerError)?
+        .map(ConfigDetail::from)
+        .ok_or_else(|| error::ErrorNotFound(format!("Config revision {} not found", config_revision)))
+}
+
+#[derive(Debug, Deserialize)]
+struct ConfigListQuery {
+    limit: Option<i64>,
+    cursor: Option<String>,
+    #[serde(default)]
+    order: SortOrder,
+    /// Only configs bound to this package version
+    package_version: Option<i32>,
+}
+
+#[derive(Serialize)]
+struct ConfigList {
+    configs: Vec<ConfigDetail>,
+    /// Pass as `cursor` to fetch the next page; absent on the last page
+    next_cursor: Option<String>,
+}
+
+#[get("")]
+async fn list_configs(
+    query: web::Query<ConfigListQuery>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<ConfigList>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, READ).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, READ).map_err(error::ErrorUnauthorized)?;
+
+    let query = query.into_inner();
+    let limit = page_size(query.limit)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let mut config_query = configs_table
+        .filter(org_id.eq(&organisation).and(app_id.eq(&application)))
+        .into_boxed();
+    if let Some(package_version) = query.package_version {
+        config_query = config_query.filter(version.eq(package_version));
+    }
+    if let Some(cursor) = query.cursor {
+        let after_revision = decode_cursor(&cursor)?
+            .parse::<i32>()
+            .map_err(|_| error::ErrorBadRequest("Invalid cursor"))?;
+        config_query = match query.order {
+            SortOrder::Asc => config_query.filter(revision.gt(after_revision)),
+            SortOrder::Desc => config_query.filter(revision.lt(after_revision)),
+        };
+    }
+    config_query = match query.order {
+        SortOrder::Asc => config_query.order_by(revision.asc()),
+        SortOrder::Desc => config_query.order_by(revision.desc()),
+    };
+
+    let entries = config_query
+        .select((ConfigEntry::as_select(), created_at))
+        .limit(limit + 1)
+        .load::<(ConfigEntry, NaiveDateTime)>(&mut conn)
         .map_err(error::ErrorInternalServerError)?;
+    let (entries, next_cursor) = paginate(entries, limit, |(config, _)| config.revision.to_string());
 
-    let ver = latest_version.unwrap_or(0);
+    Ok(Json(ConfigList {
+        configs: entries.into_iter().map(ConfigDetail::from).collect(),
+        next_cursor,
+    }))
+}
 
-    // Extract tenant_info, either from specific field or from properties
-    let tenant_info = req
-        .tenant_info
-        .clone()
-        .or_else(|| {
-            req.config
-                .properties
-                .get("tenant_info")
-                .and_then(|v| v.as_object())
-                .map(|obj| json!(obj))
-        })
-        .unwrap_or_else(|| json!({}));
+#[get("/{revision}")]
+async fn get_config(
+    path: Path<i32>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<ConfigDetail>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, READ).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, READ).map_err(error::ErrorUnauthorized)?;
 
-    // Extract properties
-    let properties = req.properties.clone().unwrap_or_else(|| json!({}));
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
 
-    // Store config data
-    diesel::insert_into(configs_table)
-        .values(ConfigEntry {
-            org_id: organisation,
-            app_id: application,
-            version: ver,
-            config_version: req.config.version.clone(),
-            release_config_timeout: req.config.release_config_timeout,
-            package_timeout: req.config.package_timeout,
-            tenant_info,
-            properties,
-        })
-        .execute(&mut conn)
+    let config = find_config(&mut conn, &organisation, &application, path.into_inner())?;
+    Ok(Json(config))
+}
+
+#[derive(Debug, Deserialize)]
+struct ConfigDiffQuery {
+    from: i32,
+    to: i32,
+}
+
+#[derive(Serialize)]
+struct ConfigDiff {
+    from: i32,
+    to: i32,
+    changes: Vec<JsonChange>,
+}
+
+/// Fields of a config that are compared by a diff
+fn diffable(config: &ConfigDetail) -> serde_json::Value {
+    json!({
+        "package_version": config.package_version,
+        "config_version": config.config_version,
+        "release_config_timeout": config.release_config_timeout,
+        "package_timeout": config.package_timeout,
+        "tenant_info": config.tenant_info,
+        "properties": config.properties,
+    })
+}
+
+#[get("/diff")]
+async fn diff_configs(
+    query: web::Query<ConfigDiffQuery>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<ConfigDiff>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, READ).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, READ).map_err(error::ErrorUnauthorized)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
         .map_err(error::ErrorInternalServerError)?;
 
-    Ok(Json(Response {
-        version: ver,
-        config_version: req.config.version.clone(),
+    let from = find_config(&mut conn, &organisation, &application, query.from)?;
+    let to = find_config(&mut conn, &organisation, &application, query.to)?;
+
+    Ok(Json(ConfigDiff {
+        from: query.from,
+        to: query.to,
+        changes: diff(&diffable(&from), &diffable(&to)),
     }))
 }
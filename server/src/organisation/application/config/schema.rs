@@ -0,0 +1,158 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Versioned JSON Schemas for the configs of an application. Every change stores a new
+// version; configs are validated against the latest one when they are created.
+
+use actix_web::{
+    error, get, post,
+    web::{self, Json, Path, ReqData},
+    Result,
+};
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    middleware::auth::{validate_user, AuthResponse, READ, WRITE},
+    types::AppState,
+    utils::{
+        config_schema::{check_schema, latest_schema},
+        db::{models::ConfigSchema, schema::hyperotaserver::config_schemas::dsl as config_schemas},
+    },
+};
+
+#[derive(Debug, Deserialize)]
+struct ConfigSchemaRequest {
+    /// JSON Schema for `tenant_info`; omit to accept any value
+    tenant_info_schema: Option<serde_json::Value>,
+    /// JSON Schema for `properties`; omit to accept any value
+    properties_schema: Option<serde_json::Value>,
+    /// Latest schema version the client has seen; the schema is rejected if a newer one exists
+    base_version: Option<i32>,
+}
+
+#[post("/schema")]
+async fn create_schema(
+    req: Json<ConfigSchemaRequest>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<ConfigSchema>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+
+    let req = req.into_inner();
+    if let Some(schema) = &req.tenant_info_schema {
+        check_schema("tenant_info_schema", schema).map_err(error::ErrorBadRequest)?;
+    }
+    if let Some(schema) = &req.properties_schema {
+        check_schema("properties_schema", schema).map_err(error::ErrorBadRequest)?;
+    }
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let latest_version = latest_schema(&mut conn, &organisation, &application)
+        .map_err(error::ErrorInternalServerError)?
+        .map_or(0, |schema| schema.version);
+    if let Some(base_version) = req.base_version {
+        if base_version != latest_version {
+            return Err(error::ErrorConflict(format!(
+                "Config schema has changed since version {}: the latest version is {}",
+                base_version, latest_version
+            )));
+        }
+    }
+
+    let schema = ConfigSchema {
+        org_id: organisation,
+        app_id: application,
+        version: latest_version + 1,
+        tenant_info_schema: req.tenant_info_schema,
+        properties_schema: req.properties_schema,
+        created_at: Utc::now(),
+        created_by: auth_response.sub,
+    };
+    diesel::insert_into(config_schemas::config_schemas)
+        .values(&schema)
+        .execute(&mut conn)
+        .map_err(|e| match e {
+            diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            ) => error::ErrorConflict("Config schema version was created concurrently, retry"),
+            e => error::ErrorInternalServerError(e),
+        })?;
+
+    Ok(Json(schema))
+}
+
+#[get("/schema")]
+async fn get_latest_schema(
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<ConfigSchema>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, READ).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, READ).map_err(error::ErrorUnauthorized)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let schema = latest_schema(&mut conn, &organisation, &application)
+        .map_err(error::ErrorInternalServerError)?
+        .ok_or_else(|| error::ErrorNotFound("No config schema registered"))?;
+    Ok(Json(schema))
+}
+
+#[get("/schema/{version}")]
+async fn get_schema(
+    path: Path<i32>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<ConfigSchema>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, READ).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, READ).map_err(error::ErrorUnauthorized)?;
+    let schema_version = path.into_inner();
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let schema = config_schemas::config_schemas
+        .filter(config_schemas::org_id.eq(&organisation))
+        .filter(config_schemas::app_id.eq(&application))
+        .filter(config_schemas::version.eq(schema_version))
+        .select(ConfigSchema::as_select())
+        .first(&mut conn)
+        .optional()
+        .map_err(error::ErrorInternalServerError)?
+        .ok_or_else(|| {
+            error::ErrorNotFound(format!("Config schema version {} not found", schema_version))
+        })?;
+    Ok(Json(schema))
+}
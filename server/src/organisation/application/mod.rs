@@ -34,6 +34,7 @@ use crate::types::AppState;
 use diesel::RunQueryDsl;
 
 use crate::utils::keycloak::get_token;
+use crate::utils::signing::provision_signing_key;
 use crate::utils::transaction_manager::TransactionManager;
 use crate::utils::db::schema::hyperotaserver::workspace_names;
 use crate::utils::db::models::{NewWorkspaceName, WorkspaceName};
@@ -42,6 +43,10 @@ mod config;
 mod package;
 mod release;
 mod dimension;
+mod settings;
+mod signing_key;
+mod telemetry;
+mod tester_group;
 
 use diesel::prelude::*;
 use diesel::ExpressionMethods;
@@ -54,6 +59,10 @@ pub fn add_routes() -> Scope {
         .service(Scope::new("/release").service(release::add_routes()))
         .service(Scope::new("/config").service(config::add_routes()))
         .service(Scope::new("/dimension").service(dimension::add_routes()))
+        .service(Scope::new("/settings").service(settings::add_routes()))
+        .service(Scope::new("/signing_key").service(signing_key::add_routes()))
+        .service(Scope::new("/telemetry").service(telemetry::add_routes()))
+        .service(Scope::new("/tester_group").service(tester_group::add_routes()))
 }
 
 #[derive(Serialize, Deserialize)]
@@ -509,6 +518,21 @@ async fn add_application(
         )
         .await?;
 
+        // Step 6: Provision the signing key, so SDKs can pin it before the first release
+        if let Err(e) = provision_signing_key(&state, &organisation, &application, &mut conn).await {
+            if let Err(rollback_err) = transaction
+                .handle_rollback_if_needed(&admin, &realm, &state)
+                .await
+            {
+                println!("Rollback failed: {}", rollback_err);
+            }
+
+            return Err(error::ErrorInternalServerError(format!(
+                "Failed to provision signing key: {}",
+                e
+            )));
+        }
+
         // Mark transaction as complete since all operations have succeeded
         transaction.set_database_inserted();
 
@@ -15,27 +15,45 @@
 use crate::utils::workspace::get_workspace_name_for_application;
 use crate::{
     middleware::auth::{validate_user, AuthResponse, READ, WRITE},
+    release::{invalidate_release_cache, targeting::compile_targeting},
     types::AppState,
     utils::{
         db::{
-            models::{PackageEntry, PackageEntryRead},
+            models::{PackageEntry, PackageEntryRead, PendingPackage, ReleaseEntry, ReleaseStatus},
             schema::hyperotaserver::{
-                packages::{app_id, dsl::packages, org_id, version},
+                configs::dsl as configs,
+                deleted_package_versions::dsl as deleted_versions,
+                package_patches::dsl as patches,
+                packages::{
+                    app_id, archived_at, created_at, created_by, dsl::packages, id, org_id, tag,
+                    version,
+                },
+                pending_packages::dsl as pending,
+                releases::dsl as releases,
             },
+            DbPool,
         },
-        s3::push_file,
+        package_gc::{collect_packages, CollectionReport},
+        pagination::{decode_cursor, page_size, paginate, SortOrder},
+        patches::spawn_patch_generation,
+        settings::find_settings,
+        s3::{file_digest, object_url, push_file_by_content},
     },
 };
 use actix_multipart::form::{tempfile::TempFile, text::Text, MultipartForm};
 use actix_web::{
+    delete,
     error::{self},
     get, post,
     web::{self, Json, ReqData},
-    Result, Scope,
+    HttpResponse, Result, Scope,
 };
+use chrono::{DateTime, Duration, Utc};
 use diesel::dsl::max;
+use log::{debug, error, warn};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use uuid::Uuid;
 use superposition_rust_sdk::{
     apis::default_api::create_experiment,
     models,
@@ -45,130 +63,382 @@ use diesel::prelude::*;
 use diesel::ExpressionMethods;
 use diesel::QueryDsl;
 
+mod upload;
+
 pub fn add_routes() -> Scope {
     Scope::new("")
         .service(list)
+        .service(create)
         .service(create_json)
         .service(create_package_json_v1)
         .service(create_json_v1_multipart)
+        .service(upload::create_upload)
+        .service(upload::finalize_upload)
+        .service(collect_garbage)
+        .service(get_package)
+        .service(archive_package)
+        .service(delete_package)
+}
+
+#[derive(Serialize)]
+struct Response {
+    version: i32,
 }
 
+/// Package upload with every file. Each file's multipart filename is its path within the
+/// package; `json` carries the package metadata of a V1 request without any files.
 #[derive(Debug, MultipartForm)]
 struct PackageCreateRequest {
-    #[multipart(rename = "splits")]
-    files: Vec<TempFile>,
+    #[multipart(rename = "json")]
+    json: Text<String>,
     #[multipart(rename = "index")]
     index: TempFile,
-    #[multipart(rename = "version_splits")]
-    version_splits: Text<bool>,
+    #[multipart(rename = "important")]
+    important: Vec<TempFile>,
+    #[multipart(rename = "lazy")]
+    lazy: Vec<TempFile>,
+    #[multipart(rename = "resources")]
+    resources: Vec<TempFile>,
 }
 
-#[derive(Serialize)]
-struct Response {
+#[derive(Debug, Deserialize, Serialize)]
+struct PackageUploadInfo {
+    name: String,
+    version: String,
+    #[serde(flatten)]
+    properties: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct PackageUploadManifest {
+    package: PackageUploadInfo,
+    #[serde(default)]
+    contexts: Vec<PackageContext>,
+    #[serde(default)]
+    targeting: Option<serde_json::Value>,
+}
+
+/// How long a version reserved by a single-request create is held if it is never released
+const RESERVATION_EXPIRY_SECS: i64 = 3600;
+
+/// Version for a new package: one past the stored packages, those reserved by pending
+/// uploads and those deleted, so no version is ever reused
+fn next_package_version(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+) -> Result<i32> {
+    let latest_version = packages
+        .filter(org_id.eq(organisation).and(app_id.eq(application)))
+        .select(max(version))
+        .first::<Option<i32>>(conn)
+        .map_err(error::ErrorInternalServerError)?;
+    let latest_reserved = pending::pending_packages
+        .filter(pending::org_id.eq(organisation))
+        .filter(pending::app_id.eq(application))
+        .select(max(pending::version))
+        .first::<Option<i32>>(conn)
+        .map_err(error::ErrorInternalServerError)?;
+    let latest_deleted = deleted_versions::deleted_package_versions
+        .filter(deleted_versions::org_id.eq(organisation))
+        .filter(deleted_versions::app_id.eq(application))
+        .select(max(deleted_versions::version))
+        .first::<Option<i32>>(conn)
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(latest_version
+        .max(latest_reserved)
+        .max(latest_deleted)
+        .unwrap_or(0)
+        + 1)
+}
+
+/// Reserve the next package version in `pending_packages` until it is stored or expires
+fn reserve_package_version(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    manifest: serde_json::Value,
+    expires_in: Duration,
+) -> Result<PendingPackage> {
+    let now = Utc::now();
+    let reservation = PendingPackage {
+        id: Uuid::new_v4(),
+        org_id: organisation.to_string(),
+        app_id: application.to_string(),
+        version: next_package_version(conn, organisation, application)?,
+        manifest,
+        created_at: now,
+        expires_at: now + expires_in,
+    };
+    diesel::insert_into(pending::pending_packages)
+        .values(&reservation)
+        .execute(conn)
+        .map_err(|e| match e {
+            diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            ) => error::ErrorConflict("Package version was reserved concurrently, retry"),
+            e => error::ErrorInternalServerError(e),
+        })?;
+    Ok(reservation)
+}
+
+/// Version held by a package created in a single request. Dropping it releases the
+/// reservation, so it must live until the package row is stored or creating it failed.
+struct VersionReservation {
+    db_pool: DbPool,
+    id: Uuid,
     version: i32,
 }
 
-// #[post("/create")]
-// async fn create(
-//     MultipartForm(form): MultipartForm<PackageCreateRequest>,
-//     auth_response: ReqData<AuthResponse>,
-//     state: web::Data<AppState>,
-// ) -> Result<Json<Response>, actix_web::Error> {
-//     let mut file_list: Vec<String> = vec![];
-//     let files = form.files;
-//     let index_name = form.index.file_name.clone().unwrap_or_default();
-
-//     println!("index_name: {:?}", index_name);
-
-//     // Make push to s3 a util function
-//     // Push index first to a versioned path
-//     // Push other files to versioned / non versioned path based on the url sent in the request
-//     let auth_response = auth_response.into_inner();
-
-//     println!("auth_response : {:?}", auth_response.organisation);
-//     let organisation =
-//         validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
-//     let application =
-//         validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
-
-//     println!("organisation: {:?}", organisation);
-//     println!("application: {:?}", application);
-
-//     // Ideally I should create a new schema / table for each org/application
-//     // Get incremental version for package
-//     // Store package against application group path
-//     // Create package needs write ACL on the application
-
-//     let mut conn = state
-//         .db_pool
-//         .get()
-//         .map_err(error::ErrorInternalServerError)?;
-
-//     let latest_version = packages
-//         .filter(org_id.eq(&organisation).and(app_id.eq(&application)))
-//         .select(max(version))
-//         .first::<Option<i32>>(&mut conn);
-
-//     println!("latest_version: {:?}", latest_version);
-
-//     let ver = latest_version.unwrap_or(Some(0)).unwrap_or(0);
-//     let ver = ver + 1;
-
-//     let s3_client = &state.s3_client;
-//     let version_splits = form.version_splits.into_inner();
-
-//     push_file(
-//         s3_client,
-//         state.env.bucket_name.clone(),
-//         form.index,
-//         format!(
-//             "assets/{}/{}/{}/{}",
-//             organisation, application, ver, index_name
-//         ),
-//     )
-//     .await
-//     .map_err(error::ErrorInternalServerError)?;
-
-//     for file in files {
-//         if let Some(filename) = file.file_name.clone() {
-//             let file_path = {
-//                 if version_splits {
-//                     format!(
-//                         "assets/{}/{}/{}/{}",
-//                         organisation, application, ver, filename
-//                     )
-//                 } else {
-//                     format!("assets/{}/{}/{}", organisation, application, filename)
-//                 }
-//             };
-
-//             match push_file(s3_client, state.env.bucket_name.clone(), file, file_path).await {
-//                 Ok(_) => {
-//                     // Add list of files to save in db
-//                     file_list.push(filename);
-//                 }
-//                 Err(e) => {
-//                     return Err(actix_web::error::ErrorInternalServerError(e));
-//                 }
-//             }
-//         }
-//     }
-
-//     diesel::insert_into(packages)
-//         .values(PackageEntry {
-//             version: ver,
-//             app_id: application,
-//             org_id: organisation,
-//             contents: file_list.into_iter().map(Some).collect(),
-//             index: index_name,
-//             version_splits,
-//             use_urls: false,
-//         })
-//         .execute(&mut conn)
-//         .map_err(error::ErrorInternalServerError)?;
-
-//     Ok(Json(Response {}))
-// }
+impl VersionReservation {
+    /// `manifest` lists the content hashes of the files about to be uploaded, in the format of
+    /// `/uploads`, so package GC keeps their objects while the reservation is held
+    fn new(
+        state: &AppState,
+        conn: &mut PgConnection,
+        organisation: &str,
+        application: &str,
+        manifest: serde_json::Value,
+    ) -> Result<Self> {
+        // Files are already received, so only uploading them to S3 happens while it is held
+        let reservation = reserve_package_version(
+            conn,
+            organisation,
+            application,
+            manifest,
+            Duration::seconds(RESERVATION_EXPIRY_SECS),
+        )?;
+        Ok(VersionReservation {
+            db_pool: state.db_pool.clone(),
+            id: reservation.id,
+            version: reservation.version,
+        })
+    }
+}
+
+impl Drop for VersionReservation {
+    fn drop(&mut self) {
+        let released = self.db_pool.get().map_err(|e| e.to_string()).and_then(|mut conn| {
+            diesel::delete(pending::pending_packages.filter(pending::id.eq(self.id)))
+                .execute(&mut conn)
+                .map_err(|e| e.to_string())
+        });
+        // An unreleased version is purged by package GC once it expires
+        if let Err(e) = released {
+            warn!("Failed to release reserved package version {}: {}", self.version, e);
+        }
+    }
+}
+
+/// Map a failed package insert, which conflicts when the version is taken concurrently
+fn package_insert_error(e: diesel::result::Error) -> actix_web::Error {
+    match e {
+        diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::UniqueViolation,
+            _,
+        ) => error::ErrorConflict("Package version was taken concurrently, retry"),
+        e => error::ErrorInternalServerError(e),
+    }
+}
+
+/// Check that a file path is relative to the package root and stays within it
+fn validate_file_path(file_path: &str) -> Result<()> {
+    if file_path.is_empty()
+        || file_path.starts_with('/')
+        || file_path.contains('\\')
+        || file_path.split('/').any(|segment| segment.is_empty() || segment == "." || segment == "..")
+    {
+        return Err(error::ErrorBadRequest(format!(
+            "Invalid file name {:?}: expected a relative path within the package",
+            file_path
+        )));
+    }
+    Ok(())
+}
+
+/// Path of an uploaded file within its package, from its multipart filename
+fn upload_file_path(file: &TempFile) -> Result<String> {
+    let file_path = file.file_name.clone().unwrap_or_default();
+    validate_file_path(&file_path)?;
+    Ok(file_path)
+}
+
+/// Uploaded file whose content hash is known, before it is stored
+struct DigestedFile {
+    file_path: String,
+    sha256: String,
+    size: i64,
+    file: TempFile,
+}
+
+impl DigestedFile {
+    async fn new(file_path: String, file: TempFile) -> Result<Self> {
+        let (sha256, size) = file_digest(file.file.path())
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+        Ok(DigestedFile {
+            file_path,
+            sha256,
+            size,
+            file,
+        })
+    }
+
+    /// Entry of the file in a reservation manifest
+    fn declaration(&self) -> serde_json::Value {
+        json!({ "filePath": self.file_path, "sha256": self.sha256, "size": self.size })
+    }
+}
+
+async fn digest_package_files(files: Vec<TempFile>) -> Result<Vec<DigestedFile>> {
+    let mut digested = Vec::with_capacity(files.len());
+    for file in files {
+        digested.push(DigestedFile::new(upload_file_path(&file)?, file).await?);
+    }
+    Ok(digested)
+}
+
+/// Upload a file to the content-addressed store of the application, unless an identical
+/// file is already stored, returning its public URL, SHA-256 and size
+async fn upload_package_file(
+    state: &AppState,
+    organisation: &str,
+    application: &str,
+    digested: DigestedFile,
+) -> Result<crate::utils::db::models::File> {
+    let DigestedFile {
+        file_path,
+        sha256,
+        size,
+        file,
+    } = digested;
+
+    let s3_path = push_file_by_content(
+        &state.s3_client,
+        state.env.bucket_name.clone(),
+        file,
+        organisation,
+        application,
+        &sha256,
+    )
+    .await
+    .map_err(|e| {
+        error!("Failed to upload {}: {:?}", file_path, e);
+        error::ErrorInternalServerError(format!("Failed to upload {}", file_path))
+    })?;
+
+    Ok(crate::utils::db::models::File {
+        url: object_url(&state.env, &s3_path),
+        file_path,
+        sha256: Some(sha256),
+        size: Some(size),
+    })
+}
+
+async fn upload_package_files(
+    state: &AppState,
+    organisation: &str,
+    application: &str,
+    files: Vec<DigestedFile>,
+) -> Result<Vec<crate::utils::db::models::File>> {
+    let mut uploaded = Vec::with_capacity(files.len());
+    for file in files {
+        uploaded.push(upload_package_file(state, organisation, application, file).await?);
+    }
+    Ok(uploaded)
+}
+
+#[post("/create")]
+async fn create(
+    MultipartForm(form): MultipartForm<PackageCreateRequest>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<Response>, actix_web::Error> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+
+    let manifest: PackageUploadManifest = serde_json::from_str(&form.json.into_inner())
+        .map_err(|e| error::ErrorBadRequest(format!("Invalid JSON: {}", e)))?;
+
+    // Check every file name before anything is uploaded
+    let index_path = upload_file_path(&form.index)?;
+    let mut seen = std::collections::HashSet::from([index_path.clone()]);
+    for file in form.important.iter().chain(&form.lazy).chain(&form.resources) {
+        let file_path = upload_file_path(file)?;
+        if !seen.insert(file_path.clone()) {
+            return Err(error::ErrorBadRequest(format!(
+                "File {} is uploaded more than once",
+                file_path
+            )));
+        }
+    }
+
+    let index = DigestedFile::new(index_path, form.index).await?;
+    let important = digest_package_files(form.important).await?;
+    let lazy = digest_package_files(form.lazy).await?;
+    let resources = digest_package_files(form.resources).await?;
+    let declarations =
+        |files: &[DigestedFile]| files.iter().map(DigestedFile::declaration).collect::<Vec<_>>();
+    let reservation_manifest = json!({
+        "index": index.declaration(),
+        "important": declarations(&important),
+        "lazy": declarations(&lazy),
+        "resources": declarations(&resources),
+    });
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let reservation = VersionReservation::new(
+        &state,
+        &mut conn,
+        &organisation,
+        &application,
+        reservation_manifest,
+    )?;
+    let ver = reservation.version;
+
+    let index = upload_package_file(&state, &organisation, &application, index).await?;
+    let important = upload_package_files(&state, &organisation, &application, important).await?;
+    let lazy = upload_package_files(&state, &organisation, &application, lazy).await?;
+    let resources = upload_package_files(&state, &organisation, &application, resources).await?;
+
+    let req = PackageJsonV1Request {
+        package: PackageV1 {
+            name: manifest.package.name,
+            version: manifest.package.version,
+            properties: manifest.package.properties,
+            index: index.url,
+            important,
+            lazy,
+        },
+        resources,
+        contexts: manifest.contexts,
+        targeting: manifest.targeting,
+    };
+    let index_integrity = index.sha256.zip(index.size);
+
+    store_package_v1(
+        &state,
+        &mut conn,
+        NewPackage {
+            organisation,
+            application,
+            version: ver,
+            user_id: auth_response.sub,
+        },
+        req,
+        index_integrity,
+    )
+    .await
+}
 
 #[derive(Debug, Deserialize, Serialize)]
 struct PackageConfig {
@@ -257,13 +527,9 @@ async fn create_json(
         .get()
         .map_err(error::ErrorInternalServerError)?;
 
-    let latest_version = packages
-        .filter(org_id.eq(&organisation).and(app_id.eq(&application)))
-        .select(max(version))
-        .first::<Option<i32>>(&mut conn)
-        .map_err(error::ErrorInternalServerError)?;
-
-    let ver = latest_version.unwrap_or(0) + 1;
+    let reservation =
+        VersionReservation::new(&state, &mut conn, &organisation, &application, json!({}))?;
+    let ver = reservation.version;
 
     let index_url = &req.package.index;
     let index_name = index_url
@@ -394,8 +660,10 @@ async fn create_json(
             if let Some(filename) = file_path.split('/').last() {
                 // For now, treat manifest files as important
                 important_files.push(crate::utils::db::models::File {
-                    url: format!("{}/{}", state.env.public_url, file_path),
+                    url: object_url(&state.env, file_path),
                     file_path: filename.to_string(),
+                    sha256: None,
+                    size: None,
                 });
             }
         }
@@ -408,6 +676,8 @@ async fn create_json(
                 important_files.push(crate::utils::db::models::File {
                     url: entry.url.clone(),
                     file_path: entry.filePath.clone(),
+                    sha256: None,
+                    size: None,
                 });
             }
         }
@@ -416,6 +686,8 @@ async fn create_json(
                 lazy_files.push(crate::utils::db::models::File {
                     url: entry.url.clone(),
                     file_path: entry.filePath.clone(),
+                    sha256: None,
+                    size: None,
                 });
             }
         }
@@ -427,6 +699,8 @@ async fn create_json(
                 important_files.push(crate::utils::db::models::File {
                     url: entry.url.clone(),
                     file_path: entry.filePath.clone(),
+                    sha256: None,
+                    size: None,
                 });
             }
         }
@@ -435,6 +709,8 @@ async fn create_json(
                 lazy_files.push(crate::utils::db::models::File {
                     url: entry.url.clone(),
                     file_path: entry.filePath.clone(),
+                    sha256: None,
+                    size: None,
                 });
             }
         }
@@ -443,8 +719,8 @@ async fn create_json(
     diesel::insert_into(packages)
         .values(PackageEntry {
             version: ver,
-            app_id: application,
-            org_id: organisation,
+            app_id: application.clone(),
+            org_id: organisation.clone(),
             index: index_name,
             version_splits: true,
             use_urls: true,
@@ -453,9 +729,17 @@ async fn create_json(
             important: serde_json::to_value(&important_files).map_err(error::ErrorInternalServerError)?,
             lazy: serde_json::to_value(&lazy_files).map_err(error::ErrorInternalServerError)?,
             resources: serde_json::Value::Array(vec![]), // Default to empty array for create_json
+            index_sha256: None,
+            index_size: None,
+            created_at: Utc::now(),
+            created_by: auth_response.sub.clone(),
+            tag: Some(req.package.version.clone()),
         })
         .execute(&mut conn)
-        .map_err(error::ErrorInternalServerError)?;
+        .map_err(package_insert_error)?;
+
+    invalidate_release_cache(&state, &organisation, &application);
+    spawn_patch_generation(state.clone(), organisation, application, ver);
 
     Ok(Json(Response { version: ver }))
 }
@@ -463,6 +747,8 @@ async fn create_json(
 #[derive(Serialize)]
 struct PackageList {
     packages: Vec<Package>,
+    /// Pass as `cursor` to fetch the next page; absent on the last page
+    next_cursor: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -472,10 +758,30 @@ struct Package {
     lazy: Vec<crate::utils::db::models::File>,
     version: i32,
     id: String,
+    created_at: DateTime<Utc>,
+    created_by: Option<String>,
+    tag: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PackageListQuery {
+    limit: Option<i64>,
+    cursor: Option<String>,
+    #[serde(default)]
+    order: SortOrder,
+    /// Inclusive range of package versions
+    from_version: Option<i32>,
+    to_version: Option<i32>,
+    created_by: Option<String>,
+    /// Inclusive range of creation times
+    created_after: Option<DateTime<Utc>>,
+    created_before: Option<DateTime<Utc>>,
+    tag: Option<String>,
 }
 
 #[get("")]
 async fn list(
+    query: web::Query<PackageListQuery>,
     state: web::Data<AppState>,
     auth_response: ReqData<AuthResponse>,
 ) -> Result<Json<PackageList>> {
@@ -485,75 +791,345 @@ async fn list(
     let application =
         validate_user(auth_response.application, READ).map_err(error::ErrorUnauthorized)?;
 
+    let query = query.into_inner();
+    let limit = page_size(query.limit)?;
+
     let mut conn = state
         .db_pool
         .get()
         .map_err(error::ErrorInternalServerError)?;
 
-    let entries = packages
-        .filter(org_id.eq(organisation).and(app_id.eq(application)))
+    let mut entries_query = packages
+        .filter(org_id.eq(&organisation).and(app_id.eq(&application)))
+        .filter(archived_at.is_null())
+        .into_boxed();
+    if let Some(from_version) = query.from_version {
+        entries_query = entries_query.filter(version.ge(from_version));
+    }
+    if let Some(to_version) = query.to_version {
+        entries_query = entries_query.filter(version.le(to_version));
+    }
+    if let Some(creator) = query.created_by {
+        entries_query = entries_query.filter(created_by.eq(creator));
+    }
+    if let Some(after) = query.created_after {
+        entries_query = entries_query.filter(created_at.ge(after));
+    }
+    if let Some(before) = query.created_before {
+        entries_query = entries_query.filter(created_at.le(before));
+    }
+    if let Some(package_tag) = query.tag {
+        entries_query = entries_query.filter(tag.eq(package_tag));
+    }
+    // Versions are unique within an application, so they alone order the pages
+    if let Some(cursor) = query.cursor {
+        let after_version = decode_cursor(&cursor)?
+            .parse::<i32>()
+            .map_err(|_| error::ErrorBadRequest("Invalid cursor"))?;
+        entries_query = match query.order {
+            SortOrder::Asc => entries_query.filter(version.gt(after_version)),
+            SortOrder::Desc => entries_query.filter(version.lt(after_version)),
+        };
+    }
+    entries_query = match query.order {
+        SortOrder::Asc => entries_query.order_by(version.asc()),
+        SortOrder::Desc => entries_query.order_by(version.desc()),
+    };
+
+    let entries = entries_query
+        .limit(limit + 1)
         .load::<PackageEntryRead>(&mut conn)
         .map_err(error::ErrorInternalServerError)?;
-    
+    let (entries, next_cursor) = paginate(entries, limit, |entry| entry.version.to_string());
+
     let entries = entries
-        .iter()
+        .into_iter()
         .map(|a| {
             let important: Vec<crate::utils::db::models::File> = 
-                serde_json::from_value(a.important.clone()).unwrap_or_default();
+                serde_json::from_value(a.important).unwrap_or_default();
             let lazy: Vec<crate::utils::db::models::File> = 
-                serde_json::from_value(a.lazy.clone()).unwrap_or_default();
+                serde_json::from_value(a.lazy).unwrap_or_default();
             
             Package {
-                index: a.index.to_owned(),
+                index: a.index,
                 important,
                 lazy,
                 version: a.version,
                 id: a.id.to_string(),
+                created_at: a.created_at,
+                created_by: a.created_by,
+                tag: a.tag,
             }
         })
         .collect();
 
-    Ok(Json(PackageList { packages: entries }))
-}
-
-// #[get("/{package_id}")]
-// async fn get_package(
-//     path: Path<String>,
-//     state: web::Data<AppState>,
-//     auth_response: ReqData<AuthResponse>,
-// ) -> Result<Json<Package>, actix_web::Error> {
-//     let package_version = path
-//         .into_inner()
-//         .parse::<i32>()
-//         .map_err(error::ErrorBadRequest)?;
-//     let auth_response = auth_response.into_inner();
-//     let organisation =
-//         validate_user(auth_response.organisation, READ).map_err(error::ErrorUnauthorized)?;
-//     let application =
-//         validate_user(auth_response.application, READ).map_err(error::ErrorUnauthorized)?;
-
-//     let mut conn = state
-//         .db_pool
-//         .get()
-//         .map_err(error::ErrorInternalServerError)?;
-
-//     let package = packages
-//         .filter(
-//             org_id
-//                 .eq(&organisation)
-//                 .and(app_id.eq(&application))
-//                 .and(package_version.eq(package_version)),
-//         )
-//         .first::<PackageEntryRead>(&mut conn)
-//         .map_err(|_| error::ErrorNotFound("Package not found"))?;
-
-//     Ok(Json(Package {
-//         index: package.index,
-//         splits: package.contents,
-//         version: package.version,
-//         id: package.id.to_string(),
-//     }))
-// }
+    Ok(Json(PackageList {
+        packages: entries,
+        next_cursor,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct CollectGarbageQuery {
+    /// Only report what would be removed; defaults to true
+    dry_run: Option<bool>,
+}
+
+#[post("/gc")]
+async fn collect_garbage(
+    query: web::Query<CollectGarbageQuery>,
+    state: web::Data<AppState>,
+    auth_response: ReqData<AuthResponse>,
+) -> Result<Json<CollectionReport>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let settings = find_settings(&organisation, &application, &mut conn)
+        .map_err(error::ErrorInternalServerError)?
+        .filter(|settings| settings.retain_package_versions.is_some())
+        .ok_or_else(|| {
+            error::ErrorBadRequest("Package retention is not configured for this application")
+        })?;
+
+    let report = collect_packages(&state, &mut conn, &settings, query.dry_run.unwrap_or(true))
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(Json(report))
+}
+
+#[derive(Serialize)]
+struct PackageDetail {
+    id: String,
+    version: i32,
+    index: String,
+    index_sha256: Option<String>,
+    index_size: Option<i64>,
+    important: Vec<crate::utils::db::models::File>,
+    lazy: Vec<crate::utils::db::models::File>,
+    resources: Vec<crate::utils::db::models::File>,
+    properties: serde_json::Value,
+    archived_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+    created_by: Option<String>,
+    tag: Option<String>,
+}
+
+impl From<PackageEntryRead> for PackageDetail {
+    fn from(package: PackageEntryRead) -> Self {
+        PackageDetail {
+            id: package.id.to_string(),
+            version: package.version,
+            index: package.index,
+            index_sha256: package.index_sha256,
+            index_size: package.index_size,
+            important: serde_json::from_value(package.important).unwrap_or_default(),
+            lazy: serde_json::from_value(package.lazy).unwrap_or_default(),
+            resources: serde_json::from_value(package.resources).unwrap_or_default(),
+            properties: package.properties,
+            archived_at: package.archived_at,
+            created_at: package.created_at,
+            created_by: package.created_by,
+            tag: package.tag,
+        }
+    }
+}
+
+/// Look up a package of an application by version number or id
+fn find_package(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    package_id: &str,
+) -> Result<PackageEntryRead> {
+    let query = packages
+        .filter(org_id.eq(organisation).and(app_id.eq(application)))
+        .into_boxed();
+    let query = if let Ok(package_version) = package_id.parse::<i32>() {
+        query.filter(version.eq(package_version))
+    } else if let Ok(uuid) = uuid::Uuid::parse_str(package_id) {
+        query.filter(id.eq(uuid))
+    } else {
+        return Err(error::ErrorBadRequest(
+            "Package id must be a version number or a UUID",
+        ));
+    };
+
+    query
+        .first::<PackageEntryRead>(conn)
+        .optional()
+        .map_err(error::ErrorInternalServerError)?
+        .ok_or_else(|| error::ErrorNotFound("Package not found"))
+}
+
+/// Releases of an application that use a package version, as new or previous package
+fn releases_using(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    package_version: i32,
+) -> Result<Vec<ReleaseEntry>> {
+    releases::releases
+        .filter(releases::org_id.eq(organisation))
+        .filter(releases::app_id.eq(application))
+        .filter(
+            releases::package_version
+                .eq(package_version)
+                .or(releases::previous_package_version.eq(package_version)),
+        )
+        .select(ReleaseEntry::as_select())
+        .load(conn)
+        .map_err(error::ErrorInternalServerError)
+}
+
+/// Whether a release still serves or may soon serve its packages
+fn is_active(release: &ReleaseEntry) -> bool {
+    matches!(
+        ReleaseStatus::parse(&release.status),
+        Some(ReleaseStatus::Draft | ReleaseStatus::Ramping | ReleaseStatus::Paused | ReleaseStatus::Live)
+    )
+}
+
+#[get("/{package_id}")]
+async fn get_package(
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+    auth_response: ReqData<AuthResponse>,
+) -> Result<Json<PackageDetail>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, READ).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, READ).map_err(error::ErrorUnauthorized)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let package = find_package(&mut conn, &organisation, &application, &path.into_inner())?;
+    Ok(Json(package.into()))
+}
+
+#[post("/{package_id}/archive")]
+async fn archive_package(
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+    auth_response: ReqData<AuthResponse>,
+) -> Result<Json<PackageDetail>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let package = find_package(&mut conn, &organisation, &application, &path.into_inner())?;
+    if package.archived_at.is_some() {
+        return Ok(Json(package.into()));
+    }
+    // Archived packages lose their files to garbage collection
+    if releases_using(&mut conn, &organisation, &application, package.version)?
+        .iter()
+        .any(is_active)
+    {
+        return Err(error::ErrorConflict(format!(
+            "Package version {} is used by an active release",
+            package.version
+        )));
+    }
+
+    let archived = diesel::update(packages.filter(id.eq(package.id)))
+        .set(archived_at.eq(Some(Utc::now())))
+        .get_result::<PackageEntryRead>(&mut conn)
+        .map_err(error::ErrorInternalServerError)?;
+
+    invalidate_release_cache(&state, &organisation, &application);
+
+    Ok(Json(archived.into()))
+}
+
+#[delete("/{package_id}")]
+async fn delete_package(
+    path: web::Path<String>,
+    state: web::Data<AppState>,
+    auth_response: ReqData<AuthResponse>,
+) -> Result<HttpResponse> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let package = find_package(&mut conn, &organisation, &application, &path.into_inner())?;
+    let package_releases = releases_using(&mut conn, &organisation, &application, package.version)?;
+    if package_releases.iter().any(is_active) {
+        return Err(error::ErrorConflict(format!(
+            "Package version {} is used by a live release",
+            package.version
+        )));
+    }
+    if !package_releases.is_empty() {
+        return Err(error::ErrorConflict(format!(
+            "Package version {} has been released and can only be archived",
+            package.version
+        )));
+    }
+
+    // Its files are left to garbage collection, as other packages may share them
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        diesel::delete(
+            patches::package_patches
+                .filter(patches::org_id.eq(&organisation))
+                .filter(patches::app_id.eq(&application))
+                .filter(
+                    patches::from_version
+                        .eq(package.version)
+                        .or(patches::to_version.eq(package.version)),
+                ),
+        )
+        .execute(conn)?;
+        diesel::delete(
+            configs::configs
+                .filter(configs::org_id.eq(&organisation))
+                .filter(configs::app_id.eq(&application))
+                .filter(configs::version.eq(package.version)),
+        )
+        .execute(conn)?;
+        diesel::insert_into(deleted_versions::deleted_package_versions)
+            .values((
+                deleted_versions::org_id.eq(&organisation),
+                deleted_versions::app_id.eq(&application),
+                deleted_versions::version.eq(package.version),
+                deleted_versions::deleted_at.eq(Utc::now()),
+                deleted_versions::deleted_by.eq(&auth_response.sub),
+            ))
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+        diesel::delete(packages.filter(id.eq(package.id))).execute(conn)
+    })
+    .map_err(error::ErrorInternalServerError)?;
+
+    invalidate_release_cache(&state, &organisation, &application);
+
+    Ok(HttpResponse::NoContent().finish())
+}
 
 #[derive(Debug, MultipartForm)]
 struct PackageJsonV1MultipartRequest {
@@ -567,6 +1143,15 @@ struct PackageJsonV1MultipartRequest {
 pub enum ContextOperator {
     #[serde(rename = "IS")]
     Is,
+    /// Any of the values in an array
+    #[serde(rename = "IN")]
+    In,
+    /// Semantic version at least the value
+    #[serde(rename = "GTE")]
+    Gte,
+    /// Semantic version at most the value
+    #[serde(rename = "LTE")]
+    Lte,
 }
 
 impl Default for ContextOperator {
@@ -578,11 +1163,38 @@ impl Default for ContextOperator {
 #[derive(Debug, Deserialize, Serialize)]
 struct PackageContext {
     key: String,
-    value: String,
+    value: serde_json::Value,
     #[serde(default)]
     operator: ContextOperator
 }
 
+impl PackageContext {
+    fn to_json_logic(&self) -> serde_json::Value {
+        let operator = match self.operator {
+            ContextOperator::Is => "==",
+            ContextOperator::In => "in",
+            ContextOperator::Gte => ">=",
+            ContextOperator::Lte => "<=",
+        };
+        json!({ operator: [{ "var": self.key }, self.value] })
+    }
+}
+
+/// Targeting rule of a package: its contexts and-ed with the optional JsonLogic rule
+fn package_targeting(
+    contexts: &[PackageContext],
+    targeting: Option<&serde_json::Value>,
+) -> Option<serde_json::Value> {
+    let mut rules: Vec<serde_json::Value> =
+        contexts.iter().map(PackageContext::to_json_logic).collect();
+    rules.extend(targeting.cloned());
+    match rules.len() {
+        0 => None,
+        1 => rules.pop(),
+        _ => Some(json!({ "and": rules })),
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 struct PackageV1 {
     name: String,
@@ -599,7 +1211,40 @@ struct PackageJsonV1Request {
     package: PackageV1,
     resources: Vec<crate::utils::db::models::File>,
     #[serde(default)]
-    contexts: Vec<PackageContext>
+    contexts: Vec<PackageContext>,
+    /// JsonLogic targeting rule, and-ed with `contexts`
+    #[serde(default)]
+    targeting: Option<serde_json::Value>,
+}
+
+/// Check client supplied integrity metadata, normalising hashes to lowercase hex
+fn validate_package_files(req: &mut PackageJsonV1Request) -> Result<()> {
+    let files = req
+        .package
+        .important
+        .iter_mut()
+        .chain(req.package.lazy.iter_mut())
+        .chain(req.resources.iter_mut());
+
+    for file in files {
+        if let Some(sha256) = &mut file.sha256 {
+            if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(error::ErrorBadRequest(format!(
+                    "Invalid sha256 for {}: expected 64 hex characters",
+                    file.file_path
+                )));
+            }
+            *sha256 = sha256.to_ascii_lowercase();
+        }
+        if file.size.is_some_and(|size| size < 0) {
+            return Err(error::ErrorBadRequest(format!(
+                "Invalid size for {}: must not be negative",
+                file.file_path
+            )));
+        }
+    }
+
+    Ok(())
 }
 
 #[post("/create_package_json_v1")]
@@ -614,92 +1259,30 @@ async fn create_package_json_v1(
     let application =
         validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
 
+    let mut req = req.into_inner();
+    validate_package_files(&mut req)?;
+
     let mut conn = state
         .db_pool
         .get()
         .map_err(error::ErrorInternalServerError)?;
 
-    let latest_version = packages
-        .filter(org_id.eq(&organisation).and(app_id.eq(&application)))
-        .select(max(version))
-        .first::<Option<i32>>(&mut conn)
-        .map_err(error::ErrorInternalServerError)?;
-
-    let ver = latest_version.unwrap_or(0) + 1;
-
-    // Use superposition_org_id from environment
-    let superposition_org_id_from_env = state.env.superposition_org_id.clone();
-    println!("Using Superposition Org ID from environment for create_package_json_v1: {}", superposition_org_id_from_env);
-
-    // Get workspace name for this application
-    let workspace_name = get_workspace_name_for_application(&application, &organisation, &mut conn).await?;
-    println!("Using workspace name for create_package_json_v1: {}", workspace_name);
-
-    // Create control variant with package configuration
-    let mut control_overrides = std::collections::HashMap::new();
-    control_overrides.insert("package.version".to_string(), json!(ver));
-    control_overrides.insert("package.name".to_string(), json!(req.package.name));
-
-    // Create experimental variant
-    let experimental_overrides = control_overrides.clone();
-
-    // Create variants
-    let control_variant = models::Variant {
-        id: "control".to_string(),
-        variant_type: models::VariantType::Control,
-        context_id: None,
-        override_id: None,
-        overrides: Some(serde_json::Value::Object(serde_json::Map::from_iter(
-            control_overrides,
-        ))),
-    };
-
-    let experimental_variant = models::Variant {
-        id: "experimental".to_string(),
-        variant_type: models::VariantType::Experimental,
-        context_id: None,
-        override_id: None,
-        overrides: Some(serde_json::Value::Object(serde_json::Map::from_iter(
-            experimental_overrides,
-        ))),
-    };
-
-    // Create experiment in Superposition
-    let experiment_content = models::CreateExperimentRequestContent::new(
-        format!("{}_v{}", application, ver),
-        std::collections::HashMap::new(),
-        vec![control_variant, experimental_variant],
-        format!("Package creation for {} v{}", application, ver),
-        "Creating new package version".to_string(),
-    );
-
-    create_experiment(
-        &state.superposition_configuration,
-        &superposition_org_id_from_env, // Use ID from env
-        &workspace_name,  // Use workspace name instead of application
-        experiment_content,
+    let reservation =
+        VersionReservation::new(&state, &mut conn, &organisation, &application, json!({}))?;
+
+    store_package_v1(
+        &state,
+        &mut conn,
+        NewPackage {
+            organisation,
+            application,
+            version: reservation.version,
+            user_id: auth_response.sub,
+        },
+        req,
+        None,
     )
     .await
-    .map_err(|e| error::ErrorInternalServerError(format!("Failed to create experiment: {}", e)))?;
-
-    // Store package data with the new important and lazy structure
-    diesel::insert_into(packages)
-        .values(PackageEntry {
-            version: ver,
-            app_id: application.clone(),
-            org_id: organisation.clone(),
-            index: req.package.index.clone(),
-            version_splits: true,
-            use_urls: true,
-            important: serde_json::to_value(&req.package.important).map_err(error::ErrorInternalServerError)?,
-            lazy: serde_json::to_value(&req.package.lazy).map_err(error::ErrorInternalServerError)?,
-            properties: serde_json::to_value(&req.package.properties).unwrap_or(serde_json::Value::Object(serde_json::Map::new())),
-            resources: serde_json::to_value(&req.resources).map_err(error::ErrorInternalServerError)?,
-        })
-        .execute(&mut conn)
-        .map_err(error::ErrorInternalServerError)?;
-
-    Ok(Json(Response { version: ver }))
 }
 
 #[post("/create_json_v1_multipart")]
@@ -717,100 +1300,144 @@ async fn create_json_v1_multipart(
     // Parse the JSON request
     let mut req: PackageJsonV1Request = serde_json::from_str(&form.json.into_inner())
         .map_err(|e| error::ErrorBadRequest(format!("Invalid JSON: {}", e)))?;
+    validate_package_files(&mut req)?;
+
+    // Extract package properties (dynamically)
+    let manifest = req
+        .package
+        .properties
+        .get("manifest")
+        .ok_or_else(|| error::ErrorBadRequest("Missing manifest in package properties"))?;
+
+    let manifest_hash = req
+        .package
+        .properties
+        .get("manifest_hash")
+        .ok_or_else(|| error::ErrorBadRequest("Missing manifest_hash in package properties"))?;
+
+    // Hash the index before reserving, so the reservation keeps its object from package GC
+    let index = match form.index {
+        Some(index_file) => {
+            let index_name = index_file.file_name.clone().unwrap_or_default();
+            if index_name.is_empty() {
+                return Err(error::ErrorBadRequest("Index file name cannot be empty"));
+            }
+            Some(DigestedFile::new(index_name, index_file).await?)
+        }
+        None => None,
+    };
+    let reservation_manifest = match &index {
+        Some(index) => json!({ "index": index.declaration() }),
+        None => json!({}),
+    };
 
     let mut conn = state
         .db_pool
         .get()
         .map_err(error::ErrorInternalServerError)?;
 
-    let latest_version = packages
-        .filter(org_id.eq(&organisation).and(app_id.eq(&application)))
-        .select(max(version))
-        .first::<Option<i32>>(&mut conn)
-        .map_err(error::ErrorInternalServerError)?;
-
-    let ver = latest_version.unwrap_or(0) + 1;
-
-    // Handle file upload if provided and not empty
-    if let Some(index_file) = form.index {
-        let index_name = index_file.file_name.clone().unwrap_or_default();
-        if index_name.is_empty() {
-            return Err(error::ErrorBadRequest("Index file name cannot be empty"));
-        }
-
+    let reservation = VersionReservation::new(
+        &state,
+        &mut conn,
+        &organisation,
+        &application,
+        reservation_manifest,
+    )?;
+    let ver = reservation.version;
+
+    // Handle file upload if provided
+    let mut index_integrity = None;
+    if let Some(DigestedFile {
+        file_path: index_name,
+        sha256: index_sha256,
+        size: index_size,
+        file: index_file,
+    }) = index
+    {
         let s3_client = &state.s3_client;
 
-        let s3_path = format!(
-            "assets/{}/{}/{}/{}",
-            organisation, application, ver, index_name
-        );
-
-        match push_file(
+        match push_file_by_content(
             s3_client,
             state.env.bucket_name.clone(),
             index_file,
-            s3_path.clone(),
+            &organisation,
+            &application,
+            &index_sha256,
         )
         .await
         {
-            Ok(_) => {
-                req.package.index = format!(
-                        "{}/{}/{}",
-                        state.env.public_url, state.env.bucket_name, s3_path
-                    );        
+            Ok(s3_path) => {
+                req.package.index = object_url(&state.env, &s3_path);
             }
             Err(e) => {
-                println!("S3 upload error details:");
-                println!("Bucket: {}", state.env.bucket_name);
-                println!("Path: {}", s3_path);
-                println!("Error: {:?}", e);
+                error!(
+                    "Failed to upload index {} ({}) to bucket {}: {:?}",
+                    index_name, index_sha256, state.env.bucket_name, e
+                );
                 return Err(error::ErrorInternalServerError(
                     "Failed to upload index file",
                 ));
             }
         }
+        index_integrity = Some((index_sha256, index_size));
     }
 
+    store_package_v1(
+        &state,
+        &mut conn,
+        NewPackage {
+            organisation,
+            application,
+            version: ver,
+            user_id: auth_response.sub,
+        },
+        req,
+        index_integrity,
+    )
+    .await
+}
+
+/// Version of a new package and who creates it
+struct NewPackage {
+    organisation: String,
+    application: String,
+    version: i32,
+    user_id: String,
+}
+
+/// Create the Superposition experiment of a new V1 package and store the package
+async fn store_package_v1(
+    state: &web::Data<AppState>,
+    conn: &mut PgConnection,
+    package: NewPackage,
+    req: PackageJsonV1Request,
+    index_integrity: Option<(String, i64)>,
+) -> Result<Json<Response>, actix_web::Error> {
+    let NewPackage {
+        organisation,
+        application,
+        version: ver,
+        user_id,
+    } = package;
+
     // Use superposition_org_id from environment
     let superposition_org_id_from_env = state.env.superposition_org_id.clone();
-    println!("Using Superposition Org ID from environment for create_json_v1_multipart: {}", superposition_org_id_from_env);
+    debug!("Using Superposition Org ID from environment for package creation: {}", superposition_org_id_from_env);
 
     // Get workspace name for this application
-    let workspace_name = get_workspace_name_for_application(&application, &organisation, &mut conn).await?;
-    println!("Using workspace name for create_json_v1_multipart: {}", workspace_name);
-
-    // Extract package properties (dynamically)
-    let manifest = req
-        .package
-        .properties
-        .get("manifest")
-        .ok_or_else(|| error::ErrorBadRequest("Missing manifest in package properties"))?;
+    let workspace_name = get_workspace_name_for_application(&application, &organisation, conn).await?;
+    debug!("Using workspace name for package creation: {}", workspace_name);
 
-    let manifest_hash = req
-        .package
-        .properties
-        .get("manifest_hash")
-        .ok_or_else(|| error::ErrorBadRequest("Missing manifest_hash in package properties"))?;
-
-    // Create context string from context operators
-    let context_string = if !req.contexts.is_empty() {
-        req.contexts.iter().map(|ctx| {
-            let operator_str = match ctx.operator {
-                ContextOperator::Is => "IS",
-            };
-            format!("{}:{}:{}", ctx.key, operator_str, ctx.value)
-        }).collect::<Vec<_>>().join(",")
-    } else {
-        String::new()
+    // Compile the package's targeting to the experiment's context
+    let context = match package_targeting(&req.contexts, req.targeting.as_ref()) {
+        Some(rule) => compile_targeting(state, &workspace_name, &rule).await?,
+        None => std::collections::HashMap::new(),
     };
 
     // Create control variant with package configuration
     let mut control_overrides = std::collections::HashMap::new();
     control_overrides.insert("package.version".to_string(), json!(ver));
     control_overrides.insert("package.name".to_string(), json!(req.package.name));
-    if !context_string.is_empty() {
-        control_overrides.insert("context".to_string(), json!(context_string));
-    }
 
     // Create experimental variant
     let experimental_overrides = control_overrides.clone();
@@ -839,7 +1466,7 @@ async fn create_json_v1_multipart(
     // Create experiment in Superposition
     let experiment_content = models::CreateExperimentRequestContent::new(
         format!("{}_v{}", application, ver),
-        std::collections::HashMap::new(),
+        context,
         vec![control_variant, experimental_variant],
         format!("Package creation for {} v{}", application, ver),
         "Creating new package version".to_string(),
@@ -867,9 +1494,17 @@ async fn create_json_v1_multipart(
             lazy: serde_json::to_value(&req.package.lazy).map_err(error::ErrorInternalServerError)?,
             properties: serde_json::to_value(&req.package.properties).unwrap_or(serde_json::Value::Object(serde_json::Map::new())),
             resources: serde_json::to_value(&req.resources).map_err(error::ErrorInternalServerError)?,
+            index_sha256: index_integrity.as_ref().map(|(sha256, _)| sha256.clone()),
+            index_size: index_integrity.map(|(_, size)| size),
+            created_at: Utc::now(),
+            created_by: user_id,
+            tag: Some(req.package.version.clone()),
         })
-        .execute(&mut conn)
-        .map_err(error::ErrorInternalServerError)?;
+        .execute(conn)
+        .map_err(package_insert_error)?;
+
+    invalidate_release_cache(state, &organisation, &application);
+    spawn_patch_generation(state.clone(), organisation, application, ver);
 
     Ok(Json(Response { version: ver }))
 }
@@ -0,0 +1,393 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Direct-to-S3 package uploads. Creating an upload reserves a package version and returns
+// a presigned PUT URL per file that is not stored yet; finalizing it checks the uploaded
+// objects against the declared hashes and sizes before the package is stored.
+
+use actix_web::{
+    error, post,
+    web::{self, Json, Path, ReqData},
+    Result,
+};
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use log::error;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{
+    reserve_package_version, store_package_v1, validate_file_path, NewPackage, PackageContext,
+    PackageJsonV1Request, PackageUploadInfo, PackageV1, Response,
+};
+use crate::{
+    middleware::auth::{validate_user, AuthResponse, WRITE},
+    types::AppState,
+    utils::{
+        db::{models::PendingPackage, schema::hyperotaserver::pending_packages::dsl as pending},
+        s3::{content_key, object_integrity, object_url, presign_put, PresignedUpload},
+    },
+};
+
+/// How long presigned URLs, and the upload they belong to, stay valid
+const UPLOAD_EXPIRY_SECS: i64 = 3600;
+
+/// Largest object S3 accepts in a single PUT
+const MAX_UPLOAD_FILE_SIZE: i64 = 5 * 1024 * 1024 * 1024;
+
+#[derive(Debug, Deserialize, Serialize)]
+struct UploadFile {
+    #[serde(rename = "filePath")]
+    file_path: String,
+    /// Lowercase hex SHA-256 of the file contents
+    sha256: String,
+    size: i64,
+}
+
+/// Package metadata and the files that will be uploaded, stored until finalized
+#[derive(Debug, Deserialize, Serialize)]
+struct UploadManifest {
+    package: PackageUploadInfo,
+    #[serde(default)]
+    contexts: Vec<PackageContext>,
+    #[serde(default)]
+    targeting: Option<serde_json::Value>,
+    index: UploadFile,
+    #[serde(default)]
+    important: Vec<UploadFile>,
+    #[serde(default)]
+    lazy: Vec<UploadFile>,
+    #[serde(default)]
+    resources: Vec<UploadFile>,
+}
+
+impl UploadManifest {
+    fn files(&self) -> impl Iterator<Item = &UploadFile> {
+        std::iter::once(&self.index)
+            .chain(&self.important)
+            .chain(&self.lazy)
+            .chain(&self.resources)
+    }
+
+    fn files_mut(&mut self) -> impl Iterator<Item = &mut UploadFile> {
+        std::iter::once(&mut self.index)
+            .chain(&mut self.important)
+            .chain(&mut self.lazy)
+            .chain(&mut self.resources)
+    }
+}
+
+#[derive(Serialize)]
+struct UploadTarget {
+    #[serde(rename = "filePath")]
+    file_path: String,
+    #[serde(flatten)]
+    upload: PresignedUpload,
+}
+
+#[derive(Serialize)]
+struct CreateUploadResponse {
+    upload_id: String,
+    version: i32,
+    expires_at: DateTime<Utc>,
+    files: Vec<UploadTarget>,
+    /// Files already stored by earlier packages, which need no upload
+    reused: Vec<String>,
+}
+
+fn validate_manifest(manifest: &mut UploadManifest) -> Result<()> {
+    for key in ["manifest", "manifest_hash"] {
+        if manifest.package.properties.get(key).is_none() {
+            return Err(error::ErrorBadRequest(format!(
+                "Missing {} in package properties",
+                key
+            )));
+        }
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    for file in manifest.files_mut() {
+        validate_file_path(&file.file_path)?;
+        if !seen.insert(file.file_path.clone()) {
+            return Err(error::ErrorBadRequest(format!(
+                "File {} is listed more than once",
+                file.file_path
+            )));
+        }
+        if file.sha256.len() != 64 || !file.sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(error::ErrorBadRequest(format!(
+                "Invalid sha256 for {}: expected 64 hex characters",
+                file.file_path
+            )));
+        }
+        file.sha256 = file.sha256.to_ascii_lowercase();
+        if !(0..=MAX_UPLOAD_FILE_SIZE).contains(&file.size) {
+            return Err(error::ErrorBadRequest(format!(
+                "Invalid size for {}: must be between 0 and {} bytes",
+                file.file_path, MAX_UPLOAD_FILE_SIZE
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[post("/uploads")]
+async fn create_upload(
+    req: Json<UploadManifest>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<CreateUploadResponse>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+
+    let mut manifest = req.into_inner();
+    validate_manifest(&mut manifest)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let pending_package = reserve_package_version(
+        &mut conn,
+        &organisation,
+        &application,
+        serde_json::to_value(&manifest).map_err(error::ErrorInternalServerError)?,
+        Duration::seconds(UPLOAD_EXPIRY_SECS),
+    )?;
+    let ver = pending_package.version;
+
+    let mut files = Vec::new();
+    let mut reused = Vec::new();
+    for file in manifest.files() {
+        let key = content_key(&organisation, &application, &file.sha256);
+        let stored = object_integrity(&state.s3_client, state.env.bucket_name.clone(), key.clone())
+            .await?;
+        if stored.is_some_and(|(_, size)| size == file.size) {
+            reused.push(file.file_path.clone());
+            continue;
+        }
+
+        let upload = presign_put(
+            &state.s3_client,
+            state.env.bucket_name.clone(),
+            key,
+            &file.sha256,
+            file.size,
+            std::time::Duration::from_secs(UPLOAD_EXPIRY_SECS as u64),
+        )
+        .await?;
+        files.push(UploadTarget {
+            file_path: file.file_path.clone(),
+            upload,
+        });
+    }
+
+    Ok(Json(CreateUploadResponse {
+        upload_id: pending_package.id.to_string(),
+        version: ver,
+        expires_at: pending_package.expires_at,
+        files,
+        reused,
+    }))
+}
+
+#[post("/uploads/{upload_id}/finalize")]
+async fn finalize_upload(
+    path: Path<String>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<Response>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+    let upload_id = Uuid::parse_str(&path.into_inner())
+        .map_err(|_| error::ErrorBadRequest("Invalid upload id"))?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let pending_package = pending::pending_packages
+        .filter(pending::id.eq(upload_id))
+        .filter(pending::org_id.eq(&organisation))
+        .filter(pending::app_id.eq(&application))
+        .select(PendingPackage::as_select())
+        .first(&mut conn)
+        .optional()
+        .map_err(error::ErrorInternalServerError)?
+        .ok_or_else(|| error::ErrorNotFound("Upload not found"))?;
+    if pending_package.expires_at < Utc::now() {
+        return Err(error::ErrorGone("Upload has expired, start a new one"));
+    }
+    let manifest: UploadManifest = serde_json::from_value(pending_package.manifest.clone())
+        .map_err(error::ErrorInternalServerError)?;
+    let ver = pending_package.version;
+
+    let mut urls = std::collections::HashMap::new();
+    for file in manifest.files() {
+        let key = content_key(&organisation, &application, &file.sha256);
+        match object_integrity(&state.s3_client, state.env.bucket_name.clone(), key.clone())
+            .await?
+        {
+            None => {
+                return Err(error::ErrorBadRequest(format!(
+                    "File {} has not been uploaded",
+                    file.file_path
+                )))
+            }
+            // Objects uploaded through the server carry no S3 checksum; their key is the hash
+            Some((sha256, size))
+                if sha256.as_ref().is_some_and(|sha256| *sha256 != file.sha256) || size != file.size =>
+            {
+                return Err(error::ErrorBadRequest(format!(
+                    "Uploaded file {} does not match its declared sha256 and size",
+                    file.file_path
+                )))
+            }
+            Some(_) => {}
+        }
+        urls.insert(
+            file.file_path.clone(),
+            object_url(&state.env, &key),
+        );
+    }
+
+    let to_files = |files: &[UploadFile]| -> Vec<crate::utils::db::models::File> {
+        files
+            .iter()
+            .map(|file| crate::utils::db::models::File {
+                url: urls[&file.file_path].clone(),
+                file_path: file.file_path.clone(),
+                sha256: Some(file.sha256.clone()),
+                size: Some(file.size),
+            })
+            .collect()
+    };
+    let req = PackageJsonV1Request {
+        package: PackageV1 {
+            name: manifest.package.name,
+            version: manifest.package.version,
+            properties: manifest.package.properties,
+            index: urls[&manifest.index.file_path].clone(),
+            important: to_files(&manifest.important),
+            lazy: to_files(&manifest.lazy),
+        },
+        resources: to_files(&manifest.resources),
+        contexts: manifest.contexts,
+        targeting: manifest.targeting,
+    };
+    let index_integrity = Some((manifest.index.sha256, manifest.index.size));
+
+    // Claim the upload so concurrent finalize calls cannot store the version twice
+    let claimed = diesel::delete(pending::pending_packages.filter(pending::id.eq(upload_id)))
+        .execute(&mut conn)
+        .map_err(error::ErrorInternalServerError)?;
+    if claimed == 0 {
+        return Err(error::ErrorConflict("Upload is already being finalized"));
+    }
+
+    let stored = store_package_v1(
+        &state,
+        &mut conn,
+        NewPackage {
+            organisation,
+            application,
+            version: ver,
+            user_id: auth_response.sub,
+        },
+        req,
+        index_integrity,
+    )
+    .await;
+    if stored.is_err() {
+        // Keep the reservation so finalizing can be retried
+        if let Err(e) = diesel::insert_into(pending::pending_packages)
+            .values(&pending_package)
+            .execute(&mut conn)
+        {
+            error!("Failed to restore pending upload {}: {:?}", upload_id, e);
+        }
+    }
+    stored
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    const SHA256: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    fn manifest(changes: impl FnOnce(&mut Value)) -> UploadManifest {
+        let mut manifest = json!({
+            "package": {
+                "name": "app",
+                "version": "1.0.0",
+                "manifest": {},
+                "manifest_hash": {}
+            },
+            "index": { "filePath": "index.js", "sha256": SHA256, "size": 10 },
+            "important": [{ "filePath": "assets/logo.png", "sha256": SHA256, "size": 0 }]
+        });
+        changes(&mut manifest);
+        serde_json::from_value(manifest).unwrap()
+    }
+
+    #[test]
+    fn valid_manifests_get_lowercase_hashes() {
+        let mut valid = manifest(|m| {
+            m["lazy"] = json!([
+                { "filePath": "lazy.js", "sha256": SHA256.to_ascii_uppercase(), "size": 5 }
+            ]);
+        });
+
+        validate_manifest(&mut valid).unwrap();
+        assert!(valid.files().all(|file| file.sha256 == SHA256));
+    }
+
+    #[test]
+    fn package_properties_need_the_manifest_and_its_hashes() {
+        for key in ["manifest", "manifest_hash"] {
+            let mut invalid = manifest(|m| {
+                m["package"].as_object_mut().unwrap().remove(key);
+            });
+            assert!(validate_manifest(&mut invalid).is_err(), "{} is not required", key);
+        }
+    }
+
+    #[test]
+    fn invalid_files_are_rejected() {
+        let invalid_files = [
+            json!({ "filePath": "index.js", "sha256": SHA256, "size": 1 }),
+            json!({ "filePath": "../escape.js", "sha256": SHA256, "size": 1 }),
+            json!({ "filePath": "/absolute.js", "sha256": SHA256, "size": 1 }),
+            json!({ "filePath": "short.js", "sha256": "abc", "size": 1 }),
+            json!({ "filePath": "hex.js", "sha256": "z".repeat(64), "size": 1 }),
+            json!({ "filePath": "negative.js", "sha256": SHA256, "size": -1 }),
+            json!({ "filePath": "huge.js", "sha256": SHA256, "size": MAX_UPLOAD_FILE_SIZE + 1 }),
+        ];
+        for file in invalid_files {
+            let mut invalid = manifest(|m| m["resources"] = json!([file.clone()]));
+            assert!(validate_manifest(&mut invalid).is_err(), "{} was accepted", file);
+        }
+    }
+}
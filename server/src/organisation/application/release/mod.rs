@@ -11,42 +11,95 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-
 use actix_web::{
     error, get, post,
-    web::{self, Json, ReqData},
+    web::{self, Json, Path, ReqData},
     Result, Scope,
 };
 use chrono::{DateTime, Utc};
 use diesel::prelude::*;
+use log::{debug, warn};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use superposition_rust_sdk::{
-    apis::default_api::{create_experiment, ramp_experiment}, // Added ramp_experiment
-    models,
-};
 
 use crate::{
     middleware::auth::{validate_user, AuthResponse, READ, WRITE},
+    release::{
+        preview::{preview_release, HypotheticalRelease, PreviewDevice, ReleasePreview},
+        targeting::compile_targeting,
+    },
     types::AppState,
     utils::{
         db::{
-            models::{PackageEntryRead, ReleaseEntry},
+            models::{PackageEntryRead, ReleaseEntry, ReleaseScheduleStep, ReleaseStatus},
             schema::hyperotaserver::releases::dsl::*,
         },
+        pagination::{decode_cursor, page_size, paginate, SortOrder},
+        release_health::find_health,
+        release_lifecycle::{
+            abort_release, conclude_release, create_release_experiment, pause_release,
+            ramp_release, rollback_release, start_release,
+        },
+        release_scheduler::{
+            advance_release, list_schedule, save_schedule, validate_schedule, ScheduleStepRequest,
+        },
+        tester_groups::find_tester_group,
         workspace::get_workspace_name_for_application,
     },
 };
 
 pub fn add_routes() -> Scope {
-    Scope::new("").service(create).service(list_releases)
+    Scope::new("")
+        .service(create)
+        .service(list_releases)
+        .service(ramp)
+        .service(pause)
+        .service(conclude)
+        .service(abort)
+        .service(get_schedule)
+        .service(get_health)
+        .service(rollback)
+        .service(preview)
+}
+
+#[derive(Debug, Deserialize)]
+struct PreviewRequest {
+    /// Dimension values of the device, as it would send them
+    #[serde(default)]
+    context: serde_json::Map<String, serde_json::Value>,
+    /// Device id used for rollout bucketing, unless `context` has one
+    device_id: Option<String>,
+    /// User signed in on the device, matched against tester groups, unless `context` has one
+    user_id: Option<String>,
+    /// Package the device runs, deciding whether it keeps a paused rollout
+    package_version: Option<i32>,
+    /// Release that does not exist yet, previewed as if it were live
+    release: Option<HypotheticalReleaseRequest>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HypotheticalReleaseRequest {
+    package_version: i32,
+    /// Targeting rule in JsonLogic, as in `CreateRequest`
+    context: Option<serde_json::Value>,
+    rollout_percentage: Option<i32>,
 }
 
 #[derive(Debug, Deserialize)]
 struct CreateRequest {
     version_id: Option<String>,
     metadata: Option<serde_json::Value>,
-    context: Option<serde_json::Value>, // Changed to accept JsonLogic format directly
+    /// Targeting rule in JsonLogic, compiled to the experiment's context
+    context: Option<serde_json::Value>,
+    rollout_percentage: Option<i32>,
+    schedule: Option<Vec<ScheduleStepRequest>>,
+    /// Name of a tester group to pin the package to instead of releasing it publicly
+    tester_group: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RampRequest {
+    rollout_percentage: i32,
 }
 
 #[derive(Serialize)]
@@ -55,11 +108,52 @@ struct CreateResponse {
     created_at: DateTime<Utc>,
     package_version: i32,
     config_version: String,
+    config_revision: Option<i32>,
+    rollout_percentage: i32,
+    previous_package_version: Option<i32>,
+    status: String,
 }
 
 #[derive(Serialize)]
 struct ReleaseHistoryResponse {
     releases: Vec<ReleaseHistoryEntry>,
+    /// Pass as `cursor` to fetch the next page; absent on the last page
+    next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseHistoryQuery {
+    limit: Option<i64>,
+    cursor: Option<String>,
+    #[serde(default)]
+    order: SortOrder,
+    /// Inclusive range of released package versions
+    from_version: Option<i32>,
+    to_version: Option<i32>,
+    created_by: Option<String>,
+    /// Inclusive range of creation times
+    created_after: Option<DateTime<Utc>>,
+    created_before: Option<DateTime<Utc>>,
+    /// Tag of the released package
+    tag: Option<String>,
+    status: Option<ReleaseStatus>,
+}
+
+/// Releases are ordered by creation time, then id for releases created at the same time
+fn release_cursor(release: &ReleaseEntry) -> String {
+    format!("{}:{}", release.created_at.timestamp_micros(), release.id)
+}
+
+fn parse_release_cursor(cursor: &str) -> Result<(DateTime<Utc>, Uuid)> {
+    let key = decode_cursor(cursor)?;
+    key.split_once(':')
+        .and_then(|(micros, release_id)| {
+            Some((
+                DateTime::from_timestamp_micros(micros.parse().ok()?)?,
+                Uuid::parse_str(release_id).ok()?,
+            ))
+        })
+        .ok_or_else(|| error::ErrorBadRequest("Invalid cursor"))
 }
 
 #[derive(Serialize)]
@@ -67,9 +161,69 @@ struct ReleaseHistoryEntry {
     id: String,
     package_version: i32,
     config_version: String,
+    config_revision: Option<i32>,
     created_at: DateTime<Utc>,
     created_by: String,
     metadata: serde_json::Value,
+    rollout_percentage: i32,
+    previous_package_version: Option<i32>,
+    rolled_back_from: Option<String>,
+    tester_group_id: Option<String>,
+    status: String,
+    ramped_at: Option<DateTime<Utc>>,
+    paused_at: Option<DateTime<Utc>>,
+    live_at: Option<DateTime<Utc>>,
+    concluded_at: Option<DateTime<Utc>>,
+    aborted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+struct ReleaseHealthResponse {
+    release: ReleaseHistoryEntry,
+    boots: i64,
+    boot_failures: i64,
+    updates: i64,
+    package_timeouts: i64,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+struct ReleaseScheduleResponse {
+    release: ReleaseHistoryEntry,
+    steps: Vec<ReleaseScheduleStep>,
+}
+
+impl From<ReleaseEntry> for ReleaseHistoryEntry {
+    fn from(entry: ReleaseEntry) -> Self {
+        ReleaseHistoryEntry {
+            id: entry.id.to_string(),
+            package_version: entry.package_version,
+            config_version: entry.config_version,
+            config_revision: entry.config_revision,
+            created_at: entry.created_at,
+            created_by: entry.created_by,
+            metadata: entry.metadata,
+            rollout_percentage: entry.rollout_percentage,
+            previous_package_version: entry.previous_package_version,
+            rolled_back_from: entry.rolled_back_from.map(|release| release.to_string()),
+            tester_group_id: entry.tester_group_id.map(|group| group.to_string()),
+            status: entry.status,
+            ramped_at: entry.ramped_at,
+            paused_at: entry.paused_at,
+            live_at: entry.live_at,
+            concluded_at: entry.concluded_at,
+            aborted_at: entry.aborted_at,
+        }
+    }
+}
+
+fn validate_rollout_percentage(percentage: i32) -> Result<i32> {
+    if !(0..=100).contains(&percentage) {
+        return Err(error::ErrorBadRequest(
+            "rollout_percentage must be between 0 and 100",
+        ));
+    }
+    Ok(percentage)
 }
 
 #[post("/create")]
@@ -84,6 +238,23 @@ async fn create(
     let application =
         validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
 
+    let rollout = validate_rollout_percentage(req.rollout_percentage.unwrap_or(100))?;
+    if let Some(schedule) = &req.schedule {
+        if req.rollout_percentage.is_some() {
+            return Err(error::ErrorBadRequest(
+                "Use either rollout_percentage or schedule, not both",
+            ));
+        }
+        validate_schedule(schedule)?;
+    }
+    if req.tester_group.is_some()
+        && (req.rollout_percentage.is_some() || req.schedule.is_some() || req.context.is_some())
+    {
+        return Err(error::ErrorBadRequest(
+            "Tester releases go to their whole group and take no rollout_percentage, schedule or context",
+        ));
+    }
+
     let mut conn = state
         .db_pool
         .get()
@@ -103,6 +274,7 @@ async fn create(
                             .eq(&application),
                     ),
             )
+            .filter(crate::utils::db::schema::hyperotaserver::packages::dsl::archived_at.is_null())
             .select(diesel::dsl::max(
                 crate::utils::db::schema::hyperotaserver::packages::dsl::version,
             ))
@@ -111,8 +283,8 @@ async fn create(
             .ok_or_else(|| error::ErrorNotFound("No packages found for this application"))?
     };
 
-    // Verify package exists
-    crate::utils::db::schema::hyperotaserver::packages::dsl::packages
+    // Verify package exists and its files have not been garbage collected
+    let package = crate::utils::db::schema::hyperotaserver::packages::dsl::packages
         .filter(
             crate::utils::db::schema::hyperotaserver::packages::dsl::org_id
                 .eq(&organisation)
@@ -127,6 +299,12 @@ async fn create(
         )
         .first::<PackageEntryRead>(&mut conn)
         .map_err(|_| error::ErrorNotFound(format!("Package version {} not found", pkg_version)))?;
+    if package.archived_at.is_some() {
+        return Err(error::ErrorConflict(format!(
+            "Package version {} is archived",
+            pkg_version
+        )));
+    }
 
     let config = crate::utils::db::schema::hyperotaserver::configs::dsl::configs
         .filter(
@@ -139,6 +317,8 @@ async fn create(
                     crate::utils::db::schema::hyperotaserver::configs::dsl::version.eq(pkg_version),
                 ),
         )
+        // The newest config bound to the package is released; later ones need a new release
+        .order_by(crate::utils::db::schema::hyperotaserver::configs::dsl::revision.desc())
         .select(crate::utils::db::models::ConfigEntry::as_select())
         .first(&mut conn)
         .map_err(|_| {
@@ -152,124 +332,96 @@ async fn create(
     let now = Utc::now();
     let user_id = auth_response.sub.clone();
 
-    // Use superposition_org_id from environment
-    let superposition_org_id_from_env = state.env.superposition_org_id.clone();
-    println!("Using Superposition Org ID from environment for create release: {}", superposition_org_id_from_env);
+    // Tester releases are served from the database to the group's members and are live at
+    // once; no Superposition experiment is created, so production traffic is untouched
+    if let Some(group_name) = &req.tester_group {
+        let group = find_tester_group(&mut conn, &organisation, &application, group_name)
+            .map_err(error::ErrorInternalServerError)?
+            .ok_or_else(|| error::ErrorNotFound(format!("Tester group {} not found", group_name)))?;
+
+        let new_release = ReleaseEntry {
+            id: release_id,
+            org_id: organisation.clone(),
+            app_id: application.clone(),
+            package_version: pkg_version,
+            config_version: config.config_version.clone(),
+            created_at: now,
+            created_by: user_id,
+            metadata: req
+                .metadata
+                .clone()
+                .unwrap_or_else(|| serde_json::json!({})),
+            rollout_percentage: 100,
+            previous_package_version: None,
+            experiment_id: None,
+            context: serde_json::json!({}),
+            rolled_back_from: None,
+            status: ReleaseStatus::Live.as_str().to_string(),
+            ramped_at: Some(now),
+            paused_at: None,
+            live_at: Some(now),
+            concluded_at: None,
+            aborted_at: None,
+            tester_group_id: Some(group.id),
+            config_revision: Some(config.revision),
+        };
+        diesel::insert_into(releases)
+            .values(&new_release)
+            .execute(&mut conn)
+            .map_err(error::ErrorInternalServerError)?;
+
+        return Ok(Json(CreateResponse {
+            id: release_id.to_string(),
+            created_at: now,
+            package_version: pkg_version,
+            config_version: config.config_version,
+            config_revision: Some(config.revision),
+            rollout_percentage: new_release.rollout_percentage,
+            previous_package_version: None,
+            status: new_release.status,
+        }));
+    }
 
     // Get workspace name for this application
     let workspace_name = get_workspace_name_for_application(&application, &organisation, &mut conn).await
         .map_err(|e| error::ErrorInternalServerError(format!("Failed to get workspace name: {}", e)))?;
-    println!("Using workspace name for create release: {}", workspace_name);
-
-    // Create context and variants for the experiment
-    let mut context_map: std::collections::HashMap<String, serde_json::Value> = std::collections::HashMap::new();
-    if let Some(context) = &req.context {
-        context_map = serde_json::from_value(context.clone()).map_err(error::ErrorInternalServerError)?;
-    }
+    debug!("Using workspace name for create release: {}", workspace_name);
 
-    // Create control variant with release configuration
-    let mut control_overrides = std::collections::HashMap::new();
-    control_overrides.insert("package.version".to_string(), serde_json::json!(pkg_version));
-    // control_overrides.insert("package.name".to_string(), serde_json::json!(application.clone()));
-    // control_overrides.insert("release.id".to_string(), serde_json::json!(release_id.to_string()));
-    // control_overrides.insert("release.config_version".to_string(), serde_json::json!(config.config_version.clone()));
-
-    // Create experimental variant with same overrides
-    let experimental_overrides = control_overrides.clone();
-
-    // Create variants
-    let control_variant = models::Variant {
-        id: "control".to_string(),
-        variant_type: models::VariantType::Control,
-        context_id: None,
-        override_id: None,
-        overrides: Some(serde_json::Value::Object(serde_json::Map::from_iter(
-            control_overrides,
-        ))),
+    // Compile the targeting rule to the experiment's context
+    let context_map = match &req.context {
+        Some(rule) => compile_targeting(&state, &workspace_name, rule).await?,
+        None => std::collections::HashMap::new(),
     };
-
-    let experimental_variant = models::Variant {
-        id: "experimental".to_string(),
-        variant_type: models::VariantType::Experimental,
-        context_id: None,
-        override_id: None,
-        overrides: Some(serde_json::Value::Object(serde_json::Map::from_iter(
-            experimental_overrides,
-        ))),
-    };
-
-    // Create experiment in Superposition
-    let experiment_content = models::CreateExperimentRequestContent::new(
-        format!("{}_release_{}", application, release_id),
+    let release_context = serde_json::to_value(&context_map).map_err(error::ErrorInternalServerError)?;
+
+    // Devices outside a partial rollout keep the package of the latest earlier release every
+    // device of the same context was served, or else of the context-free release
+    let previous_release_version =
+        match previous_full_release(&mut conn, &organisation, &application, &release_context)? {
+            Some(version) => Some(version),
+            None => previous_full_release(
+                &mut conn,
+                &organisation,
+                &application,
+                &serde_json::json!({}),
+            )?,
+        };
+
+    let experiment = create_release_experiment(
+        &state,
+        &workspace_name,
+        &application,
+        release_id,
+        pkg_version,
         context_map,
-        vec![control_variant, experimental_variant],
-        format!(
-            "Creating release for application '{}' with version {} and ID {}",
-            application, pkg_version, release_id
-        ),
         format!("Creating new release version {} for application {}", pkg_version, application),
-    );
-
-    let created_experiment_response = create_experiment(
-        &state.superposition_configuration,
-        &superposition_org_id_from_env,
-        &workspace_name,
-        experiment_content,
     )
-    .await
-    .map_err(|e| {
-        eprintln!("Failed to create experiment: {:?}", e); // Log the detailed error
-        error::ErrorInternalServerError(format!("Failed to create experiment in Superposition"))
-    })?;
-
-    // Assuming 'id' is the field in CreateExperimentResponseContent and it has to_string()
-    // The actual type of created_experiment_response.id is models::ExperimentId (likely i64 or similar)
-    let experiment_id_for_ramping = created_experiment_response.id.to_string(); 
-
-    println!(
-        "Experiment {} created. Attempting to ramp to 100% traffic.",
-        experiment_id_for_ramping
-    );
-
-    let ramp_payload = models::RampExperimentRequestContent {
-        change_reason: format!(
-            "Auto-activating and ramping experiment for release {} (pkg_version {}) to 100% traffic.",
-            release_id, pkg_version
-        ),
-        traffic_percentage: 50,
-    };
-
-    match ramp_experiment(
-        &state.superposition_configuration,
-        &experiment_id_for_ramping,
-        &superposition_org_id_from_env, // x_org_id
-        &workspace_name,                // x_tenant (workspace_name)
-        ramp_payload,
-    )
-    .await
-    {
-        Ok(ramp_response) => {
-            println!(
-                "Successfully ramped experiment {}: {:?}",
-                experiment_id_for_ramping, ramp_response
-            );
-            // TODO: Optionally, check ramp_response (models::RampExperimentResponseContent) 
-            // to confirm status if the model provides it.
-        }
-        Err(e) => {
-            // Log the error, but proceed with creating the release in HyperOTA's DB.
-            // The user might need to manually activate/ramp the experiment in Superposition if this fails.
-            eprintln!(
-                "Failed to ramp experiment {}: {:?}. Release will be created, but experiment may need manual activation.",
-                experiment_id_for_ramping, e
-            );
-        }
-    }
+    .await?;
 
     let new_release = ReleaseEntry {
         id: release_id,
-        org_id: organisation,
-        app_id: application,
+        org_id: organisation.clone(),
+        app_id: application.clone(),
         package_version: pkg_version,
         config_version: config.config_version.clone(),
         created_at: now,
@@ -277,7 +429,21 @@ async fn create(
         metadata: req
             .metadata
             .clone()
-            .unwrap_or_else(|| serde_json::json!({}))
+            .unwrap_or_else(|| serde_json::json!({})),
+        // Scheduled releases start at 0% until their first step is applied
+        rollout_percentage: if req.schedule.is_some() { 0 } else { rollout },
+        previous_package_version: previous_release_version,
+        experiment_id: Some(experiment),
+        context: release_context,
+        rolled_back_from: None,
+        status: ReleaseStatus::Draft.as_str().to_string(),
+        ramped_at: None,
+        paused_at: None,
+        live_at: None,
+        concluded_at: None,
+        aborted_at: None,
+        tester_group_id: None,
+        config_revision: Some(config.revision),
     };
 
     diesel::insert_into(releases)
@@ -285,49 +451,420 @@ async fn create(
         .execute(&mut conn)
         .map_err(error::ErrorInternalServerError)?;
 
+    let release = match &req.schedule {
+        Some(schedule) => {
+            save_schedule(&mut conn, release_id, now, schedule)?;
+            // Steps due right away are applied now rather than on the scheduler's next run
+            if let Err(e) = advance_release(&state, &mut conn, release_id).await {
+                warn!("{}. The release scheduler will retry.", e);
+            }
+            find_release(&mut conn, &organisation, &application, &release_id.to_string())?
+        }
+        None => start_release(&state, &mut conn, new_release, &workspace_name, rollout).await,
+    };
+
     Ok(Json(CreateResponse {
         id: release_id.to_string(),
         created_at: now,
         package_version: pkg_version,
         config_version: config.config_version,
+        config_revision: Some(config.revision),
+        rollout_percentage: release.rollout_percentage,
+        previous_package_version: previous_release_version,
+        status: release.status,
+    }))
+}
+
+/// Load a release of the application by its id from the path
+/// Package of the latest public release of a context that every device in it was served.
+/// Drafts, aborted releases and partial rollouts don't count.
+fn previous_full_release(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    release_context: &serde_json::Value,
+) -> Result<Option<i32>> {
+    releases
+        .filter(org_id.eq(organisation).and(app_id.eq(application)))
+        .filter(tester_group_id.is_null())
+        .filter(context.eq(release_context))
+        .filter(
+            status
+                .eq_any([ReleaseStatus::Live.as_str(), ReleaseStatus::Concluded.as_str()])
+                .or(status
+                    .eq_any([ReleaseStatus::Ramping.as_str(), ReleaseStatus::Paused.as_str()])
+                    .and(rollout_percentage.eq(100))),
+        )
+        .order_by(created_at.desc())
+        .select(package_version)
+        .first::<i32>(conn)
+        .optional()
+        .map_err(error::ErrorInternalServerError)
+}
+
+fn find_release(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    release_id: &str,
+) -> Result<ReleaseEntry> {
+    let release_id =
+        Uuid::parse_str(release_id).map_err(|_| error::ErrorBadRequest("Invalid release id"))?;
+
+    releases
+        .filter(
+            id.eq(release_id)
+                .and(org_id.eq(organisation))
+                .and(app_id.eq(application)),
+        )
+        .select(ReleaseEntry::as_select())
+        .first(conn)
+        .optional()
+        .map_err(error::ErrorInternalServerError)?
+        .ok_or_else(|| error::ErrorNotFound("Release not found"))
+}
+
+#[post("/{release_id}/ramp")]
+async fn ramp(
+    path: Path<String>,
+    req: Json<RampRequest>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<ReleaseHistoryEntry>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+
+    let rollout = validate_rollout_percentage(req.rollout_percentage)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let release = find_release(&mut conn, &organisation, &application, &path.into_inner())?;
+    let workspace_name =
+        get_workspace_name_for_application(&application, &organisation, &mut conn).await?;
+
+    let release = ramp_release(&state, &mut conn, &release, &workspace_name, rollout).await?;
+    Ok(Json(release.into()))
+}
+
+#[post("/{release_id}/pause")]
+async fn pause(
+    path: Path<String>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<ReleaseHistoryEntry>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let release = find_release(&mut conn, &organisation, &application, &path.into_inner())?;
+
+    let release = pause_release(&state, &mut conn, &release)?;
+    Ok(Json(release.into()))
+}
+
+#[post("/{release_id}/conclude")]
+async fn conclude(
+    path: Path<String>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<ReleaseHistoryEntry>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let release = find_release(&mut conn, &organisation, &application, &path.into_inner())?;
+    let workspace_name =
+        get_workspace_name_for_application(&application, &organisation, &mut conn).await?;
+
+    let release = conclude_release(&state, &mut conn, &release, &workspace_name).await?;
+    Ok(Json(release.into()))
+}
+
+#[post("/{release_id}/abort")]
+async fn abort(
+    path: Path<String>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<ReleaseHistoryEntry>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let release = find_release(&mut conn, &organisation, &application, &path.into_inner())?;
+    let workspace_name =
+        get_workspace_name_for_application(&application, &organisation, &mut conn).await?;
+
+    let release = abort_release(&state, &mut conn, &release, &workspace_name).await?;
+    Ok(Json(release.into()))
+}
+
+#[get("/{release_id}/schedule")]
+async fn get_schedule(
+    path: Path<String>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<ReleaseScheduleResponse>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, READ).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, READ).map_err(error::ErrorUnauthorized)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let release = find_release(&mut conn, &organisation, &application, &path.into_inner())?;
+    let steps = list_schedule(&mut conn, release.id).map_err(error::ErrorInternalServerError)?;
+
+    Ok(Json(ReleaseScheduleResponse {
+        release: release.into(),
+        steps,
+    }))
+}
+
+#[get("/{release_id}/health")]
+async fn get_health(
+    path: Path<String>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<ReleaseHealthResponse>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, READ).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, READ).map_err(error::ErrorUnauthorized)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let release = find_release(&mut conn, &organisation, &application, &path.into_inner())?;
+    let health = find_health(&mut conn, release.id).map_err(error::ErrorInternalServerError)?;
+
+    Ok(Json(match health {
+        Some(health) => ReleaseHealthResponse {
+            release: release.into(),
+            boots: health.boots,
+            boot_failures: health.boot_failures,
+            updates: health.updates,
+            package_timeouts: health.package_timeouts,
+            updated_at: Some(health.updated_at),
+        },
+        None => ReleaseHealthResponse {
+            release: release.into(),
+            boots: 0,
+            boot_failures: 0,
+            updates: 0,
+            package_timeouts: 0,
+            updated_at: None,
+        },
+    }))
+}
+
+#[post("/{release_id}/rollback")]
+async fn rollback(
+    path: Path<String>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<CreateResponse>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+
+    let target_id = Uuid::parse_str(&path.into_inner())
+        .map_err(|_| error::ErrorBadRequest("Invalid release id"))?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let release = rollback_release(
+        &state,
+        &mut conn,
+        &organisation,
+        &application,
+        target_id,
+        auth_response.sub.clone(),
+        serde_json::json!({}),
+    )
+    .await?;
+
+    Ok(Json(CreateResponse {
+        id: release.id.to_string(),
+        created_at: release.created_at,
+        package_version: release.package_version,
+        config_version: release.config_version,
+        config_revision: release.config_revision,
+        rollout_percentage: release.rollout_percentage,
+        previous_package_version: release.previous_package_version,
+        status: release.status,
     }))
 }
 
 #[get("/history")]
 async fn list_releases(
+    query: web::Query<ReleaseHistoryQuery>,
     state: web::Data<AppState>,
     auth_response: ReqData<AuthResponse>,
 ) -> Result<Json<ReleaseHistoryResponse>> {
+    use crate::utils::db::schema::hyperotaserver::packages::dsl as package_dsl;
+
     let auth_response = auth_response.into_inner();
     let organisation =
         validate_user(auth_response.organisation, READ).map_err(error::ErrorUnauthorized)?;
     let application =
         validate_user(auth_response.application, READ).map_err(error::ErrorUnauthorized)?;
 
+    let query = query.into_inner();
+    let limit = page_size(query.limit)?;
+
     let mut conn = state
         .db_pool
         .get()
         .map_err(error::ErrorInternalServerError)?;
 
-    let release_entries = releases
+    let mut release_query = releases
         .filter(org_id.eq(&organisation).and(app_id.eq(&application)))
-        .order_by(created_at.desc())
+        .into_boxed();
+    if let Some(from_version) = query.from_version {
+        release_query = release_query.filter(package_version.ge(from_version));
+    }
+    if let Some(to_version) = query.to_version {
+        release_query = release_query.filter(package_version.le(to_version));
+    }
+    if let Some(creator) = query.created_by {
+        release_query = release_query.filter(created_by.eq(creator));
+    }
+    if let Some(after) = query.created_after {
+        release_query = release_query.filter(created_at.ge(after));
+    }
+    if let Some(before) = query.created_before {
+        release_query = release_query.filter(created_at.le(before));
+    }
+    if let Some(package_tag) = query.tag {
+        release_query = release_query.filter(
+            package_version.eq_any(
+                package_dsl::packages
+                    .filter(package_dsl::org_id.eq(&organisation))
+                    .filter(package_dsl::app_id.eq(&application))
+                    .filter(package_dsl::tag.eq(package_tag))
+                    .select(package_dsl::version),
+            ),
+        );
+    }
+    if let Some(release_status) = query.status {
+        release_query = release_query.filter(status.eq(release_status.as_str()));
+    }
+    if let Some(cursor) = query.cursor {
+        let (after_created_at, after_id) = parse_release_cursor(&cursor)?;
+        release_query = match query.order {
+            SortOrder::Asc => release_query.filter(
+                created_at
+                    .gt(after_created_at)
+                    .or(created_at.eq(after_created_at).and(id.gt(after_id))),
+            ),
+            SortOrder::Desc => release_query.filter(
+                created_at
+                    .lt(after_created_at)
+                    .or(created_at.eq(after_created_at).and(id.lt(after_id))),
+            ),
+        };
+    }
+    release_query = match query.order {
+        SortOrder::Asc => release_query.order_by((created_at.asc(), id.asc())),
+        SortOrder::Desc => release_query.order_by((created_at.desc(), id.desc())),
+    };
+
+    let release_entries = release_query
+        .limit(limit + 1)
         .load::<ReleaseEntry>(&mut conn)
         .map_err(error::ErrorInternalServerError)?;
+    let (release_entries, next_cursor) = paginate(release_entries, limit, release_cursor);
 
     let release_history = release_entries
         .into_iter()
-        .map(|entry| ReleaseHistoryEntry {
-            id: entry.id.to_string(),
-            package_version: entry.package_version,
-            config_version: entry.config_version,
-            created_at: entry.created_at,
-            created_by: entry.created_by,
-            metadata: entry.metadata,
-        })
+        .map(ReleaseHistoryEntry::from)
         .collect();
 
     Ok(Json(ReleaseHistoryResponse {
         releases: release_history,
+        next_cursor,
     }))
 }
+
+#[post("/preview")]
+async fn preview(
+    req: Json<PreviewRequest>,
+    state: web::Data<AppState>,
+    auth_response: ReqData<AuthResponse>,
+) -> Result<Json<ReleasePreview>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, READ).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, READ).map_err(error::ErrorUnauthorized)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let req = req.into_inner();
+    let hypothetical = match req.release {
+        Some(release) => Some(HypotheticalRelease {
+            package_version: release.package_version,
+            targeting: release.context,
+            rollout_percentage: validate_rollout_percentage(release.rollout_percentage.unwrap_or(100))?
+                as u8,
+        }),
+        None => None,
+    };
+    let release_preview = preview_release(
+        &state,
+        &mut conn,
+        &organisation,
+        &application,
+        PreviewDevice {
+            context: req.context,
+            device_id: req.device_id,
+            user_id: req.user_id,
+            package_version: req.package_version,
+        },
+        hypothetical,
+    )
+    .await?;
+    Ok(Json(release_preview))
+}
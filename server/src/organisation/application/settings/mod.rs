@@ -0,0 +1,225 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::{
+    error, get,
+    http::header::HeaderValue,
+    put,
+    web::{self, Json, ReqData},
+    Result, Scope,
+};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    middleware::auth::{validate_user, AuthResponse, READ, WRITE},
+    types::AppState,
+    utils::{
+        db::{
+            models::ApplicationSettings,
+            schema::hyperotaserver::application_settings::{self, dsl::*},
+        },
+        settings::{
+            find_settings, DEFAULT_CACHE_CONTROL, DEFAULT_MIN_HEALTH_SAMPLES,
+            DEFAULT_RETAIN_RELEASED_DAYS,
+        },
+    },
+};
+
+pub fn add_routes() -> Scope {
+    Scope::new("")
+        .service(get_settings)
+        .service(update_settings)
+}
+
+/// Settings to change; omitted fields keep their current value. Nullable settings are
+/// cleared with an explicit `null`.
+#[derive(Debug, Deserialize)]
+struct UpdateSettingsRequest {
+    cache_control: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    max_boot_failure_rate: Option<Option<f64>>,
+    #[serde(default, deserialize_with = "present")]
+    max_package_timeout_rate: Option<Option<f64>>,
+    min_health_samples: Option<i32>,
+    #[serde(default, deserialize_with = "present")]
+    retain_package_versions: Option<Option<i32>>,
+    retain_released_days: Option<i32>,
+}
+
+/// Tell a field sent as `null` (`Some(None)`) apart from an omitted one (`None`)
+fn present<'de, T, D>(deserializer: D) -> std::result::Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Columns changed by an update; `None` leaves a column as it is
+#[derive(AsChangeset)]
+#[diesel(table_name = application_settings)]
+struct SettingsChange {
+    cache_control: Option<String>,
+    updated_at: DateTime<Utc>,
+    max_boot_failure_rate: Option<Option<f64>>,
+    max_package_timeout_rate: Option<Option<f64>>,
+    min_health_samples: Option<i32>,
+    retain_package_versions: Option<Option<i32>>,
+    retain_released_days: Option<i32>,
+}
+
+#[derive(Serialize)]
+struct SettingsResponse {
+    cache_control: String,
+    max_boot_failure_rate: Option<f64>,
+    max_package_timeout_rate: Option<f64>,
+    min_health_samples: i32,
+    retain_package_versions: Option<i32>,
+    retain_released_days: i32,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+impl From<ApplicationSettings> for SettingsResponse {
+    fn from(settings: ApplicationSettings) -> Self {
+        SettingsResponse {
+            cache_control: settings.cache_control,
+            max_boot_failure_rate: settings.max_boot_failure_rate,
+            max_package_timeout_rate: settings.max_package_timeout_rate,
+            min_health_samples: settings.min_health_samples,
+            retain_package_versions: settings.retain_package_versions,
+            retain_released_days: settings.retain_released_days,
+            updated_at: Some(settings.updated_at),
+        }
+    }
+}
+
+fn validate_rate(name: &str, rate: Option<f64>) -> Result<()> {
+    match rate {
+        Some(rate) if !(0.0..=1.0).contains(&rate) => Err(error::ErrorBadRequest(format!(
+            "{} must be between 0 and 1",
+            name
+        ))),
+        _ => Ok(()),
+    }
+}
+
+#[get("")]
+async fn get_settings(
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<SettingsResponse>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, READ).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, READ).map_err(error::ErrorUnauthorized)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let settings = find_settings(&organisation, &application, &mut conn)
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(Json(match settings {
+        Some(settings) => settings.into(),
+        None => SettingsResponse {
+            cache_control: DEFAULT_CACHE_CONTROL.to_string(),
+            max_boot_failure_rate: None,
+            max_package_timeout_rate: None,
+            min_health_samples: DEFAULT_MIN_HEALTH_SAMPLES,
+            retain_package_versions: None,
+            retain_released_days: DEFAULT_RETAIN_RELEASED_DAYS,
+            updated_at: None,
+        },
+    }))
+}
+
+#[put("")]
+async fn update_settings(
+    req: Json<UpdateSettingsRequest>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<SettingsResponse>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+
+    let req = req.into_inner();
+    // The value is sent verbatim as a response header, so it has to be a valid one
+    let new_cache_control = req.cache_control.map(|value| value.trim().to_string());
+    if new_cache_control
+        .as_ref()
+        .is_some_and(|value| value.is_empty() || HeaderValue::from_str(value).is_err())
+    {
+        return Err(error::ErrorBadRequest("Invalid Cache-Control value"));
+    }
+    validate_rate("max_boot_failure_rate", req.max_boot_failure_rate.flatten())?;
+    validate_rate("max_package_timeout_rate", req.max_package_timeout_rate.flatten())?;
+    if req.min_health_samples.is_some_and(|samples| samples < 1) {
+        return Err(error::ErrorBadRequest("min_health_samples must be at least 1"));
+    }
+    if req.retain_package_versions.flatten().is_some_and(|versions| versions < 1) {
+        return Err(error::ErrorBadRequest("retain_package_versions must be at least 1"));
+    }
+    if req.retain_released_days.is_some_and(|days| days < 0) {
+        return Err(error::ErrorBadRequest("retain_released_days cannot be negative"));
+    }
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let now = Utc::now();
+    // Applications without settings yet start from the defaults
+    let defaults = ApplicationSettings {
+        org_id: organisation,
+        app_id: application,
+        cache_control: new_cache_control
+            .clone()
+            .unwrap_or_else(|| DEFAULT_CACHE_CONTROL.to_string()),
+        updated_at: now,
+        max_boot_failure_rate: req.max_boot_failure_rate.flatten(),
+        max_package_timeout_rate: req.max_package_timeout_rate.flatten(),
+        min_health_samples: req.min_health_samples.unwrap_or(DEFAULT_MIN_HEALTH_SAMPLES),
+        retain_package_versions: req.retain_package_versions.flatten(),
+        retain_released_days: req.retain_released_days.unwrap_or(DEFAULT_RETAIN_RELEASED_DAYS),
+    };
+    let change = SettingsChange {
+        cache_control: new_cache_control,
+        updated_at: now,
+        max_boot_failure_rate: req.max_boot_failure_rate,
+        max_package_timeout_rate: req.max_package_timeout_rate,
+        min_health_samples: req.min_health_samples,
+        retain_package_versions: req.retain_package_versions,
+        retain_released_days: req.retain_released_days,
+    };
+
+    let saved = diesel::insert_into(application_settings)
+        .values(&defaults)
+        .on_conflict((org_id, app_id))
+        .do_update()
+        .set(&change)
+        .returning(ApplicationSettings::as_returning())
+        .get_result(&mut conn)
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(Json(saved.into()))
+}
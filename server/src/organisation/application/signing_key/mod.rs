@@ -0,0 +1,114 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::{
+    error, get, post,
+    web::{self, Json, ReqData},
+    Result, Scope,
+};
+use serde::Serialize;
+
+use crate::{
+    middleware::auth::{validate_user, AuthResponse, READ, WRITE},
+    types::AppState,
+    utils::signing::{
+        get_or_provision_signing_key, list_signing_keys, rotate_signing_key, PublicKey,
+    },
+};
+
+pub fn add_routes() -> Scope {
+    Scope::new("")
+        .service(list_keys)
+        .service(provision_key)
+        .service(rotate_key)
+}
+
+#[derive(Serialize)]
+struct SigningKeysResponse {
+    keys: Vec<PublicKey>,
+}
+
+#[get("")]
+async fn list_keys(
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<SigningKeysResponse>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, READ).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, READ).map_err(error::ErrorUnauthorized)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let keys = list_signing_keys(&organisation, &application, &mut conn)
+        .map_err(error::ErrorInternalServerError)?
+        .into_iter()
+        .map(PublicKey::from)
+        .collect();
+    Ok(Json(SigningKeysResponse { keys }))
+}
+
+/// Provision the key of an application created before keys were provisioned with it, so
+/// SDKs can pin it before the first poll. A no-op when the application already has one.
+#[post("")]
+async fn provision_key(
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<SigningKeysResponse>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    get_or_provision_signing_key(&state, &organisation, &application, &mut conn).await?;
+
+    let keys = list_signing_keys(&organisation, &application, &mut conn)
+        .map_err(error::ErrorInternalServerError)?
+        .into_iter()
+        .map(PublicKey::from)
+        .collect();
+    Ok(Json(SigningKeysResponse { keys }))
+}
+
+/// Retire the active key and generate a new one. Retired keys stay listed so SDKs can
+/// still verify configs signed before the rotation.
+#[post("/rotate")]
+async fn rotate_key(
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<PublicKey>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let entry = rotate_signing_key(&state, &organisation, &application, &mut conn).await?;
+    Ok(Json(entry.into()))
+}
@@ -0,0 +1,264 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::{
+    delete, error, get, post,
+    web::{self, Json, Path, Query, ReqData},
+    HttpResponse, Result, Scope,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    middleware::auth::{validate_user, AuthResponse, READ, WRITE},
+    types::AppState,
+    utils::telemetry::{
+        active_devices, adoption_curve, create_ingest_key, list_ingest_keys, revoke_ingest_key,
+        AdoptionPoint, VersionDevices,
+    },
+};
+
+const DEFAULT_ADOPTION_DAYS: i64 = 14;
+const MAX_ADOPTION_DAYS: i64 = 90;
+const DEFAULT_ACTIVE_WITHIN_HOURS: i64 = 24;
+const MAX_ACTIVE_WITHIN_HOURS: i64 = 24 * 90;
+
+pub fn add_routes() -> Scope {
+    Scope::new("")
+        .service(get_adoption)
+        .service(get_active_devices)
+        .service(create_key)
+        .service(list_keys)
+        .service(revoke_key)
+}
+
+#[derive(Debug, Deserialize)]
+struct AdoptionQuery {
+    days: Option<i64>,
+    granularity: Option<String>,
+}
+
+#[derive(Serialize)]
+struct AdoptionResponse {
+    granularity: String,
+    since: DateTime<Utc>,
+    points: Vec<AdoptionPoint>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ActiveDevicesQuery {
+    active_within_hours: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct ActiveDevicesResponse {
+    since: DateTime<Utc>,
+    total_devices: i64,
+    versions: Vec<VersionDevices>,
+}
+
+#[get("/adoption")]
+async fn get_adoption(
+    query: Query<AdoptionQuery>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<AdoptionResponse>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, READ).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, READ).map_err(error::ErrorUnauthorized)?;
+
+    let days = query.days.unwrap_or(DEFAULT_ADOPTION_DAYS);
+    if !(1..=MAX_ADOPTION_DAYS).contains(&days) {
+        return Err(error::ErrorBadRequest(format!(
+            "days must be between 1 and {}",
+            MAX_ADOPTION_DAYS
+        )));
+    }
+    let granularity = query.granularity.clone().unwrap_or_else(|| "day".to_string());
+    if granularity != "hour" && granularity != "day" {
+        return Err(error::ErrorBadRequest("granularity must be hour or day"));
+    }
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let since = Utc::now() - Duration::days(days);
+    let points = adoption_curve(&mut conn, &organisation, &application, &granularity, since)
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(Json(AdoptionResponse {
+        granularity,
+        since,
+        points,
+    }))
+}
+
+#[get("/devices")]
+async fn get_active_devices(
+    query: Query<ActiveDevicesQuery>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<ActiveDevicesResponse>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, READ).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, READ).map_err(error::ErrorUnauthorized)?;
+
+    let hours = query
+        .active_within_hours
+        .unwrap_or(DEFAULT_ACTIVE_WITHIN_HOURS);
+    if !(1..=MAX_ACTIVE_WITHIN_HOURS).contains(&hours) {
+        return Err(error::ErrorBadRequest(format!(
+            "active_within_hours must be between 1 and {}",
+            MAX_ACTIVE_WITHIN_HOURS
+        )));
+    }
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let since = Utc::now() - Duration::hours(hours);
+    let versions = active_devices(&mut conn, &organisation, &application, since)
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(Json(ActiveDevicesResponse {
+        since,
+        total_devices: versions.iter().map(|version| version.active_devices).sum(),
+        versions,
+    }))
+}
+
+#[derive(Serialize)]
+struct IngestKeyResponse {
+    id: Uuid,
+    /// Sent by devices in `x-telemetry-key`; it cannot be retrieved again
+    key: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct IngestKeyInfo {
+    id: Uuid,
+    created_at: DateTime<Utc>,
+    created_by: String,
+    revoked_at: Option<DateTime<Utc>>,
+    revoked_by: Option<String>,
+}
+
+#[derive(Serialize)]
+struct IngestKeysResponse {
+    keys: Vec<IngestKeyInfo>,
+}
+
+/// Create a key devices send telemetry with. Earlier keys keep working until revoked.
+#[post("/key")]
+async fn create_key(
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<IngestKeyResponse>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let (key, entry) =
+        create_ingest_key(&mut conn, &organisation, &application, &auth_response.sub)
+            .map_err(error::ErrorInternalServerError)?;
+    Ok(Json(IngestKeyResponse {
+        id: entry.id,
+        key,
+        created_at: entry.created_at,
+    }))
+}
+
+#[get("/keys")]
+async fn list_keys(
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<IngestKeysResponse>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, READ).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, READ).map_err(error::ErrorUnauthorized)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let keys = list_ingest_keys(&mut conn, &organisation, &application)
+        .map_err(error::ErrorInternalServerError)?
+        .into_iter()
+        .map(|key| IngestKeyInfo {
+            id: key.id,
+            created_at: key.created_at,
+            created_by: key.created_by,
+            revoked_at: key.revoked_at,
+            revoked_by: key.revoked_by,
+        })
+        .collect();
+    Ok(Json(IngestKeysResponse { keys }))
+}
+
+/// Revoke a key, e.g. once devices moved to a newer one or after it leaked
+#[delete("/key/{key_id}")]
+async fn revoke_key(
+    path: Path<Uuid>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+    let key_id = path.into_inner();
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let revoked = revoke_ingest_key(
+        &mut conn,
+        &organisation,
+        &application,
+        key_id,
+        &auth_response.sub,
+    )
+    .map_err(error::ErrorInternalServerError)?;
+    if !revoked {
+        return Err(error::ErrorNotFound(format!(
+            "No active telemetry key {}",
+            key_id
+        )));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,386 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::{
+    delete, error, get, post, put,
+    web::{self, Json, Path, ReqData},
+    HttpResponse, Result, Scope,
+};
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    middleware::auth::{validate_user, AuthResponse, READ, WRITE},
+    types::AppState,
+    utils::{
+        db::{
+            models::TesterGroup,
+            schema::hyperotaserver::{releases::dsl as releases, tester_groups::dsl as tester_groups},
+        },
+        tester_groups::{find_tester_group, generate_tester_token},
+    },
+};
+
+/// Device ids and user ids a single group may hold together
+const MAX_GROUP_MEMBERS: usize = 1000;
+
+pub fn add_routes() -> Scope {
+    Scope::new("")
+        .service(list_groups)
+        .service(create_group)
+        .service(get_group)
+        .service(update_group)
+        .service(delete_group)
+        .service(regenerate_token)
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateGroupRequest {
+    name: String,
+    #[serde(flatten)]
+    members: GroupMembers,
+}
+
+/// Replaces the members of a group
+#[derive(Debug, Deserialize)]
+struct UpdateGroupRequest {
+    #[serde(flatten)]
+    members: GroupMembers,
+}
+
+#[derive(Debug, Deserialize)]
+struct GroupMembers {
+    #[serde(default)]
+    device_ids: Vec<String>,
+    #[serde(default)]
+    user_ids: Vec<String>,
+}
+
+/// A group's token is only returned when it is generated; members send it in
+/// `x-tester-token`
+#[derive(Serialize)]
+struct GroupTokenResponse {
+    #[serde(flatten)]
+    group: TesterGroup,
+    token: String,
+}
+
+#[derive(Serialize)]
+struct GroupListResponse {
+    groups: Vec<TesterGroup>,
+}
+
+/// Trim and deduplicate one kind of member id
+fn validate_ids(field: &str, ids: Vec<String>) -> Result<Vec<String>> {
+    let mut members: Vec<String> = Vec::with_capacity(ids.len());
+    for id in ids {
+        let id = id.trim();
+        if id.is_empty() {
+            return Err(error::ErrorBadRequest(format!("{} cannot be empty", field)));
+        }
+        if !members.iter().any(|existing| existing == id) {
+            members.push(id.to_string());
+        }
+    }
+    Ok(members)
+}
+
+/// Trim, deduplicate and check the device ids and user ids of a group
+fn validate_members(members: GroupMembers) -> Result<(Vec<String>, Vec<String>)> {
+    let device_ids = validate_ids("device_ids", members.device_ids)?;
+    let user_ids = validate_ids("user_ids", members.user_ids)?;
+    if device_ids.len() + user_ids.len() > MAX_GROUP_MEMBERS {
+        return Err(error::ErrorBadRequest(format!(
+            "A tester group can have at most {} members",
+            MAX_GROUP_MEMBERS
+        )));
+    }
+    Ok((device_ids, user_ids))
+}
+
+#[get("")]
+async fn list_groups(
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<GroupListResponse>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, READ).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, READ).map_err(error::ErrorUnauthorized)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let groups = tester_groups::tester_groups
+        .filter(tester_groups::org_id.eq(&organisation))
+        .filter(tester_groups::app_id.eq(&application))
+        .order_by(tester_groups::name.asc())
+        .select(TesterGroup::as_select())
+        .load(&mut conn)
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(Json(GroupListResponse { groups }))
+}
+
+#[post("")]
+async fn create_group(
+    req: Json<CreateGroupRequest>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<GroupTokenResponse>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+
+    let req = req.into_inner();
+    let group_name = req.name.trim().to_string();
+    if group_name.is_empty() {
+        return Err(error::ErrorBadRequest("name cannot be empty"));
+    }
+    let (device_ids, user_ids) = validate_members(req.members)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let now = Utc::now();
+    let (token, token_hash) = generate_tester_token();
+    let group = TesterGroup {
+        id: Uuid::new_v4(),
+        org_id: organisation,
+        app_id: application,
+        name: group_name,
+        device_ids,
+        user_ids,
+        created_at: now,
+        created_by: auth_response.sub,
+        updated_at: now,
+        token_hash: Some(token_hash),
+    };
+    diesel::insert_into(tester_groups::tester_groups)
+        .values(&group)
+        .execute(&mut conn)
+        .map_err(|e| match e {
+            diesel::result::Error::DatabaseError(
+                diesel::result::DatabaseErrorKind::UniqueViolation,
+                _,
+            ) => error::ErrorConflict(format!("Tester group {} already exists", group.name)),
+            e => error::ErrorInternalServerError(e),
+        })?;
+
+    Ok(Json(GroupTokenResponse { group, token }))
+}
+
+#[get("/{name}")]
+async fn get_group(
+    path: Path<String>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<TesterGroup>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, READ).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, READ).map_err(error::ErrorUnauthorized)?;
+    let group_name = path.into_inner();
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let group = find_tester_group(&mut conn, &organisation, &application, &group_name)
+        .map_err(error::ErrorInternalServerError)?
+        .ok_or_else(|| error::ErrorNotFound(format!("Tester group {} not found", group_name)))?;
+    Ok(Json(group))
+}
+
+#[put("/{name}")]
+async fn update_group(
+    path: Path<String>,
+    req: Json<UpdateGroupRequest>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<TesterGroup>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+    let group_name = path.into_inner();
+
+    let (device_ids, user_ids) = validate_members(req.into_inner().members)?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let group = diesel::update(
+        tester_groups::tester_groups
+            .filter(tester_groups::org_id.eq(&organisation))
+            .filter(tester_groups::app_id.eq(&application))
+            .filter(tester_groups::name.eq(&group_name)),
+    )
+    .set((
+        tester_groups::device_ids.eq(device_ids),
+        tester_groups::user_ids.eq(user_ids),
+        tester_groups::updated_at.eq(Utc::now()),
+    ))
+    .returning(TesterGroup::as_returning())
+    .get_result(&mut conn)
+    .optional()
+    .map_err(error::ErrorInternalServerError)?
+    .ok_or_else(|| error::ErrorNotFound(format!("Tester group {} not found", group_name)))?;
+
+    Ok(Json(group))
+}
+
+#[delete("/{name}")]
+async fn delete_group(
+    path: Path<String>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+    let group_name = path.into_inner();
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let group = find_tester_group(&mut conn, &organisation, &application, &group_name)
+        .map_err(error::ErrorInternalServerError)?
+        .ok_or_else(|| error::ErrorNotFound(format!("Tester group {} not found", group_name)))?;
+
+    // Releases keep pointing at their group, so it stays in the release history
+    let released = diesel::select(diesel::dsl::exists(
+        releases::releases.filter(releases::tester_group_id.eq(group.id)),
+    ))
+    .get_result::<bool>(&mut conn)
+    .map_err(error::ErrorInternalServerError)?;
+    if released {
+        return Err(error::ErrorConflict(format!(
+            "Tester group {} has releases; empty it instead",
+            group_name
+        )));
+    }
+
+    diesel::delete(tester_groups::tester_groups.filter(tester_groups::id.eq(group.id)))
+        .execute(&mut conn)
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Replace the token of a group, e.g. when it leaked or for groups created before tokens.
+/// Members keep no pinned package until they send the new one.
+#[post("/{name}/token")]
+async fn regenerate_token(
+    path: Path<String>,
+    auth_response: ReqData<AuthResponse>,
+    state: web::Data<AppState>,
+) -> Result<Json<GroupTokenResponse>> {
+    let auth_response = auth_response.into_inner();
+    let organisation =
+        validate_user(auth_response.organisation, WRITE).map_err(error::ErrorUnauthorized)?;
+    let application =
+        validate_user(auth_response.application, WRITE).map_err(error::ErrorUnauthorized)?;
+    let group_name = path.into_inner();
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let (token, token_hash) = generate_tester_token();
+    let group = diesel::update(
+        tester_groups::tester_groups
+            .filter(tester_groups::org_id.eq(&organisation))
+            .filter(tester_groups::app_id.eq(&application))
+            .filter(tester_groups::name.eq(&group_name)),
+    )
+    .set((
+        tester_groups::token_hash.eq(token_hash),
+        tester_groups::updated_at.eq(Utc::now()),
+    ))
+    .returning(TesterGroup::as_returning())
+    .get_result(&mut conn)
+    .optional()
+    .map_err(error::ErrorInternalServerError)?
+    .ok_or_else(|| error::ErrorNotFound(format!("Tester group {} not found", group_name)))?;
+
+    Ok(Json(GroupTokenResponse { group, token }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn members(device_ids: &[&str], user_ids: &[&str]) -> GroupMembers {
+        GroupMembers {
+            device_ids: device_ids.iter().map(|id| id.to_string()).collect(),
+            user_ids: user_ids.iter().map(|id| id.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn ids_are_trimmed_and_deduplicated() {
+        let (device_ids, user_ids) =
+            validate_members(members(&[" d1 ", "d2", "d1"], &["u1", "u1 "])).unwrap();
+        assert_eq!(device_ids, ["d1", "d2"]);
+        assert_eq!(user_ids, ["u1"]);
+    }
+
+    #[test]
+    fn groups_may_list_only_users() {
+        let (device_ids, user_ids) = validate_members(members(&[], &["u1"])).unwrap();
+        assert!(device_ids.is_empty());
+        assert_eq!(user_ids, ["u1"]);
+    }
+
+    #[test]
+    fn empty_ids_are_rejected() {
+        let error = validate_members(members(&["d1", "  "], &[])).unwrap_err();
+        assert!(error.to_string().contains("device_ids"));
+        let error = validate_members(members(&[], &[""])).unwrap_err();
+        assert!(error.to_string().contains("user_ids"));
+    }
+
+    #[test]
+    fn the_member_limit_counts_devices_and_users() {
+        let device_ids: Vec<String> = (0..600).map(|i| format!("d{i}")).collect();
+        let user_ids: Vec<String> = (0..400).map(|i| format!("u{i}")).collect();
+        let at_limit = GroupMembers { device_ids: device_ids.clone(), user_ids };
+        assert!(validate_members(at_limit).is_ok());
+
+        let user_ids: Vec<String> = (0..401).map(|i| format!("u{i}")).collect();
+        let over_limit = GroupMembers { device_ids, user_ids };
+        let error = validate_members(over_limit).unwrap_err();
+        assert!(error.to_string().contains("at most 1000 members"));
+    }
+}
@@ -0,0 +1,483 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Builds the Superposition context a release is resolved with from the device's request.
+//
+// Values are collected, in increasing order of precedence, from well-known headers,
+// the legacy `context` JSON query parameter and `ctx.<dimension>` query parameters.
+// They are then checked against the dimensions defined in the application's workspace.
+
+use std::{collections::HashMap, sync::Arc};
+
+use actix_web::{error, HttpRequest, Result};
+use serde_json::{Map, Value};
+use superposition_rust_sdk::apis::default_api::list_dimensions;
+
+use crate::types::AppState;
+
+/// Dimension carrying the device id, used only for rollout bucketing and tester groups
+const DEVICE_ID_DIMENSION: &str = "device_id";
+/// Dimension carrying the user signed in on the device, used only for tester groups
+const USER_ID_DIMENSION: &str = "user_id";
+
+/// Headers set by the SDK and the dimension each one populates
+const HEADER_DIMENSIONS: [(&str, &str); 6] = [
+    ("x-sdk-version", "sdk_version"),
+    ("x-app-version", "app_version"),
+    ("x-os", "os"),
+    ("x-device-id", DEVICE_ID_DIMENSION),
+    ("x-user-id", USER_ID_DIMENSION),
+    ("x-locale", "locale"),
+];
+
+/// Headers the context is built from
+pub fn context_headers() -> impl Iterator<Item = &'static str> {
+    HEADER_DIMENSIONS.iter().map(|(header_name, _)| *header_name)
+}
+
+const CONTEXT_QUERY_PREFIX: &str = "ctx.";
+const DIMENSIONS_PAGE_SIZE: f64 = 100.0;
+
+/// Suffix of the integer dimension holding the version code of a version dimension, e.g.
+/// `app_version_semver` for `app_version`
+const VERSION_CODE_SUFFIX: &str = "_semver";
+/// Major, minor and patch must each be below this to be encoded
+const VERSION_PART_LIMIT: i64 = 100_000;
+
+/// Dimension name to its JSON schema, for one workspace
+pub type Dimensions = Arc<HashMap<String, Value>>;
+
+/// Context values supplied by the device, before validation
+pub struct RawContext {
+    /// Values that must match a dimension
+    explicit: Map<String, Value>,
+    /// Values from well-known headers, dropped when the workspace has no such dimension
+    implicit: Map<String, Value>,
+}
+
+impl RawContext {
+    /// Context given as a JSON object rather than by a device request, e.g. for previews.
+    /// `device_id` and `user_id` apply unless `explicit` has them.
+    pub fn from_values(
+        explicit: Map<String, Value>,
+        device_id: Option<String>,
+        user_id: Option<String>,
+    ) -> Self {
+        let mut implicit = Map::new();
+        for (dimension, value) in [(DEVICE_ID_DIMENSION, device_id), (USER_ID_DIMENSION, user_id)] {
+            if let Some(value) = value.filter(|_| !explicit.contains_key(dimension)) {
+                implicit.insert(dimension.to_string(), Value::String(value));
+            }
+        }
+        RawContext { explicit, implicit }
+    }
+
+    /// Stable device identifier used for rollout bucketing, if the device sent one
+    pub fn device_id(&self) -> Option<&str> {
+        self.explicit
+            .get(DEVICE_ID_DIMENSION)
+            .or_else(|| self.implicit.get(DEVICE_ID_DIMENSION))
+            .and_then(Value::as_str)
+    }
+
+    /// User signed in on the device, if it sent one; only matched against tester groups
+    pub fn user_id(&self) -> Option<&str> {
+        self.explicit
+            .get(USER_ID_DIMENSION)
+            .or_else(|| self.implicit.get(USER_ID_DIMENSION))
+            .and_then(Value::as_str)
+    }
+
+    /// Whether the device sent no context at all
+    pub fn is_empty(&self) -> bool {
+        self.explicit.is_empty() && self.implicit.is_empty()
+    }
+
+    /// Context as sent, for when the workspace dimensions cannot be fetched. Values keep
+    /// the type they were sent with and unknown keys simply match no context.
+    pub fn into_unvalidated(self) -> Value {
+        let mut context = self.implicit;
+        context.extend(self.explicit);
+        Value::Object(context)
+    }
+}
+
+/// Collect context values from headers and query parameters, rejecting malformed input
+pub fn extract_context(
+    req: &HttpRequest,
+    query: &HashMap<String, String>,
+) -> Result<RawContext> {
+    let mut implicit = Map::new();
+    for (header_name, dimension) in HEADER_DIMENSIONS {
+        if let Some(value) = req.headers().get(header_name) {
+            let value = value
+                .to_str()
+                .map_err(|_| error::ErrorBadRequest(format!("Invalid {} header", header_name)))?
+                .trim();
+            if value.is_empty() {
+                return Err(error::ErrorBadRequest(format!("Empty {} header", header_name)));
+            }
+            implicit.insert(dimension.to_string(), Value::String(value.to_string()));
+        }
+    }
+
+    let mut explicit = match query.get("context") {
+        Some(context) => match serde_json::from_str(context) {
+            Ok(Value::Object(context)) => context,
+            Ok(_) => return Err(error::ErrorBadRequest("context must be a JSON object")),
+            Err(e) => return Err(error::ErrorBadRequest(format!("Invalid context JSON: {}", e))),
+        },
+        None => Map::new(),
+    };
+
+    for (key, value) in query {
+        if let Some(dimension) = key.strip_prefix(CONTEXT_QUERY_PREFIX) {
+            if dimension.is_empty() {
+                return Err(error::ErrorBadRequest("Empty dimension name in query"));
+            }
+            explicit.insert(dimension.to_string(), Value::String(value.clone()));
+        }
+    }
+
+    for key in explicit.keys() {
+        implicit.remove(key);
+    }
+
+    Ok(RawContext { explicit, implicit })
+}
+
+/// All dimensions of a workspace, served from `AppState::dimension_cache` when fresh
+pub async fn workspace_dimensions(state: &AppState, workspace_name: &str) -> Result<Dimensions> {
+    if let Some(dimensions) = state.dimension_cache.get(&workspace_name.to_string()) {
+        return Ok(dimensions);
+    }
+
+    let mut dimensions = HashMap::new();
+    let mut page = 1.0;
+    loop {
+        let response = list_dimensions(
+            &state.superposition_configuration,
+            &state.env.superposition_org_id,
+            workspace_name,
+            Some(page),
+            Some(DIMENSIONS_PAGE_SIZE),
+        )
+        .await
+        .map_err(|e| error::ErrorInternalServerError(format!("Failed to list dimensions: {}", e)))?;
+
+        for dimension in response.data.unwrap_or_default() {
+            dimensions.insert(dimension.dimension, dimension.schema);
+        }
+
+        if page >= response.total_pages.unwrap_or(1.0) {
+            break;
+        }
+        page += 1.0;
+    }
+
+    let dimensions = Arc::new(dimensions);
+    state
+        .dimension_cache
+        .insert(workspace_name.to_string(), dimensions.clone());
+    Ok(dimensions)
+}
+
+/// Check the raw context against the workspace dimensions, coercing values to the dimension type
+pub fn validate_context(raw: RawContext, dimensions: &Dimensions) -> Result<Value> {
+    let mut context = Map::new();
+
+    for (key, value) in raw.implicit {
+        if let Some(schema) = dimensions.get(&key) {
+            context.insert(key.clone(), coerce_value(&key, value, schema)?);
+        }
+    }
+
+    for (key, value) in raw.explicit {
+        let schema = dimensions
+            .get(&key)
+            .ok_or_else(|| error::ErrorBadRequest(format!("Unknown dimension: {}", key)))?;
+        context.insert(key.clone(), coerce_value(&key, value, schema)?);
+    }
+
+    // Devices and users are told apart only by rollout bucketing and tester groups, which
+    // read their ids from the raw context. Resolving without them keeps one release cache
+    // entry per context, not per device.
+    context.remove(DEVICE_ID_DIMENSION);
+    context.remove(USER_ID_DIMENSION);
+
+    // Version codes are always derived from the version, so `>=` and `<=` rules compare
+    // versions rather than strings. Versions without a code match no such rule.
+    let version_codes: Vec<(String, Option<Value>)> = context
+        .iter()
+        .filter_map(|(key, value)| {
+            let code_dimension = version_code_dimension(key);
+            dimensions.contains_key(&code_dimension).then(|| {
+                (code_dimension, value.as_str().and_then(version_code).map(Value::from))
+            })
+        })
+        .collect();
+    for (code_dimension, code) in version_codes {
+        match code {
+            Some(code) => context.insert(code_dimension, code),
+            None => context.remove(&code_dimension),
+        };
+    }
+
+    Ok(Value::Object(context))
+}
+
+/// Integer dimension holding the version code of a version dimension
+pub fn version_code_dimension(dimension: &str) -> String {
+    format!("{}{}", dimension, VERSION_CODE_SUFFIX)
+}
+
+/// A semantic version `MAJOR[.MINOR[.PATCH]]` as an integer that orders like the version,
+/// `MAJOR * 10^10 + MINOR * 10^5 + PATCH`. Pre-releases and build metadata have none.
+pub fn version_code(version: &str) -> Option<i64> {
+    let parts: Vec<&str> = version.trim().split('.').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+    let mut code = 0;
+    for index in 0..3 {
+        let part = match parts.get(index) {
+            Some(part) if !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()) => part
+                .parse::<i64>()
+                .ok()
+                .filter(|part| *part < VERSION_PART_LIMIT)?,
+            Some(_) => return None,
+            None => 0,
+        };
+        code = code * VERSION_PART_LIMIT + part;
+    }
+    Some(code)
+}
+
+/// Convert string values to the type declared by the dimension schema and check its `enum`
+pub fn coerce_value(dimension: &str, value: Value, schema: &Value) -> Result<Value> {
+    let invalid = || {
+        error::ErrorBadRequest(format!(
+            "Invalid value for dimension {}: expected {}",
+            dimension, schema
+        ))
+    };
+
+    let value = match (schema.get("type").and_then(Value::as_str), value) {
+        (Some("string"), value @ Value::String(_)) => value,
+        (Some("integer"), Value::String(s)) => {
+            s.trim().parse::<i64>().map(Value::from).map_err(|_| invalid())?
+        }
+        (Some("integer"), value @ Value::Number(_)) if value.is_i64() || value.is_u64() => value,
+        (Some("number"), Value::String(s)) => s
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number)
+            .ok_or_else(invalid)?,
+        (Some("number"), value @ Value::Number(_)) => value,
+        (Some("boolean"), Value::String(s)) => {
+            s.trim().parse::<bool>().map(Value::Bool).map_err(|_| invalid())?
+        }
+        (Some("boolean"), value @ Value::Bool(_)) => value,
+        (Some("string" | "integer" | "number" | "boolean"), _) => return Err(invalid()),
+        // Other schema types are passed through for Superposition to match
+        (_, value) => value,
+    };
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(&value) {
+            return Err(invalid());
+        }
+    }
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+    use serde_json::json;
+
+    use super::*;
+
+    fn query(params: &[(&str, &str)]) -> HashMap<String, String> {
+        params
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn coerce(value: Value, schema: Value) -> Result<Value> {
+        coerce_value("dimension", value, &schema)
+    }
+
+    #[test]
+    fn strings_are_coerced_to_the_dimension_type() {
+        assert_eq!(coerce(json!(" 42 "), json!({ "type": "integer" })).unwrap(), json!(42));
+        assert_eq!(coerce(json!("0.5"), json!({ "type": "number" })).unwrap(), json!(0.5));
+        assert_eq!(coerce(json!("true"), json!({ "type": "boolean" })).unwrap(), json!(true));
+        assert_eq!(coerce(json!("ios"), json!({ "type": "string" })).unwrap(), json!("ios"));
+    }
+
+    #[test]
+    fn typed_values_are_kept() {
+        assert_eq!(coerce(json!(42), json!({ "type": "integer" })).unwrap(), json!(42));
+        assert_eq!(coerce(json!(1), json!({ "type": "number" })).unwrap(), json!(1));
+        assert_eq!(coerce(json!(false), json!({ "type": "boolean" })).unwrap(), json!(false));
+    }
+
+    #[test]
+    fn values_of_the_wrong_type_are_rejected() {
+        assert!(coerce(json!("4.2"), json!({ "type": "integer" })).is_err());
+        assert!(coerce(json!(4.2), json!({ "type": "integer" })).is_err());
+        assert!(coerce(json!("NaN"), json!({ "type": "number" })).is_err());
+        assert!(coerce(json!("yes"), json!({ "type": "boolean" })).is_err());
+        assert!(coerce(json!(1), json!({ "type": "string" })).is_err());
+    }
+
+    #[test]
+    fn enum_is_checked_after_coercion() {
+        let schema = json!({ "type": "integer", "enum": [1, 2] });
+        assert_eq!(coerce(json!("2"), schema.clone()).unwrap(), json!(2));
+        assert!(coerce(json!("3"), schema).is_err());
+    }
+
+    #[test]
+    fn version_codes_order_like_versions() {
+        assert!(version_code("1.10.0") > version_code("1.9.0"));
+        assert!(version_code("2.0.0") > version_code("1.99999.99999"));
+        assert!(version_code("1.2.10") > version_code("1.2.9"));
+        assert_eq!(version_code("2"), version_code("2.0.0"));
+        assert_eq!(version_code(" 2.1 "), version_code("2.1.0"));
+    }
+
+    #[test]
+    fn invalid_versions_have_no_code() {
+        for version in ["", "1.2.3.4", "1..2", "1.x", "v1.2", "1.2.3-beta", "-1.0", "100000.0.0"] {
+            assert_eq!(version_code(version), None, "{} has a code", version);
+        }
+    }
+
+    #[test]
+    fn version_codes_are_derived_in_contexts() {
+        let dimensions: Dimensions = Arc::new(HashMap::from([
+            ("app_version".to_string(), json!({ "type": "string" })),
+            ("app_version_semver".to_string(), json!({ "type": "integer" })),
+        ]));
+        let context = |values: Value| {
+            let Value::Object(values) = values else { unreachable!() };
+            validate_context(RawContext::from_values(values, None, None), &dimensions).unwrap()
+        };
+
+        assert_eq!(
+            context(json!({ "app_version": "1.10.0" })),
+            json!({ "app_version": "1.10.0", "app_version_semver": 10_001_000_000i64 })
+        );
+        // Codes sent by the device are replaced, or dropped with an invalid version
+        assert_eq!(
+            context(json!({ "app_version": "1.10.0", "app_version_semver": 1 })),
+            json!({ "app_version": "1.10.0", "app_version_semver": 10_001_000_000i64 })
+        );
+        assert_eq!(
+            context(json!({ "app_version": "beta", "app_version_semver": 1 })),
+            json!({ "app_version": "beta" })
+        );
+    }
+
+    #[test]
+    fn member_ids_are_kept_out_of_the_resolution_context() {
+        let dimensions: Dimensions = Arc::new(HashMap::from([
+            ("os".to_string(), json!({ "type": "string" })),
+            ("device_id".to_string(), json!({ "type": "string" })),
+            ("user_id".to_string(), json!({ "type": "string" })),
+        ]));
+        let raw = RawContext::from_values(
+            Map::from_iter([("os".to_string(), json!("android"))]),
+            Some("device-1".to_string()),
+            Some("user-1".to_string()),
+        );
+
+        assert_eq!(raw.device_id(), Some("device-1"));
+        assert_eq!(raw.user_id(), Some("user-1"));
+        assert_eq!(validate_context(raw, &dimensions).unwrap(), json!({ "os": "android" }));
+    }
+
+    #[test]
+    fn context_is_read_from_headers_and_query() {
+        let req = TestRequest::default()
+            .insert_header(("x-os", " android "))
+            .insert_header(("x-app-version", "2.1.0"))
+            .insert_header(("x-device-id", "device-1"))
+            .to_http_request();
+        let raw = extract_context(
+            &req,
+            &query(&[
+                ("context", r#"{ "os": "ios", "tier": "gold" }"#),
+                ("ctx.tier", "silver"),
+                ("unrelated", "ignored"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(raw.device_id(), Some("device-1"));
+        // The legacy context overrides headers, and `ctx.` parameters override both
+        assert_eq!(
+            raw.into_unvalidated(),
+            json!({
+                "os": "ios",
+                "app_version": "2.1.0",
+                "device_id": "device-1",
+                "tier": "silver"
+            })
+        );
+    }
+
+    #[test]
+    fn headers_of_unknown_dimensions_are_ignored_but_query_values_are_not() {
+        let dimensions: Dimensions =
+            Arc::new(HashMap::from([("os".to_string(), json!({ "type": "string" }))]));
+        let req = TestRequest::default()
+            .insert_header(("x-os", "android"))
+            .insert_header(("x-locale", "en-IN"))
+            .to_http_request();
+
+        let raw = extract_context(&req, &HashMap::new()).unwrap();
+        assert_eq!(validate_context(raw, &dimensions).unwrap(), json!({ "os": "android" }));
+
+        let raw = extract_context(&req, &query(&[("ctx.locale", "en-IN")])).unwrap();
+        assert!(validate_context(raw, &dimensions).is_err());
+    }
+
+    #[test]
+    fn malformed_context_is_rejected() {
+        let plain = TestRequest::default().to_http_request();
+        let empty_header = TestRequest::default()
+            .insert_header(("x-os", "  "))
+            .to_http_request();
+
+        assert!(extract_context(&empty_header, &HashMap::new()).is_err());
+        assert!(extract_context(&plain, &query(&[("context", "[1, 2]")])).is_err());
+        assert!(extract_context(&plain, &query(&[("context", "{ os")])).is_err());
+        assert!(extract_context(&plain, &query(&[("ctx.", "android")])).is_err());
+        assert!(extract_context(&plain, &HashMap::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn other_schema_types_pass_through() {
+        let value = json!(["a", "b"]);
+        assert_eq!(coerce(value.clone(), json!({ "type": "array" })).unwrap(), value);
+        assert_eq!(coerce(json!("x"), json!({})).unwrap(), json!("x"));
+    }
+}
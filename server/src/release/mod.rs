@@ -14,22 +14,31 @@
 
 use actix_web::{
     error, get,
-    web::{self, Json},
-    Result, Scope,
+    http::header::{self, ContentType},
+    web, HttpRequest, HttpResponse, Result, Scope,
 };
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
 use superposition_rust_sdk::apis::default_api::{applicable_variants, get_resolved_config};
 
-use crate::utils::{db::schema::hyperotaserver::configs::dsl::{
-    app_id as config_app_id, configs as configs_table, org_id as config_org_id,
-    version as config_version,
-}, workspace::get_workspace_name_for_application};
+use crate::utils::{
+    db::schema::hyperotaserver::configs::dsl::{
+        app_id as config_app_id, configs as configs_table, org_id as config_org_id,
+        revision as config_revision, version as config_version,
+    },
+    db::schema::hyperotaserver::last_known_good_releases::dsl as last_known_good,
+    patches::{find_patches, INDEX_PATCH_PATH},
+    s3::{content_key, object_url},
+    settings::{get_cache_control, DEFAULT_CACHE_CONTROL},
+    signing::{get_signing_key, list_signing_keys, AppSigningKey, PublicKey},
+    tester_groups::{find_pinned_release, MemberIds},
+    workspace::get_workspace_name_for_application,
+};
 use crate::{
     types::AppState,
-    utils::db::{
-        models::{ConfigEntry, PackageEntryRead},
-    },
+    utils::db::models::{ConfigEntry, LastKnownGoodRelease, PackageEntryRead},
 };
 
 use crate::utils::db::schema::hyperotaserver::packages::dsl::*;
@@ -38,20 +47,85 @@ use diesel::prelude::*;
 use diesel::ExpressionMethods;
 use diesel::QueryDsl;
 
+pub mod context;
+pub mod preview;
+pub mod rollout;
+pub mod targeting;
+pub mod telemetry;
+
+use context::{context_headers, extract_context, validate_context, workspace_dimensions};
+use rollout::{find_rollout, Rollout};
+
 pub fn add_routes() -> Scope {
     Scope::new("")
         .service(serve_release)
         .service(serve_release_v2)
+        .service(list_public_keys)
+        .service(telemetry::ingest_telemetry)
 }
 
-#[derive(Serialize, Debug)]
-struct ReleaseConfig {
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReleaseConfig {
     config: Config,
     package: Package,
     resources: serde_json::Value,
 }
 
-#[derive(Serialize, Debug)]
+/// Release configs resolved for one context. While the release is partially rolled out,
+/// devices outside the rollout are served the config of the previous package.
+#[derive(Debug, Clone)]
+pub struct ResolvedRelease {
+    current: ReleaseConfig,
+    rollout: Option<(Rollout, ReleaseConfig)>,
+}
+
+impl ResolvedRelease {
+    fn for_device(&self, device_id: Option<&str>, current_version: Option<i32>) -> &ReleaseConfig {
+        match &self.rollout {
+            Some((rollout, previous)) if !rollout.includes(device_id, current_version) => previous,
+            _ => &self.current,
+        }
+    }
+
+    /// Config every device is guaranteed to be allowed, kept as the last-known-good fallback
+    fn stable(&self) -> &ReleaseConfig {
+        match &self.rollout {
+            Some((_, previous)) => previous,
+            None => &self.current,
+        }
+    }
+}
+
+/// Identifies a resolved release in `AppState::release_cache`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ReleaseCacheKey {
+    organisation: String,
+    application: String,
+    workspace: String,
+    context: String,
+}
+
+/// Detached signature of the response body and the id of the key that produced it
+const SIGNATURE_HEADER: &str = "x-signature";
+const SIGNATURE_KEY_ID_HEADER: &str = "x-signature-key-id";
+
+/// Set on release configs served from `last_known_good_releases` because resolution failed
+const DEGRADED_HEADER: &str = "x-release-degraded";
+
+/// Package version the client currently runs, so patches from it can be advertised
+const PACKAGE_VERSION_HEADER: &str = "x-package-version";
+
+/// Token of the tester group the device belongs to
+const TESTER_TOKEN_HEADER: &str = "x-tester-token";
+
+/// Drop all cached release configs of an application so the next poll resolves them afresh
+pub fn invalidate_release_cache(state: &AppState, organisation: &str, application: &str) {
+    state
+        .release_cache
+        .invalidate_where(|key| key.organisation == organisation && key.application == application);
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 struct Config {
     version: String,
     release_config_timeout: u32,
@@ -59,7 +133,7 @@ struct Config {
     properties: ConfigProperties,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 struct ConfigProperties {
     tenant_info: serde_json::Value,
 }
@@ -83,20 +157,41 @@ struct InnerPackage {
     version: i32,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 struct File {
     url: String,
     #[serde(rename = "filePath")]
     file_path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    size: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    patch: Option<FilePatch>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Binary diff (bsdiff) turning the client's copy of a file into this version of it
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct FilePatch {
+    url: String,
+    from_version: i32,
+    sha256: String,
+    size: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 struct Package {
     name: String,
     version: String,
     #[serde(flatten)]
     properties: serde_json::Value,
     index: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    index_sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    index_size: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    index_patch: Option<FilePatch>,
     important: Vec<File>,
     lazy: Vec<File>,
 }
@@ -191,13 +286,68 @@ fn decode_to_config_v2(value: Value) -> Result<PackageMeta> {
     Ok(PackageMeta { config, package })
 }
 
+/// Strong validator for a serialized release config; identical configs always hash the same
+fn compute_etag(body: &[u8]) -> String {
+    format!("\"{:x}\"", Sha256::digest(body))
+}
+
+fn etag_matches(req: &HttpRequest, etag: &str) -> bool {
+    req.headers()
+        .get_all(header::IF_NONE_MATCH)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|entity_tag| entity_tag.trim())
+        .any(|entity_tag| entity_tag == "*" || entity_tag.trim_start_matches("W/") == etag)
+}
+
+/// Request headers a release response depends on, for `Vary`: the context headers, including
+/// the device id rollouts are bucketed by, the tester token and the client's package version
+/// patches are chosen for. Shared caches must not hand one device's response to another.
+fn vary_headers() -> String {
+    context_headers()
+        .chain([TESTER_TOKEN_HEADER, PACKAGE_VERSION_HEADER])
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Serialize and sign the release config, answering with 304 if the client already holds this exact body
+fn release_response(
+    req: &HttpRequest,
+    release_config: &ReleaseConfig,
+    cache_control: &str,
+    signing_key: &AppSigningKey,
+) -> Result<HttpResponse> {
+    let body = serde_json::to_vec(release_config).map_err(error::ErrorInternalServerError)?;
+    let etag = compute_etag(&body);
+
+    if etag_matches(req, &etag) {
+        return Ok(HttpResponse::NotModified()
+            .insert_header((header::ETAG, etag))
+            .insert_header((header::VARY, vary_headers()))
+            .insert_header((header::CACHE_CONTROL, cache_control))
+            .finish());
+    }
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::VARY, vary_headers()))
+        .insert_header((header::CACHE_CONTROL, cache_control))
+        .insert_header((SIGNATURE_HEADER, signing_key.sign(&body)))
+        .insert_header((SIGNATURE_KEY_ID_HEADER, signing_key.key_id.as_str()))
+        .content_type(ContentType::json())
+        .body(body))
+}
+
 #[get("{organisation}/{application}")]
 async fn serve_release(
+    req: HttpRequest,
     path: web::Path<(String, String)>,
+    query: web::Query<std::collections::HashMap<String, String>>,
     state: web::Data<AppState>,
-) -> Result<Json<ReleaseConfig>> {
+) -> Result<HttpResponse> {
     println!("serve_release : {:?}", path);
     let (organisation, application) = path.into_inner();
+    let raw_context = extract_context(&req, &query)?;
     // Check CAC to find which package to use.
     // Read Package from the DB
     // Read Other keys from CAC
@@ -211,6 +361,22 @@ async fn serve_release(
 
     let superposition_org_id_from_env = state.env.superposition_org_id.clone();
 
+    // Legacy releases are resolved in a workspace named after the application. Plain
+    // polls carry no context, so they don't depend on the dimensions being listed.
+    let context = if raw_context.is_empty() {
+        Value::Object(Map::new())
+    } else {
+        match workspace_dimensions(&state, &application).await {
+            Ok(dimensions) => validate_context(raw_context, &dimensions)?,
+            Err(e) => {
+                warn!(
+                    "Failed to list dimensions of {}, using the context unvalidated: {}",
+                    application, e
+                );
+                raw_context.into_unvalidated()
+            }
+        }
+    };
 
     let config = get_resolved_config(
         &state.superposition_configuration,
@@ -220,7 +386,7 @@ async fn serve_release(
         None,
         None,
         Some(superposition_rust_sdk::models::MergeStrategy::Merge),
-        Some(json!({})), // TODO: Add Find out how to add custom dimesions
+        Some(context),
     )
     .await
     .map_err(|e| error::ErrorInternalServerError(format!("Failed to get config: {}", e)))?;
@@ -266,77 +432,62 @@ async fn serve_release(
     let lazy_files: Vec<crate::utils::db::models::File> = 
         serde_json::from_value(package_data.lazy.clone()).unwrap_or_default();
     
-    // If not using URLs, construct full URLs for the files
-    let final_important_files = if !package_data.use_urls {
-        important_files.iter().map(|file| {
-            crate::utils::db::models::File {
-                url: if package_data.version_splits {
-                    format!(
-                        "{}/assets/{}/{}/{}/{}",
-                        &state.env.public_url,
-                        &package_data.org_id,
-                        &package_data.app_id,
-                        package_data.version,
-                        file.file_path
-                    )
-                } else {
-                    format!(
-                        "{}/assets/{}/{}/{}",
-                        &state.env.public_url,
-                        &package_data.org_id,
-                        &package_data.app_id,
-                        file.file_path
-                    )
-                },
-                file_path: file.file_path.clone(),
-            }
-        }).collect()
-    } else {
-        important_files
+    // If not using URLs, construct full URLs for the files. Files with a known hash live in
+    // the shared content-addressed store, others under the package's asset path.
+    let asset_url = |file_path: &str, sha256: Option<&String>| match sha256 {
+        Some(sha256) => object_url(
+            &state.env,
+            &content_key(&package_data.org_id, &package_data.app_id, sha256),
+        ),
+        None if package_data.version_splits => object_url(
+            &state.env,
+            &format!(
+                "assets/{}/{}/{}/{}",
+                package_data.org_id, package_data.app_id, package_data.version, file_path
+            ),
+        ),
+        None => object_url(
+            &state.env,
+            &format!("assets/{}/{}/{}", package_data.org_id, package_data.app_id, file_path),
+        ),
     };
-
-    let final_lazy_files = if !package_data.use_urls {
-        lazy_files.iter().map(|file| {
-            crate::utils::db::models::File {
-                url: if package_data.version_splits {
-                    format!(
-                        "{}/assets/{}/{}/{}/{}",
-                        &state.env.public_url,
-                        &package_data.org_id,
-                        &package_data.app_id,
-                        package_data.version,
-                        file.file_path
-                    )
-                } else {
-                    format!(
-                        "{}/assets/{}/{}/{}",
-                        &state.env.public_url,
-                        &package_data.org_id,
-                        &package_data.app_id,
-                        file.file_path
-                    )
-                },
-                file_path: file.file_path.clone(),
-            }
-        }).collect()
-    } else {
-        lazy_files
+    let with_urls = |files: Vec<crate::utils::db::models::File>| {
+        if package_data.use_urls {
+            return files;
+        }
+        files
+            .into_iter()
+            .map(|file| crate::utils::db::models::File {
+                url: asset_url(&file.file_path, file.sha256.as_ref()),
+                ..file
+            })
+            .collect()
     };
+    let final_important_files = with_urls(important_files);
+    let final_lazy_files = with_urls(lazy_files);
 
     let package_index = if !package_data.use_urls {
-        format!(
-                "{}/assets/{}/{}/{}/{}",
-                &state.env.public_url,
-                &package_data.org_id,
-                &package_data.app_id,
-                package_data.version,
-                package_data.index
-            )
+        match &package_data.index_sha256 {
+            Some(sha256) => asset_url(&package_data.index, Some(sha256)),
+            None => object_url(
+                &state.env,
+                &format!(
+                    "assets/{}/{}/{}/{}",
+                    package_data.org_id,
+                    package_data.app_id,
+                    package_data.version,
+                    package_data.index
+                ),
+            ),
+        }
     } else {
         package_data.index.clone()
     };
 
-    Ok(Json(ReleaseConfig {
+    let cache_control = get_cache_control(&organisation, &application, &mut conn)
+        .map_err(error::ErrorInternalServerError)?;
+
+    let release_config = ReleaseConfig {
         config: Config {
             version: packages_meta.config.version,
             release_config_timeout: packages_meta.config.release_config_timeout as u32,
@@ -350,71 +501,417 @@ async fn serve_release(
             version: packages_meta.package.version.to_string(),
             properties: package_data.properties.clone(),
             index: package_index,
+            index_sha256: package_data.index_sha256,
+            index_size: package_data.index_size,
+            index_patch: None,
             important: final_important_files.iter().map(|f| File {
                 url: f.url.clone(),
                 file_path: f.file_path.clone(),
+                sha256: f.sha256.clone(),
+                size: f.size,
+                patch: None,
             }).collect(),
             lazy: final_lazy_files.iter().map(|f| File {
                 url: f.url.clone(),
                 file_path: f.file_path.clone(),
+                sha256: f.sha256.clone(),
+                size: f.size,
+                patch: None,
             }).collect(),
         },
         resources: package_data.resources,
-    }))
+    };
+
+    let signing_key = get_signing_key(&state, &organisation, &application, &mut conn).await?;
+
+    release_response(&req, &release_config, &cache_control, &signing_key)
 }
 
 
 #[get("v2/{organisation}/{application}")]
 async fn serve_release_v2(
+    req: HttpRequest,
     path: web::Path<(String, String)>,
     query: web::Query<std::collections::HashMap<String, String>>,
     state: web::Data<AppState>,
-) -> Result<Json<ReleaseConfig>> {
+) -> Result<HttpResponse> {
     let (organisation, application) = path.into_inner();
-    println!(
+    debug!(
         "Serving release for org: {}, app: {}",
         organisation, application
     );
+    let raw_context = extract_context(&req, &query)?;
+    let device_id = raw_context.device_id().map(str::to_string);
+    let user_id = raw_context.user_id().map(str::to_string);
 
     let mut conn = state
         .db_pool
         .get()
         .map_err(error::ErrorInternalServerError)?;
 
-    let superposition_org_id_from_env = state.env.superposition_org_id.clone();
+    // Tester group members get their pinned package, whatever Superposition and the public
+    // rollout would serve
+    let tester_token = req
+        .headers()
+        .get(TESTER_TOKEN_HEADER)
+        .and_then(|value| value.to_str().ok());
+    match find_pinned_release(
+        &mut conn,
+        &organisation,
+        &application,
+        MemberIds {
+            device_id: device_id.as_deref(),
+            user_id: user_id.as_deref(),
+        },
+        tester_token,
+    ) {
+        Ok(Some(pinned)) => {
+            info!(
+                "Serving package {} of tester release {} (group {})",
+                pinned.package_version, pinned.release_id, pinned.tester_group_id
+            );
+            let release_config =
+                load_release_config(
+                    &mut conn,
+                    &organisation,
+                    &application,
+                    pinned.package_version,
+                    pinned.config_revision,
+                )?;
+            return serve_release_config(
+                &req,
+                &state,
+                &mut conn,
+                &organisation,
+                &application,
+                &release_config,
+            )
+            .await;
+        }
+        Ok(None) => {}
+        Err(e) => warn!("Failed to look up tester groups, serving the public release: {:?}", e),
+    }
 
-    let workspace_name = get_workspace_name_for_application(&application, &organisation, &mut conn).await
-        .map_err(|e| error::ErrorInternalServerError(format!("Failed to get workspace name: {}", e)))?;
+    let workspace_name = match get_workspace_name_for_application(&application, &organisation, &mut conn).await {
+        Ok(workspace_name) => workspace_name,
+        Err(e) => {
+            error!("Failed to get workspace name: {}", e);
+            return serve_last_known_good(
+                &req,
+                &state,
+                &mut conn,
+                &organisation,
+                &application,
+                None,
+                error::ErrorInternalServerError(format!("Failed to get workspace name: {}", e)),
+            )
+            .await;
+        }
+    };
 
-    // Extract context from query if provided
-    let context = if let Some(context) = query.get("context") {
-        serde_json::from_str(context).unwrap_or(json!({}))
-    } else {
-        json!({})
+    let context = match workspace_dimensions(&state, &workspace_name).await {
+        Ok(dimensions) => validate_context(raw_context, &dimensions)?,
+        Err(e) => {
+            warn!("Superposition unavailable, falling back to last known good: {}", e);
+            return serve_last_known_good(
+                &req,
+                &state,
+                &mut conn,
+                &organisation,
+                &application,
+                None,
+                e,
+            )
+            .await;
+        }
+    };
+
+    debug!("workspace_name: {}", workspace_name);
+    debug!("Using context: {:?}", context);
+
+    let cache_key = ReleaseCacheKey {
+        organisation: organisation.clone(),
+        application: application.clone(),
+        workspace: workspace_name.clone(),
+        context: context.to_string(),
+    };
+
+    let resolved = match state.release_cache.get(&cache_key) {
+        Some(resolved) => resolved,
+        None => {
+            let config = match fetch_resolved_config(&state, &workspace_name, &context).await {
+                Ok(config) => config,
+                Err(e) => {
+                    warn!("Superposition unavailable, falling back to last known good: {}", e);
+                    return serve_last_known_good(
+                        &req,
+                        &state,
+                        &mut conn,
+                        &organisation,
+                        &application,
+                        Some(&cache_key.context),
+                        e,
+                    )
+                    .await;
+                }
+            };
+            let resolved = resolve_release_v2(&mut conn, &organisation, &application, config)?;
+            if let Err(e) = save_last_known_good(
+                &mut conn,
+                &organisation,
+                &application,
+                &cache_key.context,
+                resolved.stable(),
+            ) {
+                warn!("Failed to persist last known good release config: {:?}", e);
+            }
+            state.release_cache.insert(cache_key, resolved.clone());
+            resolved
+        }
+    };
+    let release_config = resolved.for_device(device_id.as_deref(), client_package_version(&req));
+    serve_release_config(&req, &state, &mut conn, &organisation, &application, release_config).await
+}
+
+/// Respond with a release config, patched for the client's package version and signed
+async fn serve_release_config(
+    req: &HttpRequest,
+    state: &AppState,
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    release_config: &ReleaseConfig,
+) -> Result<HttpResponse> {
+    let patched = with_patches(req, conn, organisation, application, release_config);
+    let release_config = patched.as_ref().unwrap_or(release_config);
+
+    let cache_control = get_cache_control(organisation, application, conn)
+        .map_err(error::ErrorInternalServerError)?;
+    let signing_key = get_signing_key(state, organisation, application, conn).await?;
+
+    release_response(req, release_config, &cache_control, &signing_key)
+}
+
+/// Package version the client runs, from the `x-package-version` header
+fn client_package_version(req: &HttpRequest) -> Option<i32> {
+    req.headers()
+        .get(PACKAGE_VERSION_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<i32>().ok())
+}
+
+/// Copy of the release config with patches from the client's current package version
+/// attached to its files. None when the client sent no version or nothing can be patched.
+fn with_patches(
+    req: &HttpRequest,
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    release_config: &ReleaseConfig,
+) -> Option<ReleaseConfig> {
+    let from_version = client_package_version(req)?;
+    let to_version = release_config.package.version.parse::<i32>().ok()?;
+    if from_version == to_version {
+        return None;
+    }
+
+    let file_patches = match find_patches(conn, organisation, application, from_version, to_version) {
+        Ok(file_patches) if !file_patches.is_empty() => file_patches,
+        Ok(_) => return None,
+        Err(e) => {
+            warn!("Failed to load patches, serving full files: {:?}", e);
+            return None;
+        }
+    };
+    let patch_for = |file_path: &str| {
+        file_patches.get(file_path).map(|patch| FilePatch {
+            url: patch.url.clone(),
+            from_version: patch.from_version,
+            sha256: patch.sha256.clone(),
+            size: patch.size,
+        })
     };
 
-    println!("workspace_name: {}", workspace_name);
-    println!("superposition_org_id_from_env: {}", superposition_org_id_from_env);
+    let mut patched = release_config.clone();
+    patched.package.index_patch = patch_for(INDEX_PATCH_PATH);
+    for file in patched
+        .package
+        .important
+        .iter_mut()
+        .chain(patched.package.lazy.iter_mut())
+    {
+        file.patch = patch_for(&file.file_path);
+    }
+    if let Some(resource_files) = patched.resources.as_array_mut() {
+        for resource in resource_files {
+            let patch = resource
+                .get("filePath")
+                .and_then(Value::as_str)
+                .and_then(&patch_for);
+            if let (Some(patch), Some(resource)) = (patch, resource.as_object_mut()) {
+                resource.insert("patch".to_string(), json!(patch));
+            }
+        }
+    }
+    Some(patched)
+}
+
+/// Context key of the fallback served when a request's context could not be validated
+const DEFAULT_CONTEXT_KEY: &str = "{}";
+
+/// Persist a freshly resolved release config as the fallback for its context,
+/// skipping the write when the stored copy is already up to date
+fn save_last_known_good(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    context_key: &str,
+    release_config: &ReleaseConfig,
+) -> Result<()> {
+    let release_config =
+        serde_json::to_value(release_config).map_err(error::ErrorInternalServerError)?;
+
+    let stored = last_known_good::last_known_good_releases
+        .filter(
+            last_known_good::org_id
+                .eq(organisation)
+                .and(last_known_good::app_id.eq(application))
+                .and(last_known_good::context_key.eq(context_key)),
+        )
+        .select(last_known_good::release_config)
+        .first::<Value>(conn)
+        .optional()
+        .map_err(error::ErrorInternalServerError)?;
+    if stored.as_ref() == Some(&release_config) {
+        return Ok(());
+    }
+
+    let entry = LastKnownGoodRelease {
+        org_id: organisation.to_string(),
+        app_id: application.to_string(),
+        context_key: context_key.to_string(),
+        release_config,
+        updated_at: chrono::Utc::now(),
+    };
+
+    diesel::insert_into(last_known_good::last_known_good_releases)
+        .values(&entry)
+        .on_conflict((
+            last_known_good::org_id,
+            last_known_good::app_id,
+            last_known_good::context_key,
+        ))
+        .do_update()
+        .set(&entry)
+        .execute(conn)
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(())
+}
+
+/// Serve the persisted last-known-good release config of `context_key` with the degraded header,
+/// falling back to the context-free copy when the context is unknown or was never resolved.
+/// Fails with `cause` when neither exists.
+async fn serve_last_known_good(
+    req: &HttpRequest,
+    state: &AppState,
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    context_key: Option<&str>,
+    cause: error::Error,
+) -> Result<HttpResponse> {
+    let context_keys = match context_key {
+        Some(context_key) => vec![context_key, DEFAULT_CONTEXT_KEY],
+        None => vec![DEFAULT_CONTEXT_KEY],
+    };
+    let entry = last_known_good::last_known_good_releases
+        .filter(
+            last_known_good::org_id
+                .eq(organisation)
+                .and(last_known_good::app_id.eq(application))
+                .and(last_known_good::context_key.eq_any(&context_keys)),
+        )
+        .select(LastKnownGoodRelease::as_select())
+        .load(conn)
+        .map_err(error::ErrorInternalServerError)?
+        .into_iter()
+        .min_by_key(|entry| entry.context_key == DEFAULT_CONTEXT_KEY);
+
+    let Some(entry) = entry else {
+        return Err(cause);
+    };
 
-    println!("Using context: {:?}", context);
+    let release_config: ReleaseConfig =
+        serde_json::from_value(entry.release_config).map_err(error::ErrorInternalServerError)?;
 
-    let applicable_variants  = applicable_variants( 
+    // Degraded copies must be revalidated so devices pick up the live config once it recovers
+    let signing_key = get_signing_key(state, organisation, application, conn).await?;
+    let mut response =
+        release_response(req, &release_config, DEFAULT_CACHE_CONTROL, &signing_key)?;
+    response.headers_mut().insert(
+        header::HeaderName::from_static(DEGRADED_HEADER),
+        header::HeaderValue::from_static("true"),
+    );
+    Ok(response)
+}
+
+#[derive(Serialize)]
+struct PublicKeysResponse {
+    keys: Vec<PublicKey>,
+}
+
+/// Public keys release configs of an application are signed with, newest first, for pinning in the SDK
+#[get("keys/{organisation}/{application}")]
+async fn list_public_keys(
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<web::Json<PublicKeysResponse>> {
+    let (organisation, application) = path.into_inner();
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let keys = list_signing_keys(&organisation, &application, &mut conn)
+        .map_err(error::ErrorInternalServerError)?
+        .into_iter()
+        .map(PublicKey::from)
+        .collect();
+
+    Ok(web::Json(PublicKeysResponse { keys }))
+}
+
+/// Variants of running experiments in a workspace, as `{experiment_id}-{variant}` ids
+async fn fetch_applicable_variants(state: &AppState, workspace_name: &str) -> Result<Vec<String>> {
+    let superposition_org_id_from_env = state.env.superposition_org_id.clone();
+    debug!("superposition_org_id_from_env: {}", superposition_org_id_from_env);
+
+    applicable_variants(
         &state.superposition_configuration,
         &superposition_org_id_from_env,
-        &workspace_name)
-        .await
-        .map_err(|e| {
-            error::ErrorInternalServerError(format!("Failed to get applicable variants: {}", e))
-        })?;
+        workspace_name,
+    )
+    .await
+    .map_err(|e| error::ErrorInternalServerError(format!("Failed to get applicable variants: {}", e)))
+}
 
+/// Resolve the raw config for a context and a set of experiment variants from Superposition
+async fn resolve_config(
+    state: &AppState,
+    workspace_name: &str,
+    context: &Value,
+    variants: &[String],
+) -> Result<Value> {
     let mut context_with_variants = context.as_object().unwrap_or(&serde_json::Map::new()).clone();
-    context_with_variants.insert("variants".to_string(), json!(applicable_variants));
+    context_with_variants.insert("variants".to_string(), json!(variants));
     let final_context = serde_json::Value::Object(context_with_variants);
 
     let config = get_resolved_config(
         &state.superposition_configuration,
-        &superposition_org_id_from_env,
-        &workspace_name,
+        &state.env.superposition_org_id,
+        workspace_name,
         None,
         None,
         None,
@@ -424,17 +921,83 @@ async fn serve_release_v2(
     .await
     .map_err(|e| error::ErrorInternalServerError(format!("Failed to get config: {}", e)))?;
 
-    println!("Got resolved config from Superposition: {:?}", config);
+    debug!("Got resolved config from Superposition: {:?}", config);
+
+    Ok(config)
+}
+
+/// Resolve the raw config for a context from Superposition
+async fn fetch_resolved_config(
+    state: &AppState,
+    workspace_name: &str,
+    context: &Value,
+) -> Result<Value> {
+    let variants = fetch_applicable_variants(state, workspace_name).await?;
+    resolve_config(state, workspace_name, context, &variants).await
+}
+
+/// Build the release configs for a resolved Superposition config, including the previous
+/// package's config when the release it points to is only partially rolled out
+fn resolve_release_v2(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    config: Value,
+) -> Result<ResolvedRelease> {
+    let package_version = resolve_package_version(conn, organisation, application, config)?;
+    let revision = released_config_revision(conn, organisation, application, package_version)?;
+    let current = load_release_config(conn, organisation, application, package_version, revision)?;
+
+    let rollout = find_rollout(organisation, application, package_version, conn)
+        .map_err(error::ErrorInternalServerError)?;
+    let rollout = match rollout {
+        Some(rollout) => {
+            let previous_revision = released_config_revision(
+                conn,
+                organisation,
+                application,
+                rollout.previous_package_version,
+            )?;
+            match load_release_config(
+                conn,
+                organisation,
+                application,
+                rollout.previous_package_version,
+                previous_revision,
+            ) {
+                Ok(previous) => Some((rollout, previous)),
+                Err(e) => {
+                    warn!(
+                        "Previous package {} unavailable, serving version {} to all devices: {}",
+                        rollout.previous_package_version, package_version, e
+                    );
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+
+    Ok(ResolvedRelease { current, rollout })
+}
 
+/// Package version a resolved Superposition config points to; 0 stands for the latest package
+fn resolve_package_version(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    config: Value,
+) -> Result<i32> {
     let packages_meta = decode_to_config_v2(config)?;
-    println!("Successfully decoded packages meta: {:?}", packages_meta);
+    debug!("Successfully decoded packages meta: {:?}", packages_meta);
 
-    // If version is 0, get the latest version
+    // If version is 0, get the latest version. Archived packages may have lost their files.
     let package_version = if packages_meta.package.version == 0 {
         packages
-            .filter(org_id.eq(&organisation).and(app_id.eq(&application)))
+            .filter(org_id.eq(organisation).and(app_id.eq(application)))
+            .filter(archived_at.is_null())
             .select(diesel::dsl::max(version))
-            .first::<Option<i32>>(&mut conn)
+            .first::<Option<i32>>(conn)
             .map_err(|e| {
                 error::ErrorInternalServerError(format!("Failed to get latest version: {}", e))
             })?
@@ -443,26 +1006,73 @@ async fn serve_release_v2(
         packages_meta.package.version
     };
 
+    Ok(package_version)
+}
+
+/// Config revision a package version is served with: the one of its newest public release
+/// that reached devices, so configs stored later only go out with a new release
+fn released_config_revision(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    package_version: i32,
+) -> Result<Option<i32>> {
+    use crate::utils::db::{models::ReleaseStatus, schema::hyperotaserver::releases::dsl as releases};
+
+    let revision = releases::releases
+        .filter(releases::org_id.eq(organisation))
+        .filter(releases::app_id.eq(application))
+        .filter(releases::package_version.eq(package_version))
+        .filter(releases::tester_group_id.is_null())
+        .filter(releases::status.eq_any([
+            ReleaseStatus::Ramping.as_str(),
+            ReleaseStatus::Paused.as_str(),
+            ReleaseStatus::Live.as_str(),
+            ReleaseStatus::Concluded.as_str(),
+        ]))
+        .order_by(releases::created_at.desc())
+        .select(releases::config_revision)
+        .first::<Option<i32>>(conn)
+        .optional()
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(revision.flatten())
+}
+
+/// Build the release config of a package version from the package/config tables, with the
+/// given config revision or, for packages never released, the newest one
+fn load_release_config(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    package_version: i32,
+    revision: Option<i32>,
+) -> Result<ReleaseConfig> {
     // Get both package and config data
     let package_data = packages
         .filter(
             org_id
-                .eq(&organisation)
-                .and(app_id.eq(&application))
+                .eq(organisation)
+                .and(app_id.eq(application))
                 .and(version.eq(package_version)),
         )
-        .first::<PackageEntryRead>(&mut conn)
+        .first::<PackageEntryRead>(conn)
         .map_err(|_| error::ErrorNotFound("Package not found"))?;
 
-    let config_data = configs_table
+    let mut config_query = configs_table
         .filter(
             config_org_id
-                .eq(&organisation)
-                .and(config_app_id.eq(&application))
+                .eq(organisation)
+                .and(config_app_id.eq(application))
                 .and(config_version.eq(package_version)),
         )
+        .into_boxed();
+    if let Some(revision) = revision {
+        config_query = config_query.filter(config_revision.eq(revision));
+    }
+    let config_data = config_query
+        .order_by(config_revision.desc())
         .select(ConfigEntry::as_select())
-        .first::<ConfigEntry>(&mut conn)
+        .first::<ConfigEntry>(conn)
         .map_err(|_| error::ErrorNotFound("Config not found"))?;
 
     // Convert important and lazy files from JSON back to Vec<File>
@@ -471,7 +1081,7 @@ async fn serve_release_v2(
     let lazy_files: Vec<File> = 
         serde_json::from_value(package_data.lazy.clone()).unwrap_or_default();
 
-    Ok(Json(ReleaseConfig {
+    Ok(ReleaseConfig {
         config: Config {
             version: config_data.config_version,
             release_config_timeout: config_data.release_config_timeout as u32,
@@ -485,9 +1095,101 @@ async fn serve_release_v2(
             version: config_data.version.to_string(),
             properties: config_data.properties.clone(),
             index: package_data.index,
+            index_sha256: package_data.index_sha256,
+            index_size: package_data.index_size,
+            index_patch: None,
             important: important_files,
             lazy: lazy_files,
         },
         resources: package_data.resources,
-    }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test::TestRequest};
+
+    use super::*;
+
+    fn release_config() -> ReleaseConfig {
+        serde_json::from_value(json!({
+            "config": {
+                "version": "1",
+                "release_config_timeout": 1000,
+                "package_timeout": 1000,
+                "properties": { "tenant_info": {} }
+            },
+            "package": {
+                "name": "app",
+                "version": "3",
+                "index": "https://cdn.example.com/index.js",
+                "important": [],
+                "lazy": []
+            },
+            "resources": []
+        }))
+        .unwrap()
+    }
+
+    fn signing_key() -> AppSigningKey {
+        AppSigningKey::from_secret("key-1".to_string(), &[7; 32])
+    }
+
+    fn header_value<'a>(response: &'a HttpResponse, name: &str) -> Option<&'a str> {
+        response.headers().get(name).and_then(|value| value.to_str().ok())
+    }
+
+    #[test]
+    fn etags_match_strong_weak_listed_and_wildcard_tags() {
+        let etag = compute_etag(b"body");
+        let matches = |if_none_match: &str| {
+            let req = TestRequest::default()
+                .insert_header((header::IF_NONE_MATCH, if_none_match))
+                .to_http_request();
+            etag_matches(&req, &etag)
+        };
+
+        assert!(matches(&etag));
+        assert!(matches(&format!("W/{}", etag)));
+        assert!(matches(&format!("\"other\", {}", etag)));
+        assert!(matches("*"));
+        assert!(!matches(&compute_etag(b"other body")));
+        assert!(!etag_matches(&TestRequest::default().to_http_request(), &etag));
+    }
+
+    #[test]
+    fn fresh_responses_are_signed_and_tagged() {
+        let config = release_config();
+        let body = serde_json::to_vec(&config).unwrap();
+        let req = TestRequest::default().to_http_request();
+
+        let response = release_response(&req, &config, "max-age=60", &signing_key()).unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header_value(&response, "etag"), Some(compute_etag(&body).as_str()));
+        assert_eq!(header_value(&response, "cache-control"), Some("max-age=60"));
+        let signature = signing_key().sign(&body);
+        assert_eq!(header_value(&response, SIGNATURE_HEADER), Some(signature.as_str()));
+        assert_eq!(header_value(&response, SIGNATURE_KEY_ID_HEADER), Some("key-1"));
+        let vary = header_value(&response, "vary").unwrap();
+        for varied in ["x-device-id", TESTER_TOKEN_HEADER, PACKAGE_VERSION_HEADER] {
+            assert!(vary.contains(varied), "{} is not in Vary: {}", varied, vary);
+        }
+    }
+
+    #[test]
+    fn unchanged_configs_are_not_modified() {
+        let config = release_config();
+        let etag = compute_etag(&serde_json::to_vec(&config).unwrap());
+        let req = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, etag.as_str()))
+            .to_http_request();
+
+        let response = release_response(&req, &config, "max-age=60", &signing_key()).unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(header_value(&response, "etag"), Some(etag.as_str()));
+        assert!(header_value(&response, "vary").is_some());
+        assert_eq!(header_value(&response, SIGNATURE_HEADER), None);
+    }
 }
@@ -0,0 +1,378 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Dry-run of release resolution. Resolves a context through the same pipeline as
+// `serve_release_v2` and explains where the result came from, without touching the
+// release cache or the last-known-good release.
+
+use actix_web::{error, Result};
+use diesel::prelude::*;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use super::{
+    context::{validate_context, workspace_dimensions, RawContext},
+    decode_to_config_v2, fetch_applicable_variants, get_workspace_name_for_application,
+    load_release_config, released_config_revision, resolve_config, resolve_release_v2,
+    targeting::{compile_targeting, context_matches},
+    ReleaseConfig,
+};
+use crate::{
+    types::AppState,
+    utils::{
+        db::{
+            models::ReleaseEntry,
+            schema::hyperotaserver::{
+                configs::dsl as configs, packages::dsl as packages, releases::dsl as releases,
+            },
+        },
+        tester_groups::{find_member_release, MemberIds},
+    },
+};
+
+/// What decided one field of a previewed release config
+#[derive(Debug, Serialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum Decision {
+    /// A running experiment's variant; releases are served through experiments
+    Experiment {
+        experiment_id: String,
+        variant_id: String,
+        release_id: Option<String>,
+    },
+    /// A Superposition override matching the context
+    Override,
+    /// The workspace's default config
+    Default,
+    /// Version 0 was resolved, which stands for the latest package
+    LatestPackage,
+    /// The hypothetical release given with the preview. It is served when its targeting
+    /// matches the context and its rollout is above 0; which devices a partial rollout
+    /// reaches depends on the release id assigned when it is created.
+    HypotheticalRelease { matched: bool, percentage: u8 },
+    /// A partial rollout of the resolved release; devices outside it get the previous package
+    Rollout {
+        release_id: String,
+        percentage: u8,
+        paused: bool,
+        included: bool,
+    },
+    /// A live release pinned to a tester group the device belongs to
+    TesterGroup {
+        tester_group_id: String,
+        release_id: String,
+    },
+    /// The newest config bound to the package version
+    ConfigRevision { revision: i32 },
+}
+
+#[derive(Debug, Serialize)]
+pub struct FieldDecision {
+    pub field: &'static str,
+    #[serde(flatten)]
+    pub decision: Decision,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReleasePreview {
+    /// Context after validation against the workspace's dimensions
+    pub context: Value,
+    pub release_config: ReleaseConfig,
+    pub decisions: Vec<FieldDecision>,
+}
+
+/// Device the release is previewed for
+pub struct PreviewDevice {
+    /// Dimension values, as the device would send them
+    pub context: Map<String, Value>,
+    /// Device id used for rollout bucketing, unless `context` has one
+    pub device_id: Option<String>,
+    /// User signed in on the device, matched against tester groups, unless `context` has one
+    pub user_id: Option<String>,
+    /// Package the device runs, as sent in `x-package-version`
+    pub package_version: Option<i32>,
+}
+
+/// Release that does not exist yet, previewed as if it were live
+pub struct HypotheticalRelease {
+    pub package_version: i32,
+    /// Targeting rule, as given when creating a release
+    pub targeting: Option<Value>,
+    pub rollout_percentage: u8,
+}
+
+fn resolved_package_version(config: Value) -> Result<i32> {
+    Ok(decode_to_config_v2(config)?.package.version)
+}
+
+/// Experiment whose variant resolved `package_version`, preferring the one of a release
+/// of that version
+fn experiment_decision(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    variants: &[String],
+    package_version: i32,
+) -> Result<Decision> {
+    // Variant ids are the experiment id followed by the variant name
+    let experiments: Vec<(String, String)> = variants
+        .iter()
+        .filter_map(|variant| {
+            variant
+                .rsplit_once('-')
+                .map(|(experiment, _)| (experiment.to_string(), variant.clone()))
+        })
+        .collect();
+    let release = releases::releases
+        .filter(releases::org_id.eq(organisation))
+        .filter(releases::app_id.eq(application))
+        .filter(releases::package_version.eq(package_version))
+        .filter(
+            releases::experiment_id
+                .eq_any(experiments.iter().map(|(experiment, _)| experiment.clone())),
+        )
+        .order_by(releases::created_at.desc())
+        .select(ReleaseEntry::as_select())
+        .first(conn)
+        .optional()
+        .map_err(error::ErrorInternalServerError)?;
+
+    let experiment = release
+        .as_ref()
+        .and_then(|release| {
+            experiments
+                .iter()
+                .find(|(experiment, _)| release.experiment_id.as_ref() == Some(experiment))
+        })
+        .or_else(|| experiments.first());
+    Ok(match experiment {
+        Some((experiment_id, variant_id)) => Decision::Experiment {
+            experiment_id: experiment_id.clone(),
+            variant_id: variant_id.clone(),
+            release_id: release.map(|release| release.id.to_string()),
+        },
+        None => Decision::Override,
+    })
+}
+
+/// Explain the package version Superposition resolved with `variants` applied, by resolving
+/// again without them and then without the context
+async fn package_version_decision(
+    state: &AppState,
+    conn: &mut PgConnection,
+    (organisation, application, workspace_name): (&str, &str, &str),
+    context: &Value,
+    variants: &[String],
+    resolved_version: i32,
+) -> Result<Decision> {
+    if resolved_version == 0 {
+        return Ok(Decision::LatestPackage);
+    }
+
+    let without_variants =
+        resolved_package_version(resolve_config(state, workspace_name, context, &[]).await?)?;
+    if without_variants != resolved_version {
+        return experiment_decision(conn, organisation, application, variants, resolved_version);
+    }
+
+    let without_context =
+        resolved_package_version(resolve_config(state, workspace_name, &json!({}), &[]).await?)?;
+    Ok(if without_context != resolved_version {
+        Decision::Override
+    } else {
+        Decision::Default
+    })
+}
+
+/// Check that the package of a hypothetical release could be released and whether the
+/// release would be served to the context
+async fn hypothetical_decision(
+    state: &AppState,
+    conn: &mut PgConnection,
+    (organisation, application, workspace_name): (&str, &str, &str),
+    context: &Value,
+    release: &HypotheticalRelease,
+) -> Result<Decision> {
+    let package_version = release.package_version;
+    let archived = packages::packages
+        .filter(packages::org_id.eq(organisation))
+        .filter(packages::app_id.eq(application))
+        .filter(packages::version.eq(package_version))
+        .select(packages::archived_at.is_not_null())
+        .first::<bool>(conn)
+        .optional()
+        .map_err(error::ErrorInternalServerError)?
+        .ok_or_else(|| {
+            error::ErrorNotFound(format!("Package version {} not found", package_version))
+        })?;
+    if archived {
+        return Err(error::ErrorConflict(format!(
+            "Package version {} is archived",
+            package_version
+        )));
+    }
+
+    let matched = match &release.targeting {
+        Some(rule) => context_matches(&compile_targeting(state, workspace_name, rule).await?, context),
+        None => true,
+    };
+    Ok(Decision::HypotheticalRelease {
+        matched,
+        percentage: release.rollout_percentage,
+    })
+}
+
+/// Resolve the release config a device with `context` would be served. With `hypothetical`,
+/// that release is previewed as if it were live, taking precedence over the resolved
+/// release wherever it is served.
+pub async fn preview_release(
+    state: &AppState,
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    device: PreviewDevice,
+    hypothetical: Option<HypotheticalRelease>,
+) -> Result<ReleasePreview> {
+    let workspace_name = get_workspace_name_for_application(application, organisation, conn)
+        .await
+        .map_err(|e| error::ErrorInternalServerError(format!("Failed to get workspace name: {}", e)))?;
+
+    let current_version = device.package_version;
+    let raw_context = RawContext::from_values(device.context, device.device_id, device.user_id);
+    let device_id = raw_context.device_id().map(str::to_string);
+    let user_id = raw_context.user_id().map(str::to_string);
+    let dimensions = workspace_dimensions(state, &workspace_name).await?;
+    let context = validate_context(raw_context, &dimensions)?;
+
+    let member = MemberIds {
+        device_id: device_id.as_deref(),
+        user_id: user_id.as_deref(),
+    };
+    let pinned = find_member_release(conn, organisation, application, member)
+        .map_err(error::ErrorInternalServerError)?;
+
+    let mut decisions = Vec::new();
+    let mut hypothetical_version = None;
+    if let Some(release) = hypothetical {
+        let decision = hypothetical_decision(
+            state,
+            conn,
+            (organisation, application, &workspace_name),
+            &context,
+            &release,
+        )
+        .await?;
+        let served = matches!(
+            decision,
+            Decision::HypotheticalRelease { matched: true, percentage } if percentage > 0
+        );
+        if served {
+            hypothetical_version = Some(release.package_version);
+        }
+        decisions.push(FieldDecision {
+            field: "package.version",
+            decision,
+        });
+    }
+
+    // Tester group members keep their pinned package even where the release would be served
+    let pinned_revision = pinned.as_ref().map(|pinned| pinned.config_revision);
+    let release_config = match (hypothetical_version, pinned) {
+        (_, Some(pinned)) => {
+            decisions.push(FieldDecision {
+                field: "package.version",
+                decision: Decision::TesterGroup {
+                    tester_group_id: pinned.tester_group_id.to_string(),
+                    release_id: pinned.release_id.to_string(),
+                },
+            });
+            load_release_config(
+                conn,
+                organisation,
+                application,
+                pinned.package_version,
+                pinned.config_revision,
+            )?
+        }
+        (Some(package_version), None) => {
+            // A new release would be created with the newest config
+            load_release_config(conn, organisation, application, package_version, None)?
+        }
+        (None, None) => {
+            let variants = fetch_applicable_variants(state, &workspace_name).await?;
+            let config = resolve_config(state, &workspace_name, &context, &variants).await?;
+            let resolved_version = resolved_package_version(config.clone())?;
+            let decision = package_version_decision(
+                state,
+                conn,
+                (organisation, application, &workspace_name),
+                &context,
+                &variants,
+                resolved_version,
+            )
+            .await?;
+            decisions.push(FieldDecision {
+                field: "package.version",
+                decision,
+            });
+
+            let resolved = resolve_release_v2(conn, organisation, application, config)?;
+            if let Some((rollout, _)) = &resolved.rollout {
+                decisions.push(FieldDecision {
+                    field: "package.version",
+                    decision: Decision::Rollout {
+                        release_id: rollout.release_id.to_string(),
+                        percentage: rollout.percentage,
+                        paused: rollout.paused,
+                        included: rollout.includes(device_id.as_deref(), current_version),
+                    },
+                });
+            }
+            resolved.for_device(device_id.as_deref(), current_version).clone()
+        }
+    };
+
+    let package_version: i32 = release_config
+        .package
+        .version
+        .parse()
+        .map_err(error::ErrorInternalServerError)?;
+    let released_revision = match (pinned_revision, hypothetical_version) {
+        (Some(revision), _) => revision,
+        (None, Some(_)) => None,
+        (None, None) => released_config_revision(conn, organisation, application, package_version)?,
+    };
+    let revision = match released_revision {
+        Some(revision) => Some(revision),
+        None => configs::configs
+            .filter(configs::org_id.eq(organisation))
+            .filter(configs::app_id.eq(application))
+            .filter(configs::version.eq(package_version))
+            .select(diesel::dsl::max(configs::revision))
+            .first::<Option<i32>>(conn)
+            .map_err(error::ErrorInternalServerError)?,
+    };
+    if let Some(revision) = revision {
+        decisions.push(FieldDecision {
+            field: "config",
+            decision: Decision::ConfigRevision { revision },
+        });
+    }
+
+    Ok(ReleasePreview {
+        context,
+        release_config,
+        decisions,
+    })
+}
@@ -0,0 +1,172 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use diesel::prelude::*;
+use sha2::{Digest, Sha256};
+
+use crate::utils::db::{
+    models::{ReleaseEntry, ReleaseStatus},
+    schema::hyperotaserver::releases::dsl::*,
+};
+
+/// A release that only part of the devices should receive yet
+#[derive(Debug, Clone)]
+pub struct Rollout {
+    pub release_id: uuid::Uuid,
+    pub percentage: u8,
+    pub package_version: i32,
+    pub previous_package_version: i32,
+    /// Paused rollouts take no new devices; only devices already running the package keep it
+    pub paused: bool,
+}
+
+/// Latest release of a package version, if it is still ramping or paused mid-rollout
+pub fn find_rollout(
+    organisation: &str,
+    application: &str,
+    package_version_value: i32,
+    conn: &mut PgConnection,
+) -> Result<Option<Rollout>, diesel::result::Error> {
+    let release = releases
+        .filter(
+            org_id
+                .eq(organisation)
+                .and(app_id.eq(application))
+                .and(package_version.eq(package_version_value))
+                .and(status.eq_any([
+                    ReleaseStatus::Ramping.as_str(),
+                    ReleaseStatus::Paused.as_str(),
+                ])),
+        )
+        .order_by(created_at.desc())
+        .select(ReleaseEntry::as_select())
+        .first(conn)
+        .optional()?;
+
+    Ok(release.and_then(|release| match release.previous_package_version {
+        Some(previous) if release.rollout_percentage < 100 => Some(Rollout {
+            release_id: release.id,
+            percentage: release.rollout_percentage.clamp(0, 100) as u8,
+            package_version: release.package_version,
+            previous_package_version: previous,
+            paused: release.status == ReleaseStatus::Paused.as_str(),
+        }),
+        _ => None,
+    }))
+}
+
+/// Stable bucket in 0..100 for a device within a release.
+/// Hashing the release id in gives every release an independent split of the fleet.
+pub fn bucket(device_id: &str, release_id: &uuid::Uuid) -> u8 {
+    let digest = Sha256::new()
+        .chain_update(device_id.as_bytes())
+        .chain_update(b":")
+        .chain_update(release_id.as_bytes())
+        .finalize();
+    let mut prefix = [0u8; 8];
+    prefix.copy_from_slice(&digest[..8]);
+    (u64::from_be_bytes(prefix) % 100) as u8
+}
+
+impl Rollout {
+    /// Whether the device receives the new release. Raising the percentage only ever
+    /// moves devices onto the new release, so no device flips back while ramping up.
+    /// Devices without an id cannot be bucketed and stay on the previous package.
+    /// While paused, only devices reporting the release's package as their current one
+    /// (`current_version`) keep it, so the release reaches no new devices.
+    pub fn includes(&self, device_id: Option<&str>, current_version: Option<i32>) -> bool {
+        let in_bucket = device_id
+            .is_some_and(|device_id| bucket(device_id, &self.release_id) < self.percentage);
+        in_bucket && (!self.paused || current_version == Some(self.package_version))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rollout(percentage: u8, paused: bool) -> Rollout {
+        Rollout {
+            release_id: uuid::Uuid::from_u128(0x2f1c_9a4e_5b7d_4c3a_8e6f_1d2b_3c4d_5e6f),
+            percentage,
+            package_version: 3,
+            previous_package_version: 2,
+            paused,
+        }
+    }
+
+    fn device_ids() -> impl Iterator<Item = String> {
+        (0..1000).map(|index| format!("device-{}", index))
+    }
+
+    #[test]
+    fn bucket_is_stable_and_below_100() {
+        let release_id = rollout(0, false).release_id;
+        for device_id in device_ids() {
+            let bucket_value = bucket(&device_id, &release_id);
+            assert!(bucket_value < 100);
+            assert_eq!(bucket_value, bucket(&device_id, &release_id));
+        }
+    }
+
+    #[test]
+    fn releases_split_the_fleet_independently() {
+        let first = uuid::Uuid::from_u128(1);
+        let second = uuid::Uuid::from_u128(2);
+        assert!(device_ids()
+            .any(|device_id| bucket(&device_id, &first) != bucket(&device_id, &second)));
+    }
+
+    #[test]
+    fn percentage_bounds() {
+        assert!(device_ids().all(|device_id| !rollout(0, false).includes(Some(&device_id), None)));
+        assert!(device_ids().all(|device_id| rollout(100, false).includes(Some(&device_id), None)));
+    }
+
+    #[test]
+    fn ramping_up_never_drops_devices() {
+        let included = |percentage, device_id: &str| {
+            rollout(percentage, false).includes(Some(device_id), None)
+        };
+        for device_id in device_ids() {
+            for percentage in 1..100 {
+                if included(percentage, &device_id) {
+                    assert!(included(percentage + 1, &device_id));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn percentage_is_roughly_honoured() {
+        let included = device_ids()
+            .filter(|device_id| rollout(30, false).includes(Some(device_id), None))
+            .count();
+        assert!((250..350).contains(&included), "{} of 1000 included at 30%", included);
+    }
+
+    #[test]
+    fn devices_without_id_stay_on_previous_package() {
+        assert!(!rollout(100, false).includes(None, None));
+    }
+
+    #[test]
+    fn paused_rollout_only_keeps_devices_on_the_package() {
+        let paused = rollout(100, true);
+        assert!(paused.includes(Some("device-1"), Some(3)));
+        assert!(!paused.includes(Some("device-1"), Some(2)));
+        assert!(!paused.includes(Some("device-1"), None));
+        assert!(!rollout(0, true).includes(Some("device-1"), Some(3)));
+    }
+}
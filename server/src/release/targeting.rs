@@ -0,0 +1,601 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Targeting rules of releases and packages. Rules are written in a subset of JsonLogic:
+// `==` and `in` on a `{"var": dimension}`, and `>=` and `<=` on versions, combined with
+// `and` and `or`. A plain `{dimension: value}` object is read as equalities on every key
+// and `true` matches every device. Superposition would compare versions as strings
+// ("1.10.0" < "1.9.0"), so version comparisons are compiled to comparisons of the integer
+// version code dimension that `validate_context` derives from the version.
+//
+// Rules are checked against the workspace's dimensions, brought to disjunctive normal
+// form and compiled to a single Superposition context. Superposition contexts cannot
+// express `or`, so disjuncts must collapse into one by merging values of one dimension
+// into an `in`.
+
+use std::collections::HashMap;
+
+use actix_web::{error, Result};
+use serde_json::{json, Value};
+
+use super::context::{
+    coerce_value, version_code, version_code_dimension, workspace_dimensions, Dimensions,
+};
+use crate::types::AppState;
+
+/// Disjuncts a rule may expand to before they are merged
+const MAX_DISJUNCTS: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    Equals(String, Value),
+    In(String, Vec<Value>),
+    /// A version at least the value. Once validated, the dimension is the version code one
+    /// and the value a version code.
+    AtLeast(String, Value),
+    /// A version at most the value, like `AtLeast`
+    AtMost(String, Value),
+}
+
+impl Condition {
+    fn dimension(&self) -> &str {
+        match self {
+            Condition::Equals(dimension, _)
+            | Condition::In(dimension, _)
+            | Condition::AtLeast(dimension, _)
+            | Condition::AtMost(dimension, _) => dimension,
+        }
+    }
+
+    fn to_json_logic(&self) -> Value {
+        match self {
+            Condition::Equals(dimension, value) => json!({ "==": [{ "var": dimension }, value] }),
+            Condition::In(dimension, values) => json!({ "in": [{ "var": dimension }, values] }),
+            Condition::AtLeast(dimension, value) => json!({ ">=": [{ "var": dimension }, value] }),
+            Condition::AtMost(dimension, value) => json!({ "<=": [{ "var": dimension }, value] }),
+        }
+    }
+
+    /// Values the dimension may take, when they can be listed
+    fn values(&self) -> Option<&[Value]> {
+        match self {
+            Condition::Equals(_, value) => Some(std::slice::from_ref(value)),
+            Condition::In(_, values) => Some(values),
+            Condition::AtLeast(..) | Condition::AtMost(..) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TargetingRule {
+    Condition(Condition),
+    And(Vec<TargetingRule>),
+    Or(Vec<TargetingRule>),
+}
+
+fn invalid(message: impl Into<String>) -> actix_web::Error {
+    error::ErrorBadRequest(format!("Invalid targeting rule: {}", message.into()))
+}
+
+fn parse_operands(operator: &str, operands: &Value) -> Result<(String, Value)> {
+    match operands.as_array().map(Vec::as_slice) {
+        Some([variable, value]) => {
+            let dimension = variable
+                .get("var")
+                .and_then(Value::as_str)
+                .ok_or_else(|| {
+                    invalid(format!("{} must compare a {{\"var\": dimension}} to a value", operator))
+                })?;
+            Ok((dimension.to_string(), value.clone()))
+        }
+        _ => Err(invalid(format!("{} takes exactly two operands", operator))),
+    }
+}
+
+impl TargetingRule {
+    fn parse(rule: &Value) -> Result<Self> {
+        // `true` matches every device
+        if rule == &Value::Bool(true) {
+            return Ok(TargetingRule::And(Vec::new()));
+        }
+        let object = rule
+            .as_object()
+            .ok_or_else(|| invalid("expected a JSON object"))?;
+
+        let operator = match object.iter().next() {
+            Some((key, operands)) if object.len() == 1 => {
+                matches!(key.as_str(), "and" | "or" | "==" | "in" | ">=" | "<=")
+                    .then_some((key.as_str(), operands))
+            }
+            _ => None,
+        };
+        let Some((operator, operands)) = operator else {
+            // Plain dimension map, as accepted before targeting rules
+            return Ok(TargetingRule::And(
+                object
+                    .iter()
+                    .map(|(dimension, value)| {
+                        TargetingRule::Condition(Condition::Equals(dimension.clone(), value.clone()))
+                    })
+                    .collect(),
+            ));
+        };
+
+        match operator {
+            "and" | "or" => {
+                let rules = operands
+                    .as_array()
+                    .filter(|rules| !rules.is_empty())
+                    .ok_or_else(|| invalid(format!("{} takes a non-empty array of rules", operator)))?
+                    .iter()
+                    .map(TargetingRule::parse)
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(if operator == "and" {
+                    TargetingRule::And(rules)
+                } else {
+                    TargetingRule::Or(rules)
+                })
+            }
+            "==" => {
+                let (dimension, value) = parse_operands(operator, operands)?;
+                Ok(TargetingRule::Condition(Condition::Equals(dimension, value)))
+            }
+            "in" => {
+                let (dimension, values) = parse_operands(operator, operands)?;
+                let values = values
+                    .as_array()
+                    .filter(|values| !values.is_empty())
+                    .ok_or_else(|| invalid("in takes a non-empty array of values"))?
+                    .clone();
+                Ok(TargetingRule::Condition(Condition::In(dimension, values)))
+            }
+            _ => {
+                let (dimension, value) = parse_operands(operator, operands)?;
+                Ok(TargetingRule::Condition(if operator == ">=" {
+                    Condition::AtLeast(dimension, value)
+                } else {
+                    Condition::AtMost(dimension, value)
+                }))
+            }
+        }
+    }
+
+    /// Check every condition against the workspace's dimensions, coercing values to the
+    /// dimension's type
+    fn validate(self, dimensions: &Dimensions) -> Result<Self> {
+        let condition = match self {
+            TargetingRule::And(rules) => {
+                return Ok(TargetingRule::And(
+                    rules.into_iter().map(|rule| rule.validate(dimensions)).collect::<Result<_>>()?,
+                ))
+            }
+            TargetingRule::Or(rules) => {
+                return Ok(TargetingRule::Or(
+                    rules.into_iter().map(|rule| rule.validate(dimensions)).collect::<Result<_>>()?,
+                ))
+            }
+            TargetingRule::Condition(condition) => condition,
+        };
+
+        let dimension = condition.dimension().to_string();
+        let schema = dimensions
+            .get(&dimension)
+            .ok_or_else(|| invalid(format!("unknown dimension {}", dimension)))?;
+        let condition = match condition {
+            Condition::Equals(_, value) => {
+                Condition::Equals(dimension.clone(), coerce_value(&dimension, value, schema)?)
+            }
+            Condition::In(_, values) => Condition::In(
+                dimension.clone(),
+                values
+                    .into_iter()
+                    .map(|value| coerce_value(&dimension, value, schema))
+                    .collect::<Result<_>>()?,
+            ),
+            Condition::AtLeast(_, version) => {
+                let (code_dimension, code) = version_bound(&dimension, &version, dimensions)?;
+                Condition::AtLeast(code_dimension, code)
+            }
+            Condition::AtMost(_, version) => {
+                let (code_dimension, code) = version_bound(&dimension, &version, dimensions)?;
+                Condition::AtMost(code_dimension, code)
+            }
+        };
+        Ok(TargetingRule::Condition(condition))
+    }
+
+    /// Disjunction of conjunctions equivalent to the rule
+    fn disjuncts(&self) -> Result<Vec<Vec<Condition>>> {
+        let disjuncts = match self {
+            TargetingRule::Condition(condition) => vec![vec![condition.clone()]],
+            TargetingRule::Or(rules) => {
+                let mut disjuncts = Vec::new();
+                for rule in rules {
+                    disjuncts.extend(rule.disjuncts()?);
+                }
+                disjuncts
+            }
+            TargetingRule::And(rules) => {
+                let mut disjuncts = vec![Vec::new()];
+                for rule in rules {
+                    let rule_disjuncts = rule.disjuncts()?;
+                    disjuncts = disjuncts
+                        .iter()
+                        .flat_map(|conjunction| {
+                            rule_disjuncts.iter().map(move |rule_conjunction| {
+                                let mut combined = conjunction.clone();
+                                combined.extend(rule_conjunction.iter().cloned());
+                                combined
+                            })
+                        })
+                        .collect();
+                    if disjuncts.len() > MAX_DISJUNCTS {
+                        break;
+                    }
+                }
+                disjuncts
+            }
+        };
+        if disjuncts.len() > MAX_DISJUNCTS {
+            return Err(invalid(format!(
+                "expands to more than {} alternatives",
+                MAX_DISJUNCTS
+            )));
+        }
+        Ok(disjuncts)
+    }
+}
+
+/// Version code dimension and version code a version comparison is compiled to. Superposition
+/// compares strings character by character, so versions are compared through the integer
+/// dimension holding their code, which `validate_context` derives.
+fn version_bound(dimension: &str, version: &Value, dimensions: &Dimensions) -> Result<(String, Value)> {
+    let code_dimension = version_code_dimension(dimension);
+    let is_type = |dimension: &str, expected: &str| {
+        dimensions
+            .get(dimension)
+            .and_then(|schema| schema.get("type"))
+            .and_then(Value::as_str)
+            == Some(expected)
+    };
+    if !is_type(dimension, "string") || !is_type(&code_dimension, "integer") {
+        return Err(invalid(format!(
+            "{} can only be compared with >= and <= when it is a string dimension and the workspace has an integer dimension {}",
+            dimension, code_dimension
+        )));
+    }
+    let code = version
+        .as_str()
+        .and_then(version_code)
+        .ok_or_else(|| invalid(format!("{} must be compared to a version such as \"2.1.0\"", dimension)))?;
+    Ok((code_dimension, Value::from(code)))
+}
+
+/// Sort and deduplicate the conditions of a conjunction, so equal conjunctions compare equal
+fn normalize(mut conjunction: Vec<Condition>) -> Vec<Condition> {
+    conjunction.sort_by_cached_key(|condition| {
+        (condition.dimension().to_string(), condition.to_json_logic().to_string())
+    });
+    conjunction.dedup();
+    conjunction
+}
+
+/// Conjunctions that differ only in the values allowed for one dimension, merged into one
+fn merge(a: &[Condition], b: &[Condition]) -> Option<Vec<Condition>> {
+    if a == b {
+        return Some(a.to_vec());
+    }
+    if a.len() != b.len() {
+        return None;
+    }
+    let differing: Vec<usize> = (0..a.len()).filter(|&index| a[index] != b[index]).collect();
+    let [index] = differing.as_slice() else {
+        return None;
+    };
+    let (left, right) = (&a[*index], &b[*index]);
+    if left.dimension() != right.dimension() {
+        return None;
+    }
+    let mut values = left.values()?.to_vec();
+    for value in right.values()? {
+        if !values.contains(value) {
+            values.push(value.clone());
+        }
+    }
+
+    let mut merged = a.to_vec();
+    merged[*index] = Condition::In(left.dimension().to_string(), values);
+    Some(normalize(merged))
+}
+
+/// Compile a rule to the single Superposition context it is equivalent to
+fn compile(rule: &TargetingRule) -> Result<HashMap<String, Value>> {
+    let mut disjuncts: Vec<Vec<Condition>> =
+        rule.disjuncts()?.into_iter().map(normalize).collect();
+
+    'merging: loop {
+        for i in 0..disjuncts.len() {
+            for j in i + 1..disjuncts.len() {
+                if let Some(merged) = merge(&disjuncts[i], &disjuncts[j]) {
+                    disjuncts[i] = merged;
+                    disjuncts.remove(j);
+                    continue 'merging;
+                }
+            }
+        }
+        break;
+    }
+
+    let conjunction = match disjuncts.as_slice() {
+        [conjunction] => conjunction,
+        _ => {
+            return Err(invalid(
+                "`or` can only choose between values of one dimension, as a release targets a single context",
+            ))
+        }
+    };
+    let logic = match conjunction.as_slice() {
+        [] => return Ok(HashMap::new()),
+        [condition] => condition.to_json_logic(),
+        conditions => json!({ "and": conditions.iter().map(Condition::to_json_logic).collect::<Vec<_>>() }),
+    };
+    Ok(serde_json::from_value(logic).unwrap_or_default())
+}
+
+/// Parse a targeting rule, validate it against the dimensions of a workspace and compile it
+/// to a Superposition context
+pub async fn compile_targeting(
+    state: &AppState,
+    workspace_name: &str,
+    rule: &Value,
+) -> Result<HashMap<String, Value>> {
+    let rule = TargetingRule::parse(rule)?;
+    let dimensions = workspace_dimensions(state, workspace_name).await?;
+    compile(&rule.validate(&dimensions)?)
+}
+
+/// Whether a context satisfies a compiled targeting rule, as Superposition would decide
+/// when serving. Compiled rules only use `and`, `==`, `in`, and `>=` and `<=` on numbers;
+/// an empty rule matches all.
+pub fn context_matches(compiled: &HashMap<String, Value>, context: &Value) -> bool {
+    compiled.is_empty()
+        || logic_matches(&Value::Object(compiled.clone().into_iter().collect()), context)
+}
+
+fn logic_matches(logic: &Value, context: &Value) -> bool {
+    let Some((operator, operands)) = logic.as_object().and_then(|logic| logic.iter().next())
+    else {
+        return false;
+    };
+    let dimension_value = |variable: &Value| {
+        variable
+            .get("var")
+            .and_then(Value::as_str)
+            .and_then(|dimension| context.get(dimension))
+            .cloned()
+    };
+    match (operator.as_str(), operands.as_array().map(Vec::as_slice)) {
+        ("and", Some(rules)) => rules.iter().all(|rule| logic_matches(rule, context)),
+        ("==", Some([variable, value])) => dimension_value(variable).as_ref() == Some(value),
+        ("in", Some([variable, Value::Array(values)])) => {
+            dimension_value(variable).is_some_and(|value| values.contains(&value))
+        }
+        (">=", Some([variable, bound])) => dimension_value(variable)
+            .and_then(|value| value.as_f64())
+            .zip(bound.as_f64())
+            .is_some_and(|(value, bound)| value >= bound),
+        ("<=", Some([variable, bound])) => dimension_value(variable)
+            .and_then(|value| value.as_f64())
+            .zip(bound.as_f64())
+            .is_some_and(|(value, bound)| value <= bound),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::release::context::{validate_context, RawContext};
+
+    fn dimensions() -> Dimensions {
+        Arc::new(HashMap::from([
+            ("os".to_string(), json!({ "type": "string" })),
+            ("build".to_string(), json!({ "type": "integer" })),
+            ("channel".to_string(), json!({ "type": "string", "enum": ["beta", "stable"] })),
+            ("app_version".to_string(), json!({ "type": "string" })),
+            ("app_version_semver".to_string(), json!({ "type": "integer" })),
+        ]))
+    }
+
+    fn compile_rule(rule: Value) -> Result<Value> {
+        let rule = TargetingRule::parse(&rule)?.validate(&dimensions())?;
+        Ok(Value::Object(compile(&rule)?.into_iter().collect()))
+    }
+
+    #[test]
+    fn true_matches_every_device() {
+        assert_eq!(compile_rule(json!(true)).unwrap(), json!({}));
+    }
+
+    #[test]
+    fn plain_map_compiles_to_and_of_equals() {
+        assert_eq!(
+            compile_rule(json!({ "os": "android", "build": "42" })).unwrap(),
+            json!({ "and": [
+                { "==": [{ "var": "build" }, 42] },
+                { "==": [{ "var": "os" }, "android"] },
+            ] })
+        );
+    }
+
+    #[test]
+    fn single_condition_is_not_wrapped() {
+        assert_eq!(
+            compile_rule(json!({ "in": [{ "var": "os" }, ["android", "ios"]] })).unwrap(),
+            json!({ "in": [{ "var": "os" }, ["android", "ios"]] })
+        );
+    }
+
+    #[test]
+    fn or_of_one_dimension_merges_into_in() {
+        assert_eq!(
+            compile_rule(json!({ "or": [
+                { "==": [{ "var": "os" }, "android"] },
+                { "in": [{ "var": "os" }, ["ios", "android"]] },
+            ] }))
+            .unwrap(),
+            json!({ "in": [{ "var": "os" }, ["android", "ios"]] })
+        );
+    }
+
+    #[test]
+    fn and_distributes_over_or_before_merging() {
+        assert_eq!(
+            compile_rule(json!({ "and": [
+                { "or": [
+                    { "==": [{ "var": "os" }, "android"] },
+                    { "==": [{ "var": "os" }, "ios"] },
+                ] },
+                { "==": [{ "var": "build" }, 7] },
+            ] }))
+            .unwrap(),
+            json!({ "and": [
+                { "==": [{ "var": "build" }, 7] },
+                { "in": [{ "var": "os" }, ["android", "ios"]] },
+            ] })
+        );
+    }
+
+    #[test]
+    fn duplicate_disjuncts_collapse() {
+        assert_eq!(
+            compile_rule(json!({ "or": [
+                { "==": [{ "var": "os" }, "android"] },
+                { "==": [{ "var": "os" }, "android"] },
+            ] }))
+            .unwrap(),
+            json!({ "==": [{ "var": "os" }, "android"] })
+        );
+    }
+
+    #[test]
+    fn or_across_dimensions_is_rejected() {
+        assert!(compile_rule(json!({ "or": [
+            { "==": [{ "var": "os" }, "android"] },
+            { "==": [{ "var": "build" }, 7] },
+        ] }))
+        .is_err());
+    }
+
+    #[test]
+    fn too_many_alternatives_are_rejected() {
+        let alternatives: Vec<Value> = (0..=MAX_DISJUNCTS)
+            .map(|build| json!({ "==": [{ "var": "build" }, build] }))
+            .collect();
+        assert!(compile_rule(json!({ "or": alternatives })).is_err());
+    }
+
+    #[test]
+    fn malformed_rules_are_rejected() {
+        for rule in [
+            json!("android"),
+            json!({ "or": [] }),
+            json!({ "==": [{ "var": "os" }] }),
+            json!({ "==": ["os", "android"] }),
+            json!({ "in": [{ "var": "os" }, []] }),
+            json!({ ">=": [{ "var": "app_version" }] }),
+            json!({ "<=": ["app_version", "2.0.0"] }),
+        ] {
+            assert!(TargetingRule::parse(&rule).is_err(), "{} was accepted", rule);
+        }
+    }
+
+    #[test]
+    fn values_are_checked_against_dimensions() {
+        assert!(compile_rule(json!({ "region": "eu" })).is_err());
+        assert!(compile_rule(json!({ "build": "latest" })).is_err());
+        assert!(compile_rule(json!({ "channel": "nightly" })).is_err());
+        assert!(compile_rule(json!({ "channel": "beta" })).is_ok());
+    }
+
+    #[test]
+    fn version_ranges_compile_to_version_codes() {
+        assert_eq!(
+            compile_rule(json!({ "and": [
+                { ">=": [{ "var": "app_version" }, "1.9.0"] },
+                { "<=": [{ "var": "app_version" }, "1.10"] },
+            ] }))
+            .unwrap(),
+            json!({ "and": [
+                { "<=": [{ "var": "app_version_semver" }, 10_001_000_000i64] },
+                { ">=": [{ "var": "app_version_semver" }, 10_000_900_000i64] },
+            ] })
+        );
+    }
+
+    #[test]
+    fn invalid_version_ranges_are_rejected() {
+        // No version code dimension, or not a string dimension
+        assert!(compile_rule(json!({ ">=": [{ "var": "os" }, "1.0.0"] })).is_err());
+        assert!(compile_rule(json!({ ">=": [{ "var": "build" }, "1.0.0"] })).is_err());
+        // Not a version
+        assert!(compile_rule(json!({ ">=": [{ "var": "app_version" }, "latest"] })).is_err());
+        assert!(compile_rule(json!({ ">=": [{ "var": "app_version" }, 2] })).is_err());
+        // Ranges cannot be merged into an `in`
+        assert!(compile_rule(json!({ "or": [
+            { "<=": [{ "var": "app_version" }, "1.0.0"] },
+            { ">=": [{ "var": "app_version" }, "2.0.0"] },
+        ] }))
+        .is_err());
+    }
+
+    #[test]
+    fn versions_are_compared_semantically() {
+        let compiled: HashMap<String, Value> = serde_json::from_value(
+            compile_rule(json!({ ">=": [{ "var": "app_version" }, "1.9.0"] })).unwrap(),
+        )
+        .unwrap();
+        let matches = |version: &str| {
+            let Value::Object(values) = json!({ "app_version": version }) else {
+                unreachable!()
+            };
+            let raw = RawContext::from_values(values, None, None);
+            let context = validate_context(raw, &dimensions()).unwrap();
+            context_matches(&compiled, &context)
+        };
+        assert!(matches("1.10.0"));
+        assert!(matches("1.9.0"));
+        assert!(matches("2"));
+        assert!(!matches("1.8.12"));
+        assert!(!matches("not a version"));
+    }
+
+    #[test]
+    fn context_matches_compiled_rules() {
+        let compiled = HashMap::from([(
+            "and".to_string(),
+            json!([
+                { "==": [{ "var": "build" }, 7] },
+                { "in": [{ "var": "os" }, ["android", "ios"]] },
+            ]),
+        )]);
+        assert!(context_matches(&compiled, &json!({ "build": 7, "os": "ios" })));
+        assert!(!context_matches(&compiled, &json!({ "build": 8, "os": "ios" })));
+        assert!(!context_matches(&compiled, &json!({ "build": 7, "os": "web" })));
+        assert!(!context_matches(&compiled, &json!({ "build": 7 })));
+        assert!(context_matches(&HashMap::new(), &json!({})));
+    }
+}
@@ -0,0 +1,284 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Ingest of the lifecycle events the SDKs already track (`boot`, `app_load_exception`
+// and `update_result`). Events are stored for adoption statistics, the latest state of
+// each device is kept, and outcomes are counted per release for the health watcher.
+
+use std::collections::{HashMap, HashSet};
+
+use actix_web::{error, post, web, HttpRequest, Result};
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    types::AppState,
+    utils::{
+        db::{
+            models::{NewTelemetryEvent, ReleaseStatus},
+            schema::hyperotaserver::releases::dsl as releases,
+        },
+        release_health::{find_reporting_release, record_device_outcomes, HealthOutcome},
+        telemetry::{record_events, update_device_state, verify_ingest_key},
+    },
+};
+
+const MAX_EVENTS_PER_BATCH: usize = 1000;
+const MAX_DEVICE_ID_LENGTH: usize = 256;
+const DEVICE_ID_HEADER: &str = "x-device-id";
+/// Carries the application's ingest key, created through the telemetry API
+const TELEMETRY_KEY_HEADER: &str = "x-telemetry-key";
+
+/// Labels stored as telemetry; anything else is ignored
+const KNOWN_LABELS: [&str; 3] = ["boot", "app_load_exception", "update_result"];
+
+#[derive(Debug, Deserialize)]
+pub struct TelemetryEvent {
+    label: String,
+    /// Package the device booted or updated to. Events without it are stored but not
+    /// counted towards the health of any release.
+    package_version: Option<i32>,
+    /// Defaults to the `x-device-id` header of the request
+    device_id: Option<String>,
+    /// Time taken to download the package, for update results
+    download_duration_ms: Option<i64>,
+    #[serde(default)]
+    value: Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TelemetryBatch {
+    events: Vec<TelemetryEvent>,
+}
+
+#[derive(Serialize)]
+pub struct TelemetryResponse {
+    /// Events attributed to a release; repeated outcomes of a device count once
+    accepted: usize,
+    ignored: usize,
+}
+
+/// Health outcomes an event reports; none for events that say nothing about health
+fn event_outcomes(event: &TelemetryEvent) -> &'static [HealthOutcome] {
+    match event.label.as_str() {
+        "boot" => &[HealthOutcome::Boot],
+        "app_load_exception" => &[HealthOutcome::BootFailure],
+        "update_result" => match event.value.get("result").and_then(Value::as_str) {
+            Some("OK") | Some("ERROR") | Some("RELEASE_CONFIG_TIMEOUT") => &[HealthOutcome::Update],
+            Some("PACKAGE_TIMEOUT") => &[HealthOutcome::Update, HealthOutcome::PackageTimeout],
+            // "NA" means no update was attempted
+            _ => &[],
+        },
+        _ => &[],
+    }
+}
+
+/// Package versions among `versions` that some release of the application has served
+fn released_versions(
+    conn: &mut diesel::PgConnection,
+    organisation: &str,
+    application: &str,
+    versions: Vec<i32>,
+) -> Result<HashSet<i32>, diesel::result::Error> {
+    if versions.is_empty() {
+        return Ok(HashSet::new());
+    }
+    releases::releases
+        .filter(releases::org_id.eq(organisation))
+        .filter(releases::app_id.eq(application))
+        .filter(releases::package_version.eq_any(versions))
+        .filter(releases::status.ne(ReleaseStatus::Draft.as_str()))
+        .select(releases::package_version)
+        .distinct()
+        .load::<i32>(conn)
+        .map(|versions| versions.into_iter().collect())
+}
+
+/// Store the batch and update the state of the devices that sent it
+fn store_events(
+    conn: &mut diesel::PgConnection,
+    organisation: &str,
+    application: &str,
+    header_device_id: Option<&str>,
+    events: &[TelemetryEvent],
+) -> Result<()> {
+    let now = Utc::now();
+    let mut rows = Vec::new();
+    // Last booted version and update result per device, in batch order
+    let mut devices: HashMap<String, (Option<i32>, Option<String>)> = HashMap::new();
+
+    for event in events {
+        if !KNOWN_LABELS.contains(&event.label.as_str()) {
+            continue;
+        }
+        let device_id = event.device_id.as_deref().or(header_device_id);
+        if device_id.is_some_and(|device_id| device_id.len() > MAX_DEVICE_ID_LENGTH) {
+            return Err(error::ErrorBadRequest("device_id is too long"));
+        }
+        if event.download_duration_ms.is_some_and(|duration| duration < 0) {
+            return Err(error::ErrorBadRequest("download_duration_ms cannot be negative"));
+        }
+        let update_result = match event.label.as_str() {
+            "update_result" => event
+                .value
+                .get("result")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+            _ => None,
+        };
+
+        if let Some(device_id) = device_id {
+            let state = devices.entry(device_id.to_string()).or_default();
+            if event.label == "boot" && event.package_version.is_some() {
+                state.0 = event.package_version;
+            }
+            if update_result.is_some() {
+                state.1 = update_result.clone();
+            }
+        }
+
+        rows.push(NewTelemetryEvent {
+            org_id: organisation.to_string(),
+            app_id: application.to_string(),
+            device_id: device_id.map(str::to_string),
+            label: event.label.clone(),
+            package_version: event.package_version,
+            update_result,
+            download_duration_ms: event.download_duration_ms,
+            received_at: now,
+        });
+    }
+
+    if rows.is_empty() {
+        return Ok(());
+    }
+    // Events and device states are stored together, so a failed batch can be resent whole
+    conn.transaction(|conn| {
+        record_events(conn, &rows)?;
+        for (device_id, (booted_version, update_result)) in devices {
+            update_device_state(
+                conn,
+                organisation,
+                application,
+                &device_id,
+                booted_version,
+                update_result,
+                now,
+            )?;
+        }
+        Ok::<_, diesel::result::Error>(())
+    })
+    .map_err(error::ErrorInternalServerError)
+}
+
+#[post("telemetry/{organisation}/{application}")]
+async fn ingest_telemetry(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    batch: web::Json<TelemetryBatch>,
+    state: web::Data<AppState>,
+) -> Result<web::Json<TelemetryResponse>> {
+    let (organisation, application) = path.into_inner();
+    let batch = batch.into_inner();
+
+    if batch.events.len() > MAX_EVENTS_PER_BATCH {
+        return Err(error::ErrorBadRequest(format!(
+            "At most {} events can be sent at once",
+            MAX_EVENTS_PER_BATCH
+        )));
+    }
+
+    let ingest_key = req
+        .headers()
+        .get(TELEMETRY_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| {
+            error::ErrorUnauthorized(format!("Missing {} header", TELEMETRY_KEY_HEADER))
+        })?;
+
+    let mut conn = state
+        .db_pool
+        .get()
+        .map_err(error::ErrorInternalServerError)?;
+
+    if !verify_ingest_key(&mut conn, &organisation, &application, ingest_key)
+        .map_err(error::ErrorInternalServerError)?
+    {
+        return Err(error::ErrorUnauthorized("Invalid telemetry key"));
+    }
+
+    // Events about packages that were never released are dropped rather than stored
+    let released = released_versions(
+        &mut conn,
+        &organisation,
+        &application,
+        batch.events.iter().filter_map(|event| event.package_version).collect(),
+    )
+    .map_err(error::ErrorInternalServerError)?;
+    let (events, dropped): (Vec<TelemetryEvent>, Vec<TelemetryEvent>) =
+        batch.events.into_iter().partition(|event| {
+            event
+                .package_version
+                .is_none_or(|version| released.contains(&version))
+        });
+    let mut ignored = dropped.len();
+
+    let header_device_id = req
+        .headers()
+        .get(DEVICE_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty());
+    store_events(&mut conn, &organisation, &application, header_device_id, &events)?;
+
+    // Events counted and outcomes reported per package version. Events without a version
+    // or a device are stored, but say nothing about the health of a release.
+    let mut by_version: HashMap<i32, (usize, HashSet<(String, HealthOutcome)>)> = HashMap::new();
+    for event in &events {
+        let outcomes = event_outcomes(event);
+        let device_id = event.device_id.as_deref().or(header_device_id);
+        match (event.package_version, device_id) {
+            (Some(version), Some(device_id)) if !outcomes.is_empty() => {
+                let (counted, reported) = by_version.entry(version).or_default();
+                *counted += 1;
+                reported.extend(
+                    outcomes
+                        .iter()
+                        .map(|outcome| (device_id.to_string(), *outcome)),
+                );
+            }
+            _ => ignored += 1,
+        }
+    }
+
+    let mut accepted = 0;
+    for (version, (counted, reported)) in by_version {
+        let release = find_reporting_release(&mut conn, &organisation, &application, version)
+            .map_err(error::ErrorInternalServerError)?;
+        match release {
+            Some(release_id) => {
+                record_device_outcomes(&mut conn, release_id, &reported)
+                    .map_err(error::ErrorInternalServerError)?;
+                accepted += counted;
+            }
+            None => ignored += counted,
+        }
+    }
+
+    Ok(web::Json(TelemetryResponse { accepted, ignored }))
+}
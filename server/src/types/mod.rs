@@ -14,14 +14,19 @@
 
 use superposition_rust_sdk::apis::configuration::Configuration;
 
-use crate::utils::db;
+use crate::release::{context::Dimensions, ReleaseCacheKey, ResolvedRelease};
+use crate::utils::{cache::TtlCache, db, signing::AppSigningKey};
 
 #[derive(Clone)]
 pub struct AppState {
     pub env: Environment,
     pub db_pool: db::DbPool,
     pub s3_client: aws_sdk_s3::Client,
+    pub kms_client: aws_sdk_kms::Client,
     pub superposition_configuration: Configuration,
+    pub release_cache: TtlCache<ReleaseCacheKey, ResolvedRelease>,
+    pub signing_keys: TtlCache<(String, String), AppSigningKey>,
+    pub dimension_cache: TtlCache<String, Dimensions>,
 }
 
 #[derive(Clone, Debug)]
@@ -35,4 +40,7 @@ pub struct Environment {
     pub realm: String,
     pub bucket_name: String,
     pub superposition_org_id: String,
+    pub kms_key_id: String,
+    /// How many earlier package versions binary patches are generated from
+    pub patch_base_versions: i64,
 }
@@ -0,0 +1,68 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+/// Number of entries after which expired entries are swept on insert
+const SWEEP_THRESHOLD: usize = 1024;
+
+/// In-memory key/value cache where every entry expires after a fixed TTL.
+/// Clones share the same underlying storage.
+#[derive(Clone)]
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    entries: Arc<RwLock<HashMap<K, (Instant, V)>>>,
+}
+
+impl<K, V> TtlCache<K, V>
+where
+    K: Eq + Hash,
+    V: Clone,
+{
+    pub fn new(ttl: Duration) -> Self {
+        TtlCache {
+            ttl,
+            entries: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Returns the cached value if it has not expired yet
+    pub fn get(&self, key: &K) -> Option<V> {
+        let entries = self.entries.read().unwrap();
+        entries
+            .get(key)
+            .filter(|(inserted_at, _)| inserted_at.elapsed() < self.ttl)
+            .map(|(_, value)| value.clone())
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        let mut entries = self.entries.write().unwrap();
+        if entries.len() >= SWEEP_THRESHOLD {
+            let ttl = self.ttl;
+            entries.retain(|_, (inserted_at, _)| inserted_at.elapsed() < ttl);
+        }
+        entries.insert(key, (Instant::now(), value));
+    }
+
+    /// Drops every entry whose key matches the predicate
+    pub fn invalidate_where(&self, predicate: impl Fn(&K) -> bool) {
+        let mut entries = self.entries.write().unwrap();
+        entries.retain(|key, _| !predicate(key));
+    }
+}
@@ -0,0 +1,155 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// JSON Schemas for the `tenant_info` and `properties` of configs. Each application may
+// register schemas; new configs are validated against the latest version. Remote `$ref`s
+// are not resolved.
+
+use diesel::prelude::*;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::utils::db::{
+    models::ConfigSchema, schema::hyperotaserver::config_schemas::dsl as config_schemas,
+};
+
+#[derive(Debug, Serialize)]
+pub struct FieldError {
+    /// JSON Pointer of the invalid value within the config, e.g. `/tenant_info/theme`
+    pub field: String,
+    pub message: String,
+}
+
+/// Check that `schema` is a valid JSON Schema that can be compiled
+pub fn check_schema(name: &str, schema: &Value) -> Result<(), String> {
+    jsonschema::meta::validate(schema).map_err(|e| format!("Invalid {}: {}", name, e))?;
+    jsonschema::validator_for(schema).map_err(|e| format!("Invalid {}: {}", name, e))?;
+    Ok(())
+}
+
+pub fn latest_schema(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+) -> Result<Option<ConfigSchema>, diesel::result::Error> {
+    config_schemas::config_schemas
+        .filter(config_schemas::org_id.eq(organisation))
+        .filter(config_schemas::app_id.eq(application))
+        .order_by(config_schemas::version.desc())
+        .select(ConfigSchema::as_select())
+        .first(conn)
+        .optional()
+}
+
+fn validate_field(
+    field: &str,
+    schema: Option<&Value>,
+    instance: &Value,
+    errors: &mut Vec<FieldError>,
+) -> Result<(), String> {
+    let Some(schema) = schema else {
+        return Ok(());
+    };
+    let validator = jsonschema::validator_for(schema)
+        .map_err(|e| format!("Stored {} schema does not compile: {}", field, e))?;
+    errors.extend(validator.iter_errors(instance).map(|e| FieldError {
+        field: format!("/{}{}", field, e.instance_path),
+        message: e.to_string(),
+    }));
+    Ok(())
+}
+
+/// Errors of a config's `tenant_info` and `properties` against an application's schemas.
+/// Fails only if a stored schema cannot be compiled.
+pub fn validate_config(
+    schema: &ConfigSchema,
+    tenant_info: &Value,
+    properties: &Value,
+) -> Result<Vec<FieldError>, String> {
+    let mut errors = Vec::new();
+    validate_field("tenant_info", schema.tenant_info_schema.as_ref(), tenant_info, &mut errors)?;
+    validate_field("properties", schema.properties_schema.as_ref(), properties, &mut errors)?;
+    Ok(errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema(tenant_info: Option<Value>, properties: Option<Value>) -> ConfigSchema {
+        ConfigSchema {
+            org_id: "org".to_string(),
+            app_id: "app".to_string(),
+            version: 1,
+            tenant_info_schema: tenant_info,
+            properties_schema: properties,
+            created_at: chrono::Utc::now(),
+            created_by: "tester".to_string(),
+        }
+    }
+
+    fn theme_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": { "theme": { "type": "string" } },
+            "required": ["theme"]
+        })
+    }
+
+    #[test]
+    fn valid_configs_have_no_errors() {
+        let schema = schema(Some(theme_schema()), Some(json!({ "type": "object" })));
+        let errors =
+            validate_config(&schema, &json!({ "theme": "dark" }), &json!({ "a": 1 })).unwrap();
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn missing_schemas_accept_anything() {
+        let errors = validate_config(&schema(None, None), &json!(42), &json!("text")).unwrap();
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn errors_point_at_the_invalid_value() {
+        let schema = schema(Some(theme_schema()), Some(json!({ "type": "object" })));
+        let errors = validate_config(&schema, &json!({ "theme": 1 }), &json!([])).unwrap();
+        let fields: Vec<&str> = errors.iter().map(|error| error.field.as_str()).collect();
+        assert_eq!(fields, ["/tenant_info/theme", "/properties"]);
+        assert!(errors.iter().all(|error| !error.message.is_empty()));
+    }
+
+    #[test]
+    fn missing_required_fields_point_at_the_object() {
+        let schema = schema(Some(theme_schema()), None);
+        let errors = validate_config(&schema, &json!({}), &json!({})).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "/tenant_info");
+    }
+
+    #[test]
+    fn uncompilable_stored_schemas_fail() {
+        let schema = schema(None, Some(json!({ "type": "not-a-type" })));
+        let error = validate_config(&schema, &json!({}), &json!({})).unwrap_err();
+        assert!(error.contains("properties"));
+    }
+
+    #[test]
+    fn check_schema_rejects_invalid_schemas() {
+        assert!(check_schema("tenant_info_schema", &theme_schema()).is_ok());
+        let error = check_schema("tenant_info_schema", &json!({ "type": 5 })).unwrap_err();
+        assert!(error.starts_with("Invalid tenant_info_schema"));
+    }
+}
@@ -6,7 +6,9 @@ use diesel::sql_types::Json;
 use serde::{Deserialize, Serialize};
 
 use crate::utils::db::schema::hyperotaserver::{
-    cleanup_outbox, configs, packages, releases, workspace_names
+    application_settings, cleanup_outbox, config_schemas, configs, device_states, last_known_good_releases,
+    package_patches, packages, pending_packages, release_health, release_health_reports, release_schedule_steps, releases,
+    signing_keys, telemetry_events, telemetry_keys, tester_groups, workspace_names
 };
 
 
@@ -15,6 +17,12 @@ pub struct File {
     pub url: String,
     #[serde(rename = "filePath")]
     pub file_path: String,
+    /// Lowercase hex SHA-256 of the file contents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Size of the file in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<i64>,
 }
 
 #[derive(Insertable, Debug)]
@@ -32,6 +40,12 @@ pub struct PackageEntry {
     pub properties: serde_json::Value,
     #[diesel(sql_type = diesel::sql_types::Jsonb)]
     pub resources: serde_json::Value,
+    pub index_sha256: Option<String>,
+    pub index_size: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub created_by: String,
+    /// Version label of the app build, as sent by the client
+    pub tag: Option<String>,
 }
 
 #[derive(Queryable, Selectable, Debug)]
@@ -50,6 +64,14 @@ pub struct PackageEntryRead {
     pub properties: serde_json::Value,
     #[diesel(sql_type = diesel::sql_types::Jsonb)]
     pub resources: serde_json::Value,
+    pub index_sha256: Option<String>,
+    pub index_size: Option<i64>,
+    /// Set once the package is archived, by hand or by garbage collection; it can no longer be released
+    pub archived_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    /// Unknown for packages created before it was recorded
+    pub created_by: Option<String>,
+    pub tag: Option<String>,
 }
 
 #[derive(Queryable, Insertable, Debug, Selectable)]
@@ -57,6 +79,7 @@ pub struct PackageEntryRead {
 pub struct ConfigEntry {
     pub org_id: String,
     pub app_id: String,
+    /// Package version the config is bound to
     pub version: i32,
     pub config_version: String,
     pub release_config_timeout: i32,
@@ -65,6 +88,40 @@ pub struct ConfigEntry {
     pub tenant_info: serde_json::Value,
     #[diesel(sql_type = diesel::sql_types::Jsonb)]
     pub properties: serde_json::Value,
+    /// Version of the config itself, increasing per application
+    pub revision: i32,
+}
+
+#[derive(Queryable, Insertable, Selectable, Serialize, Debug)]
+#[diesel(table_name = config_schemas)]
+pub struct ConfigSchema {
+    pub org_id: String,
+    pub app_id: String,
+    pub version: i32,
+    /// JSON Schema for `tenant_info` of configs; None accepts any value
+    pub tenant_info_schema: Option<serde_json::Value>,
+    /// JSON Schema for `properties` of configs; None accepts any value
+    pub properties_schema: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+    pub created_by: String,
+}
+
+/// Devices and users of an application that get tester releases
+#[derive(Queryable, Insertable, Selectable, Serialize, Debug)]
+#[diesel(table_name = tester_groups)]
+pub struct TesterGroup {
+    pub id: uuid::Uuid,
+    pub org_id: String,
+    pub app_id: String,
+    pub name: String,
+    pub device_ids: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub created_by: String,
+    pub updated_at: DateTime<Utc>,
+    /// SHA-256 of the token members send in `x-tester-token`
+    #[serde(skip_serializing)]
+    pub token_hash: Option<String>,
+    pub user_ids: Vec<String>,
 }
 
 #[derive(Queryable, Insertable, Debug)]
@@ -92,6 +149,69 @@ pub struct ReleaseEntry {
     pub created_by: String,
     #[diesel(sql_type = diesel::sql_types::Jsonb)]
     pub metadata: serde_json::Value,
+    /// Share of devices (0-100) that get this release; the rest keep `previous_package_version`
+    pub rollout_percentage: i32,
+    pub previous_package_version: Option<i32>,
+    /// Superposition experiment serving this release
+    pub experiment_id: Option<String>,
+    /// Superposition context the release targets
+    pub context: serde_json::Value,
+    /// Release this one reverted, when created by a rollback
+    pub rolled_back_from: Option<uuid::Uuid>,
+    /// One of `ReleaseStatus`, stored as text
+    pub status: String,
+    pub ramped_at: Option<DateTime<Utc>>,
+    pub paused_at: Option<DateTime<Utc>>,
+    pub live_at: Option<DateTime<Utc>>,
+    pub concluded_at: Option<DateTime<Utc>>,
+    pub aborted_at: Option<DateTime<Utc>>,
+    /// Tester group the release is pinned to; such releases bypass Superposition
+    pub tester_group_id: Option<uuid::Uuid>,
+    /// Revision of the package's config the release serves
+    pub config_revision: Option<i32>,
+}
+
+/// Lifecycle of a release. Transitions are validated in `utils::release_lifecycle`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReleaseStatus {
+    /// Experiment created but not yet ramped
+    Draft,
+    /// Served to `rollout_percentage` of devices
+    Ramping,
+    /// Rollout frozen at its current percentage
+    Paused,
+    /// Served to all devices
+    Live,
+    /// Experiment concluded into the base config
+    Concluded,
+    /// Experiment discarded
+    Aborted,
+}
+
+impl ReleaseStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReleaseStatus::Draft => "draft",
+            ReleaseStatus::Ramping => "ramping",
+            ReleaseStatus::Paused => "paused",
+            ReleaseStatus::Live => "live",
+            ReleaseStatus::Concluded => "concluded",
+            ReleaseStatus::Aborted => "aborted",
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<Self> {
+        match status {
+            "draft" => Some(ReleaseStatus::Draft),
+            "ramping" => Some(ReleaseStatus::Ramping),
+            "paused" => Some(ReleaseStatus::Paused),
+            "live" => Some(ReleaseStatus::Live),
+            "concluded" => Some(ReleaseStatus::Concluded),
+            "aborted" => Some(ReleaseStatus::Aborted),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Queryable, Selectable, Serialize, Deserialize, Debug)]
@@ -110,3 +230,150 @@ pub struct NewWorkspaceName<'a> {
     pub organization_id: &'a str,
     pub workspace_name: &'a str,
 }
+
+#[derive(Queryable, Insertable, Selectable, Debug)]
+#[diesel(table_name = application_settings)]
+pub struct ApplicationSettings {
+    pub org_id: String,
+    pub app_id: String,
+    pub cache_control: String,
+    pub updated_at: DateTime<Utc>,
+    /// Share of boots that may fail before a rolling out release is rolled back
+    pub max_boot_failure_rate: Option<f64>,
+    /// Share of updates that may time out before a rolling out release is rolled back
+    pub max_package_timeout_rate: Option<f64>,
+    /// Reports needed before either rate is acted upon
+    pub min_health_samples: i32,
+    /// Newest package versions kept by garbage collection; None disables it
+    pub retain_package_versions: Option<i32>,
+    /// Packages of releases created within this many days are kept as well
+    pub retain_released_days: i32,
+}
+
+#[derive(Queryable, Insertable, AsChangeset, Selectable, Debug)]
+#[diesel(table_name = last_known_good_releases)]
+pub struct LastKnownGoodRelease {
+    pub org_id: String,
+    pub app_id: String,
+    pub context_key: String,
+    pub release_config: serde_json::Value,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Insertable, Selectable, Debug)]
+#[diesel(table_name = signing_keys)]
+pub struct SigningKeyEntry {
+    pub id: uuid::Uuid,
+    pub org_id: String,
+    pub app_id: String,
+    /// Base64 encoded Ed25519 verifying key
+    pub public_key: String,
+    /// Base64 encoded KMS ciphertext of the base64 encoded Ed25519 secret key
+    pub encrypted_private_key: String,
+    pub created_at: DateTime<Utc>,
+    /// When the key was rotated out; only the key without one signs release configs
+    pub retired_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Queryable, Insertable, Selectable, Serialize, Debug)]
+#[diesel(table_name = release_schedule_steps)]
+pub struct ReleaseScheduleStep {
+    pub release_id: uuid::Uuid,
+    pub step_index: i32,
+    pub run_at: DateTime<Utc>,
+    /// Percentage to ramp to, or `None` for the final conclude step
+    pub rollout_percentage: Option<i32>,
+    pub conclude: bool,
+    pub applied_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Queryable, Insertable, Selectable, Serialize, Debug)]
+#[diesel(table_name = release_health)]
+pub struct ReleaseHealth {
+    pub release_id: uuid::Uuid,
+    pub boots: i64,
+    pub boot_failures: i64,
+    pub updates: i64,
+    pub package_timeouts: i64,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// One kind of outcome a device reported for a release
+#[derive(Insertable, Debug)]
+#[diesel(table_name = release_health_reports)]
+pub struct ReleaseHealthReport {
+    pub release_id: uuid::Uuid,
+    pub device_id: String,
+    /// `boot`, `boot_failure`, `update` or `package_timeout`
+    pub outcome: String,
+    pub reported_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Insertable, Selectable, Debug)]
+#[diesel(table_name = telemetry_keys)]
+pub struct TelemetryKey {
+    pub org_id: String,
+    pub app_id: String,
+    /// Lowercase hex SHA-256 of the key devices send
+    pub key_hash: String,
+    pub created_at: DateTime<Utc>,
+    pub created_by: String,
+    pub id: uuid::Uuid,
+    /// Set once the key was revoked; devices sending it are rejected from then on
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub revoked_by: Option<String>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = telemetry_events)]
+pub struct NewTelemetryEvent {
+    pub org_id: String,
+    pub app_id: String,
+    pub device_id: Option<String>,
+    pub label: String,
+    pub package_version: Option<i32>,
+    pub update_result: Option<String>,
+    pub download_duration_ms: Option<i64>,
+    pub received_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Insertable, Selectable, Debug)]
+#[diesel(table_name = device_states)]
+pub struct DeviceState {
+    pub org_id: String,
+    pub app_id: String,
+    pub device_id: String,
+    /// Package the device last booted
+    pub package_version: Option<i32>,
+    pub last_update_result: Option<String>,
+    pub first_seen_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Insertable, Selectable, Debug)]
+#[diesel(table_name = package_patches)]
+pub struct PackagePatch {
+    pub org_id: String,
+    pub app_id: String,
+    pub from_version: i32,
+    pub to_version: i32,
+    pub file_path: String,
+    pub url: String,
+    /// Lowercase hex SHA-256 of the patch itself
+    pub sha256: String,
+    pub size: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Insertable, Selectable, Debug)]
+#[diesel(table_name = pending_packages)]
+pub struct PendingPackage {
+    pub id: uuid::Uuid,
+    pub org_id: String,
+    pub app_id: String,
+    pub version: i32,
+    /// Package metadata and the files expected to be uploaded, as sent by the client
+    pub manifest: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
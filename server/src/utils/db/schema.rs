@@ -1,6 +1,20 @@
 // @generated automatically by Diesel CLI.
 
 pub mod hyperotaserver {
+    diesel::table! {
+        hyperotaserver.application_settings (org_id, app_id) {
+            org_id -> Text,
+            app_id -> Text,
+            cache_control -> Text,
+            updated_at -> Timestamptz,
+            max_boot_failure_rate -> Nullable<Float8>,
+            max_package_timeout_rate -> Nullable<Float8>,
+            min_health_samples -> Int4,
+            retain_package_versions -> Nullable<Int4>,
+            retain_released_days -> Int4,
+        }
+    }
+
     diesel::table! {
         hyperotaserver.cleanup_outbox (transaction_id) {
             transaction_id -> Text,
@@ -13,6 +27,18 @@ pub mod hyperotaserver {
         }
     }
 
+    diesel::table! {
+        hyperotaserver.config_schemas (org_id, app_id, version) {
+            org_id -> Text,
+            app_id -> Text,
+            version -> Int4,
+            tenant_info_schema -> Nullable<Jsonb>,
+            properties_schema -> Nullable<Jsonb>,
+            created_at -> Timestamptz,
+            created_by -> Text,
+        }
+    }
+
     diesel::table! {
         hyperotaserver.configs (id) {
             id -> Int4,
@@ -25,6 +51,53 @@ pub mod hyperotaserver {
             tenant_info -> Jsonb,
             properties -> Jsonb,
             created_at -> Timestamp,
+            revision -> Int4,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.deleted_package_versions (org_id, app_id, version) {
+            org_id -> Text,
+            app_id -> Text,
+            version -> Int4,
+            deleted_at -> Timestamptz,
+            deleted_by -> Text,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.device_states (org_id, app_id, device_id) {
+            org_id -> Text,
+            app_id -> Text,
+            device_id -> Text,
+            package_version -> Nullable<Int4>,
+            last_update_result -> Nullable<Text>,
+            first_seen_at -> Timestamptz,
+            last_seen_at -> Timestamptz,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.last_known_good_releases (org_id, app_id, context_key) {
+            org_id -> Text,
+            app_id -> Text,
+            context_key -> Text,
+            release_config -> Jsonb,
+            updated_at -> Timestamptz,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.package_patches (org_id, app_id, from_version, to_version, file_path) {
+            org_id -> Text,
+            app_id -> Text,
+            from_version -> Int4,
+            to_version -> Int4,
+            file_path -> Text,
+            url -> Text,
+            sha256 -> Text,
+            size -> Int8,
+            created_at -> Timestamptz,
         }
     }
 
@@ -41,6 +114,55 @@ pub mod hyperotaserver {
             lazy -> Jsonb,
             properties -> Jsonb,
             resources -> Jsonb,
+            index_sha256 -> Nullable<Text>,
+            index_size -> Nullable<Int8>,
+            archived_at -> Nullable<Timestamptz>,
+            created_at -> Timestamptz,
+            created_by -> Nullable<Text>,
+            tag -> Nullable<Text>,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.pending_packages (id) {
+            id -> Uuid,
+            org_id -> Text,
+            app_id -> Text,
+            version -> Int4,
+            manifest -> Jsonb,
+            created_at -> Timestamptz,
+            expires_at -> Timestamptz,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.release_health (release_id) {
+            release_id -> Uuid,
+            boots -> Int8,
+            boot_failures -> Int8,
+            updates -> Int8,
+            package_timeouts -> Int8,
+            updated_at -> Timestamptz,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.release_health_reports (release_id, device_id, outcome) {
+            release_id -> Uuid,
+            device_id -> Text,
+            outcome -> Text,
+            reported_at -> Timestamptz,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.release_schedule_steps (release_id, step_index) {
+            release_id -> Uuid,
+            step_index -> Int4,
+            run_at -> Timestamptz,
+            rollout_percentage -> Nullable<Int4>,
+            conclude -> Bool,
+            applied_at -> Nullable<Timestamptz>,
         }
     }
 
@@ -54,6 +176,73 @@ pub mod hyperotaserver {
             created_at -> Timestamptz,
             created_by -> Text,
             metadata -> Jsonb,
+            rollout_percentage -> Int4,
+            previous_package_version -> Nullable<Int4>,
+            experiment_id -> Nullable<Text>,
+            context -> Jsonb,
+            rolled_back_from -> Nullable<Uuid>,
+            status -> Text,
+            ramped_at -> Nullable<Timestamptz>,
+            paused_at -> Nullable<Timestamptz>,
+            live_at -> Nullable<Timestamptz>,
+            concluded_at -> Nullable<Timestamptz>,
+            aborted_at -> Nullable<Timestamptz>,
+            tester_group_id -> Nullable<Uuid>,
+            config_revision -> Nullable<Int4>,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.signing_keys (id) {
+            id -> Uuid,
+            org_id -> Text,
+            app_id -> Text,
+            public_key -> Text,
+            encrypted_private_key -> Text,
+            created_at -> Timestamptz,
+            retired_at -> Nullable<Timestamptz>,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.telemetry_events (id) {
+            id -> Int8,
+            org_id -> Text,
+            app_id -> Text,
+            device_id -> Nullable<Text>,
+            label -> Text,
+            package_version -> Nullable<Int4>,
+            update_result -> Nullable<Text>,
+            download_duration_ms -> Nullable<Int8>,
+            received_at -> Timestamptz,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.telemetry_keys (id) {
+            org_id -> Text,
+            app_id -> Text,
+            key_hash -> Text,
+            created_at -> Timestamptz,
+            created_by -> Text,
+            id -> Uuid,
+            revoked_at -> Nullable<Timestamptz>,
+            revoked_by -> Nullable<Text>,
+        }
+    }
+
+    diesel::table! {
+        hyperotaserver.tester_groups (id) {
+            id -> Uuid,
+            org_id -> Text,
+            app_id -> Text,
+            name -> Text,
+            device_ids -> Array<Text>,
+            created_at -> Timestamptz,
+            created_by -> Text,
+            updated_at -> Timestamptz,
+            token_hash -> Nullable<Text>,
+            user_ids -> Array<Text>,
         }
     }
 
@@ -66,10 +255,24 @@ pub mod hyperotaserver {
     }
 
     diesel::allow_tables_to_appear_in_same_query!(
+        application_settings,
         cleanup_outbox,
+        config_schemas,
         configs,
+        deleted_package_versions,
+        device_states,
+        last_known_good_releases,
+        package_patches,
         packages,
+        pending_packages,
+        release_health,
+        release_health_reports,
+        release_schedule_steps,
         releases,
+        signing_keys,
+        telemetry_events,
+        telemetry_keys,
+        tester_groups,
         workspace_names,
     );
 }
@@ -0,0 +1,160 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Structural diff of JSON documents. Objects are compared key by key and arrays index by
+// index; every other difference is reported at the path where the values part ways.
+
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonChange {
+    /// JSON Pointer (RFC 6901) of the changed value
+    pub path: String,
+    pub kind: ChangeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<Value>,
+}
+
+fn pointer(parent: &str, token: &str) -> String {
+    format!("{}/{}", parent, token.replace('~', "~0").replace('/', "~1"))
+}
+
+fn diff_at(path: String, from: Option<&Value>, to: Option<&Value>, changes: &mut Vec<JsonChange>) {
+    match (from, to) {
+        (None, None) => {}
+        (None, Some(to)) => changes.push(JsonChange {
+            path,
+            kind: ChangeKind::Added,
+            from: None,
+            to: Some(to.clone()),
+        }),
+        (Some(from), None) => changes.push(JsonChange {
+            path,
+            kind: ChangeKind::Removed,
+            from: Some(from.clone()),
+            to: None,
+        }),
+        (Some(Value::Object(from)), Some(Value::Object(to))) => {
+            let keys: BTreeSet<&String> = from.keys().chain(to.keys()).collect();
+            for key in keys {
+                diff_at(pointer(&path, key), from.get(key), to.get(key), changes);
+            }
+        }
+        (Some(Value::Array(from)), Some(Value::Array(to))) => {
+            for index in 0..from.len().max(to.len()) {
+                diff_at(
+                    pointer(&path, &index.to_string()),
+                    from.get(index),
+                    to.get(index),
+                    changes,
+                );
+            }
+        }
+        (Some(from), Some(to)) if from != to => changes.push(JsonChange {
+            path,
+            kind: ChangeKind::Changed,
+            from: Some(from.clone()),
+            to: Some(to.clone()),
+        }),
+        _ => {}
+    }
+}
+
+/// Changes turning `from` into `to`, ordered by path
+pub fn diff(from: &Value, to: &Value) -> Vec<JsonChange> {
+    let mut changes = Vec::new();
+    diff_at(String::new(), Some(from), Some(to), &mut changes);
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn summary(changes: &[JsonChange]) -> Vec<(&str, ChangeKind)> {
+        changes
+            .iter()
+            .map(|change| (change.path.as_str(), change.kind))
+            .collect()
+    }
+
+    #[test]
+    fn equal_values_have_no_changes() {
+        let value = json!({ "a": [1, { "b": null }] });
+        assert!(diff(&value, &value).is_empty());
+    }
+
+    #[test]
+    fn objects_are_compared_by_key_in_path_order() {
+        let changes = diff(
+            &json!({ "b": 1, "a": { "x": true }, "c": "gone" }),
+            &json!({ "a": { "x": false }, "b": 1, "d": "new" }),
+        );
+        assert_eq!(
+            summary(&changes),
+            vec![
+                ("/a/x", ChangeKind::Changed),
+                ("/c", ChangeKind::Removed),
+                ("/d", ChangeKind::Added),
+            ]
+        );
+        assert_eq!(changes[0].from, Some(json!(true)));
+        assert_eq!(changes[0].to, Some(json!(false)));
+        assert_eq!(changes[1].to, None);
+        assert_eq!(changes[2].from, None);
+    }
+
+    #[test]
+    fn arrays_are_compared_by_index() {
+        let changes = diff(&json!([1, 2, 3]), &json!([1, 4]));
+        assert_eq!(
+            summary(&changes),
+            vec![("/1", ChangeKind::Changed), ("/2", ChangeKind::Removed)]
+        );
+    }
+
+    #[test]
+    fn values_of_different_types_are_replaced() {
+        let changes = diff(&json!({ "a": { "b": 1 } }), &json!({ "a": [1] }));
+        assert_eq!(summary(&changes), vec![("/a", ChangeKind::Changed)]);
+    }
+
+    #[test]
+    fn root_change_has_empty_path() {
+        assert_eq!(summary(&diff(&json!(1), &json!(2))), vec![("", ChangeKind::Changed)]);
+    }
+
+    #[test]
+    fn keys_are_escaped_in_pointers() {
+        let changes = diff(&json!({}), &json!({ "a/b": { "c~d": 1 } }));
+        assert_eq!(summary(&changes), vec![("/a~1b", ChangeKind::Added)]);
+        let changes = diff(&json!({ "a/b": { "c~d": 1 } }), &json!({ "a/b": { "c~d": 2 } }));
+        assert_eq!(summary(&changes), vec![("/a~1b/c~0d", ChangeKind::Changed)]);
+    }
+}
@@ -23,3 +23,36 @@ pub async fn decrypt_kms(kms_client: &Client, value: String) -> String {
     )
     .expect("Could not convert to UTF-8")
 }
+
+/// Fallible counterpart of `decrypt_kms` for use while serving requests
+pub async fn try_decrypt_kms(kms_client: &Client, value: &str) -> Result<String, String> {
+    let ciphertext = general_purpose::STANDARD
+        .decode(value)
+        .map_err(|e| format!("Failed to decode base64: {}", e))?;
+    let output = kms_client
+        .decrypt()
+        .ciphertext_blob(Blob::new(ciphertext))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to decrypt with KMS: {}", e))?;
+    let plaintext = output
+        .plaintext()
+        .ok_or_else(|| "KMS returned no plaintext".to_string())?;
+    String::from_utf8(plaintext.as_ref().to_vec())
+        .map_err(|e| format!("Could not convert to UTF-8: {}", e))
+}
+
+/// Encrypt `value` with the KMS key `key_id`, returning the base64 encoded ciphertext
+pub async fn encrypt_kms(kms_client: &Client, key_id: &str, value: &str) -> Result<String, String> {
+    let output = kms_client
+        .encrypt()
+        .key_id(key_id)
+        .plaintext(Blob::new(value.as_bytes()))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to encrypt with KMS: {}", e))?;
+    let ciphertext = output
+        .ciphertext_blob()
+        .ok_or_else(|| "KMS returned no ciphertext".to_string())?;
+    Ok(general_purpose::STANDARD.encode(ciphertext.as_ref()))
+}
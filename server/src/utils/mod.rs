@@ -12,9 +12,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod cache;
+pub mod config_schema;
 pub mod db;
+pub mod json_diff;
 pub mod keycloak;
 pub mod kms;
+pub mod package_gc;
+pub mod pagination;
+pub mod patches;
+pub mod release_health;
+pub mod release_lifecycle;
+pub mod release_scheduler;
 pub mod s3;
+pub mod settings;
+pub mod signing;
+pub mod telemetry;
+pub mod tester_groups;
 pub mod transaction_manager;
 pub mod workspace;
@@ -0,0 +1,433 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Package garbage collection. Packages outside an application's retention rules are
+// archived, and S3 objects under the application's prefix that no remaining package
+// refers to are deleted. Archived package rows are kept so versions are never reused.
+
+use std::collections::HashSet;
+
+use actix_web::web;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use log::{debug, error, info};
+use serde::Serialize;
+
+use crate::{
+    types::AppState,
+    utils::{
+        db::{
+            models::{ApplicationSettings, File, PackageEntryRead, PendingPackage, ReleaseEntry, ReleaseStatus},
+            schema::hyperotaserver::{
+                application_settings::dsl as settings, package_patches::dsl as patches,
+                packages::dsl as packages, pending_packages::dsl as pending,
+                releases::dsl as releases,
+            },
+        },
+        s3::{delete_objects, list_objects, object_key},
+    },
+};
+
+const GC_INTERVAL_SECS: u64 = 3600;
+
+/// Objects this recent are never deleted, as they may belong to a package being created
+const GC_GRACE_HOURS: i64 = 24;
+
+/// Public releases whose package is always kept: the latest ones, so the live release and the
+/// release it would be rolled back to stay servable
+const RETAINED_LATEST_RELEASES: usize = 2;
+
+type GcError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Serialize)]
+pub struct CollectionReport {
+    pub dry_run: bool,
+    pub archived_versions: Vec<i32>,
+    pub deleted_objects: Vec<String>,
+    pub freed_bytes: i64,
+}
+
+/// Package versions the retention rules of an application keep
+fn retained_versions(
+    conn: &mut PgConnection,
+    app_settings: &ApplicationSettings,
+    retain_package_versions: i32,
+    all_packages: &[PackageEntryRead],
+) -> Result<HashSet<i32>, diesel::result::Error> {
+    let mut retained: HashSet<i32> = HashSet::new();
+
+    let mut versions: Vec<i32> = all_packages.iter().map(|package| package.version).collect();
+    versions.sort_unstable_by(|a, b| b.cmp(a));
+    retained.extend(versions.into_iter().take(retain_package_versions as usize));
+
+    let release_entries: Vec<ReleaseEntry> = releases::releases
+        .filter(releases::org_id.eq(&app_settings.org_id))
+        .filter(releases::app_id.eq(&app_settings.app_id))
+        .order_by(releases::created_at.desc())
+        .select(ReleaseEntry::as_select())
+        .load(conn)?;
+
+    let released_since = Utc::now() - Duration::days(i64::from(app_settings.retain_released_days));
+    let mut latest = 0;
+    let mut fleet_release_found = false;
+    for release in &release_entries {
+        let status = ReleaseStatus::parse(&release.status);
+        let in_progress = matches!(
+            status,
+            Some(ReleaseStatus::Draft | ReleaseStatus::Ramping | ReleaseStatus::Paused | ReleaseStatus::Live)
+        );
+        // Tester releases reach only their group, so they neither count as the latest
+        // releases nor stand in for what the fleet is served
+        let is_public = release.tester_group_id.is_none();
+        let is_latest = is_public
+            && latest < RETAINED_LATEST_RELEASES
+            && !matches!(status, Some(ReleaseStatus::Draft | ReleaseStatus::Aborted));
+        if is_latest {
+            latest += 1;
+        }
+        // The newest live or concluded public release is what the fleet runs, however old
+        let is_fleet_release = is_public
+            && !fleet_release_found
+            && matches!(status, Some(ReleaseStatus::Live | ReleaseStatus::Concluded));
+        if is_fleet_release {
+            fleet_release_found = true;
+        }
+
+        if in_progress || is_latest || is_fleet_release || release.created_at >= released_since {
+            retained.insert(release.package_version);
+            retained.extend(release.previous_package_version);
+        }
+    }
+    Ok(retained)
+}
+
+/// Keys, content hashes and versions the packages that are kept refer to
+#[derive(Default)]
+struct References {
+    keys: HashSet<String>,
+    hashes: HashSet<String>,
+    versions: HashSet<i32>,
+}
+
+impl References {
+    fn add_package(&mut self, state: &AppState, package: &PackageEntryRead) {
+        self.versions.insert(package.version);
+        self.keys.extend(object_key(&state.env, &package.index).map(str::to_string));
+        self.hashes.extend(package.index_sha256.clone());
+        for files in [&package.important, &package.lazy, &package.resources] {
+            let files: Vec<File> = serde_json::from_value(files.clone()).unwrap_or_default();
+            for file in files {
+                self.keys.extend(object_key(&state.env, &file.url).map(str::to_string));
+                self.hashes.extend(file.sha256);
+            }
+        }
+    }
+
+    /// Files declared by an upload that has not been finalized yet
+    fn add_pending(&mut self, pending_package: &PendingPackage) {
+        self.versions.insert(pending_package.version);
+        let manifest = &pending_package.manifest;
+        let declared = std::iter::once(&manifest["index"]).chain(
+            ["important", "lazy", "resources"]
+                .into_iter()
+                .filter_map(|group| manifest[group].as_array())
+                .flatten(),
+        );
+        self.hashes.extend(
+            declared.filter_map(|file| file["sha256"].as_str().map(str::to_string)),
+        );
+    }
+
+    /// Whether an object under `assets/{organisation}/{application}/` is still needed.
+    /// Objects outside the layouts written by the server are always kept.
+    fn keeps(&self, prefix: &str, key: &str) -> bool {
+        if self.keys.contains(key) {
+            return true;
+        }
+        let Some(relative) = key.strip_prefix(prefix) else {
+            return true;
+        };
+        let segments: Vec<&str> = relative.split('/').collect();
+        let version_at = |index: usize| segments.get(index).and_then(|s| s.parse::<i32>().ok());
+
+        match segments.as_slice() {
+            ["objects", sha256] => self.hashes.contains(*sha256),
+            [_, "patches", _, ..] => match (version_at(0), version_at(2)) {
+                (Some(to_version), Some(from_version)) => {
+                    self.versions.contains(&to_version) && self.versions.contains(&from_version)
+                }
+                _ => true,
+            },
+            _ => version_at(0).is_none_or(|version| self.versions.contains(&version)),
+        }
+    }
+}
+
+fn load_packages(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+) -> Result<Vec<PackageEntryRead>, diesel::result::Error> {
+    packages::packages
+        .filter(packages::org_id.eq(organisation))
+        .filter(packages::app_id.eq(application))
+        .load::<PackageEntryRead>(conn)
+}
+
+/// Apply the retention rules of an application. With `dry_run`, nothing is changed and
+/// the report lists what would be removed.
+pub async fn collect_packages(
+    state: &AppState,
+    conn: &mut PgConnection,
+    app_settings: &ApplicationSettings,
+    dry_run: bool,
+) -> Result<CollectionReport, GcError> {
+    let organisation = app_settings.org_id.as_str();
+    let application = app_settings.app_id.as_str();
+    let Some(retain_package_versions) = app_settings.retain_package_versions else {
+        return Err("Package retention is not configured for this application".into());
+    };
+
+    let all_packages = load_packages(conn, organisation, application)?;
+    let retained = retained_versions(conn, app_settings, retain_package_versions, &all_packages)?;
+    let mut archived_versions: Vec<i32> = all_packages
+        .iter()
+        .filter(|package| package.archived_at.is_none() && !retained.contains(&package.version))
+        .map(|package| package.version)
+        .collect();
+    archived_versions.sort_unstable();
+
+    let kept_packages = if dry_run {
+        all_packages
+            .into_iter()
+            .filter(|package| package.archived_at.is_none() && retained.contains(&package.version))
+            .collect()
+    } else {
+        diesel::update(
+            packages::packages
+                .filter(packages::org_id.eq(organisation))
+                .filter(packages::app_id.eq(application))
+                .filter(packages::version.eq_any(&archived_versions))
+                .filter(packages::archived_at.is_null()),
+        )
+        .set(packages::archived_at.eq(Utc::now()))
+        .execute(conn)?;
+
+        // Reload, so packages created meanwhile keep the objects they reuse
+        let remaining: Vec<PackageEntryRead> = load_packages(conn, organisation, application)?
+            .into_iter()
+            .filter(|package| package.archived_at.is_none())
+            .collect();
+        let remaining_versions: Vec<i32> = remaining.iter().map(|package| package.version).collect();
+        diesel::delete(
+            patches::package_patches
+                .filter(patches::org_id.eq(organisation))
+                .filter(patches::app_id.eq(application))
+                .filter(
+                    patches::from_version
+                        .ne_all(&remaining_versions)
+                        .or(patches::to_version.ne_all(&remaining_versions)),
+                ),
+        )
+        .execute(conn)?;
+        remaining
+    };
+
+    let mut references = References::default();
+    for package in &kept_packages {
+        references.add_package(state, package);
+    }
+    let pending_packages: Vec<PendingPackage> = pending::pending_packages
+        .filter(pending::org_id.eq(organisation))
+        .filter(pending::app_id.eq(application))
+        .filter(pending::expires_at.gt(Utc::now()))
+        .select(PendingPackage::as_select())
+        .load(conn)?;
+    for pending_package in &pending_packages {
+        references.add_pending(pending_package);
+    }
+
+    let prefix = format!("assets/{}/{}/", organisation, application);
+    let grace_cutoff = Utc::now() - Duration::hours(GC_GRACE_HOURS);
+    let objects = list_objects(&state.s3_client, state.env.bucket_name.clone(), prefix.clone())
+        .await
+        .map_err(|e| format!("Failed to list objects under {}: {}", prefix, e))?;
+    let unreferenced: Vec<_> = objects
+        .into_iter()
+        .filter(|object| object.last_modified.is_some_and(|modified| modified < grace_cutoff))
+        .filter(|object| !references.keeps(&prefix, &object.key))
+        .collect();
+
+    let freed_bytes = unreferenced.iter().map(|object| object.size).sum();
+    let deleted_objects: Vec<String> = unreferenced.into_iter().map(|object| object.key).collect();
+    if !dry_run && !deleted_objects.is_empty() {
+        delete_objects(&state.s3_client, state.env.bucket_name.clone(), &deleted_objects)
+            .await
+            .map_err(|e| format!("Failed to delete objects: {}", e))?;
+    }
+
+    Ok(CollectionReport {
+        dry_run,
+        archived_versions,
+        deleted_objects,
+        freed_bytes,
+    })
+}
+
+/// Purge expired version reservations and collect the packages of every application with
+/// retention rules
+pub async fn run_package_gc(app_state: &web::Data<AppState>) -> Result<(), GcError> {
+    let mut conn = app_state.db_pool.get()?;
+
+    // Versions reserved by abandoned uploads or creates that never released them
+    let expired = diesel::delete(pending::pending_packages.filter(pending::expires_at.le(Utc::now())))
+        .execute(&mut conn)?;
+    if expired > 0 {
+        info!("Purged {} expired package version reservations", expired);
+    }
+
+    let configured: Vec<ApplicationSettings> = settings::application_settings
+        .filter(settings::retain_package_versions.is_not_null())
+        .select(ApplicationSettings::as_select())
+        .load(&mut conn)?;
+
+    if configured.is_empty() {
+        debug!("No applications with package retention rules");
+        return Ok(());
+    }
+
+    for app_settings in configured {
+        match collect_packages(app_state, &mut conn, &app_settings, false).await {
+            Ok(report) => info!(
+                "Package GC for {}/{}: archived versions {:?}, deleted {} objects ({} bytes)",
+                app_settings.org_id,
+                app_settings.app_id,
+                report.archived_versions,
+                report.deleted_objects.len(),
+                report.freed_bytes
+            ),
+            Err(e) => error!(
+                "Package GC failed for {}/{}: {}",
+                app_settings.org_id, app_settings.app_id, e
+            ),
+        }
+    }
+    Ok(())
+}
+
+pub fn start_package_gc(app_state: web::Data<AppState>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = run_package_gc(&app_state).await {
+                error!("Error running package garbage collection: {}", e);
+            }
+
+            tokio::time::sleep(std::time::Duration::from_secs(GC_INTERVAL_SECS)).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const PREFIX: &str = "assets/org/app/";
+
+    fn pending_package(version: i32, manifest: serde_json::Value) -> PendingPackage {
+        PendingPackage {
+            id: uuid::Uuid::new_v4(),
+            org_id: "org".to_string(),
+            app_id: "app".to_string(),
+            version,
+            manifest,
+            created_at: Utc::now(),
+            expires_at: Utc::now() + Duration::hours(1),
+        }
+    }
+
+    fn references(versions: &[i32], hashes: &[&str]) -> References {
+        References {
+            keys: HashSet::new(),
+            hashes: hashes.iter().map(|hash| hash.to_string()).collect(),
+            versions: versions.iter().copied().collect(),
+        }
+    }
+
+    #[test]
+    fn versioned_objects_follow_their_package() {
+        let refs = references(&[3], &[]);
+        assert!(refs.keeps(PREFIX, "assets/org/app/3/index.js"));
+        assert!(refs.keeps(PREFIX, "assets/org/app/3/nested/file.png"));
+        assert!(!refs.keeps(PREFIX, "assets/org/app/2/index.js"));
+    }
+
+    #[test]
+    fn content_objects_follow_their_hash() {
+        let refs = references(&[], &["abc"]);
+        assert!(refs.keeps(PREFIX, "assets/org/app/objects/abc"));
+        assert!(!refs.keeps(PREFIX, "assets/org/app/objects/def"));
+    }
+
+    #[test]
+    fn patches_need_both_ends() {
+        let refs = references(&[2, 3], &[]);
+        assert!(refs.keeps(PREFIX, "assets/org/app/3/patches/2/index.js.patch"));
+        assert!(!refs.keeps(PREFIX, "assets/org/app/3/patches/1/index.js.patch"));
+        assert!(!refs.keeps(PREFIX, "assets/org/app/4/patches/2/index.js.patch"));
+    }
+
+    #[test]
+    fn unknown_layouts_are_kept() {
+        let refs = references(&[], &[]);
+        assert!(refs.keeps(PREFIX, "assets/org/other/1/index.js"));
+        assert!(refs.keeps(PREFIX, "assets/org/app/custom/file.txt"));
+        assert!(refs.keeps(PREFIX, "assets/org/app/4/patches/latest/index.js.patch"));
+    }
+
+    #[test]
+    fn referenced_keys_are_kept() {
+        let mut refs = references(&[], &[]);
+        refs.keys.insert("assets/org/app/1/index.js".to_string());
+        assert!(refs.keeps(PREFIX, "assets/org/app/1/index.js"));
+        assert!(!refs.keeps(PREFIX, "assets/org/app/1/other.js"));
+    }
+
+    #[test]
+    fn pending_uploads_keep_their_declared_files() {
+        let mut refs = References::default();
+        refs.add_pending(&pending_package(
+            5,
+            json!({
+                "index": { "filePath": "index.js", "sha256": "aaa" },
+                "important": [{ "filePath": "a.js", "sha256": "bbb" }],
+                "lazy": [{ "filePath": "b.js" }],
+                "resources": [{ "filePath": "c.png", "sha256": "ccc" }]
+            }),
+        ));
+        assert!(refs.keeps(PREFIX, "assets/org/app/5/index.js"));
+        for hash in ["aaa", "bbb", "ccc"] {
+            assert!(refs.keeps(PREFIX, &format!("assets/org/app/objects/{hash}")));
+        }
+        assert!(!refs.keeps(PREFIX, "assets/org/app/objects/ddd"));
+    }
+
+    #[test]
+    fn pending_uploads_without_files_keep_their_version() {
+        let mut refs = References::default();
+        refs.add_pending(&pending_package(6, json!({})));
+        assert!(refs.keeps(PREFIX, "assets/org/app/6/index.js"));
+        assert!(refs.hashes.is_empty());
+    }
+}
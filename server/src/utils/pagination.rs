@@ -0,0 +1,111 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Cursor-based pagination of listings. A cursor is the sort key of the last item of a
+// page, encoded so clients treat it as opaque.
+
+use actix_web::error;
+use base64::{engine::general_purpose, Engine};
+use serde::Deserialize;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// Number of items to return, validated against `MAX_PAGE_SIZE`
+pub fn page_size(limit: Option<i64>) -> actix_web::Result<i64> {
+    match limit {
+        None => Ok(DEFAULT_PAGE_SIZE),
+        Some(limit) if (1..=MAX_PAGE_SIZE).contains(&limit) => Ok(limit),
+        Some(_) => Err(error::ErrorBadRequest(format!(
+            "limit must be between 1 and {}",
+            MAX_PAGE_SIZE
+        ))),
+    }
+}
+
+pub fn encode_cursor(key: &str) -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode(key)
+}
+
+pub fn decode_cursor(cursor: &str) -> actix_web::Result<String> {
+    general_purpose::URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|key| String::from_utf8(key).ok())
+        .ok_or_else(|| error::ErrorBadRequest("Invalid cursor"))
+}
+
+/// Split a page fetched with one extra item into the page and the cursor of the next one
+pub fn paginate<T>(
+    mut items: Vec<T>,
+    limit: i64,
+    cursor_key: impl Fn(&T) -> String,
+) -> (Vec<T>, Option<String>) {
+    if items.len() as i64 <= limit {
+        return (items, None);
+    }
+    items.truncate(limit as usize);
+    let next_cursor = items.last().map(|item| encode_cursor(&cursor_key(item)));
+    (items, next_cursor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_size_defaults_and_bounds() {
+        assert_eq!(page_size(None).unwrap(), DEFAULT_PAGE_SIZE);
+        assert_eq!(page_size(Some(1)).unwrap(), 1);
+        assert_eq!(page_size(Some(MAX_PAGE_SIZE)).unwrap(), MAX_PAGE_SIZE);
+        assert!(page_size(Some(0)).is_err());
+        assert!(page_size(Some(-1)).is_err());
+        assert!(page_size(Some(MAX_PAGE_SIZE + 1)).is_err());
+    }
+
+    #[test]
+    fn cursor_round_trips() {
+        let key = "2025-07-01T10:00:00Z/5c1e0d5e-0b8f-4c55-9d0a-3b0f6a1f2e11";
+        assert_eq!(decode_cursor(&encode_cursor(key)).unwrap(), key);
+    }
+
+    #[test]
+    fn invalid_cursors_are_rejected() {
+        assert!(decode_cursor("not a cursor!").is_err());
+        // Valid base64 that is not UTF-8
+        assert!(decode_cursor(&general_purpose::URL_SAFE_NO_PAD.encode([0xff, 0xfe])).is_err());
+    }
+
+    #[test]
+    fn paginate_stops_at_the_last_page() {
+        let (items, next) = paginate(vec![1, 2, 3], 3, i32::to_string);
+        assert_eq!(items, vec![1, 2, 3]);
+        assert_eq!(next, None);
+    }
+
+    #[test]
+    fn paginate_returns_cursor_of_the_last_item() {
+        let (items, next) = paginate(vec![1, 2, 3, 4], 3, i32::to_string);
+        assert_eq!(items, vec![1, 2, 3]);
+        assert_eq!(next.map(|cursor| decode_cursor(&cursor).unwrap()), Some("3".to_string()));
+    }
+}
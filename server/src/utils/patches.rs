@@ -0,0 +1,293 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Binary delta patches between package versions. When a package is created, its index and
+// each of its files that changed since one of the previous versions are diffed (bsdiff)
+// against that version's copy. Patches are stored in S3 next to the package's assets.
+// Sources are only read from the application's content-addressed objects, never from the
+// URLs stored on packages, which clients may supply.
+
+use std::{collections::HashMap, time::Duration};
+
+use actix_web::web;
+use aws_sdk_s3::config::timeout::TimeoutConfig;
+use chrono::Utc;
+use diesel::prelude::*;
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    types::AppState,
+    utils::{
+        db::{
+            models::{File, PackageEntryRead, PackagePatch},
+            schema::hyperotaserver::{package_patches::dsl as patches, packages::dsl as packages},
+        },
+        s3::{content_key, object_url, push_bytes},
+    },
+};
+
+/// Files larger than this are not diffed; bsdiff needs several times the input in memory
+const MAX_PATCH_SOURCE_BYTES: usize = 32 * 1024 * 1024;
+
+const DOWNLOAD_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time a single file download may take, including reading the body
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Path the package index is patched under. Package file paths are relative, so it cannot
+/// clash with one of them.
+pub const INDEX_PATCH_PATH: &str = "@index";
+
+type PatchError = Box<dyn std::error::Error + Send + Sync>;
+
+/// All files of a package by path: the index, important, lazy and resources
+fn package_files(package: &PackageEntryRead) -> HashMap<String, File> {
+    let index = File {
+        url: package.index.clone(),
+        file_path: INDEX_PATCH_PATH.to_string(),
+        sha256: package.index_sha256.clone(),
+        size: package.index_size,
+    };
+    [&package.important, &package.lazy, &package.resources]
+        .into_iter()
+        .flat_map(|files| serde_json::from_value::<Vec<File>>(files.clone()).unwrap_or_default())
+        .chain(std::iter::once(index))
+        .map(|file| (file.file_path.clone(), file))
+        .collect()
+}
+
+/// Whether a file differs between two versions. Hashes are compared when both are known.
+fn file_changed(old: &File, new: &File) -> bool {
+    match (&old.sha256, &new.sha256) {
+        (Some(old_sha256), Some(new_sha256)) => old_sha256 != new_sha256,
+        _ => old.url != new.url,
+    }
+}
+
+/// Content of a package file, fetched from the application's content-addressed store in the
+/// server's own bucket by its declared SHA-256. Files without a hash, including those only
+/// known by an externally supplied URL, are never fetched.
+async fn download(
+    state: &AppState,
+    organisation: &str,
+    application: &str,
+    file: &File,
+) -> Result<Vec<u8>, PatchError> {
+    let sha256 = file
+        .sha256
+        .as_deref()
+        .filter(|sha256| sha256.len() == 64 && sha256.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or_else(|| format!("{} has no valid sha256", file.file_path))?;
+    let key = content_key(organisation, application, sha256);
+
+    let fetch = async {
+        let object = state
+            .s3_client
+            .get_object()
+            .bucket(&state.env.bucket_name)
+            .key(&key)
+            .customize()
+            .config_override(
+                aws_sdk_s3::config::Builder::new().timeout_config(
+                    TimeoutConfig::builder()
+                        .connect_timeout(DOWNLOAD_CONNECT_TIMEOUT)
+                        .build(),
+                ),
+            )
+            .send()
+            .await?;
+        if object
+            .content_length()
+            .is_some_and(|length| length as usize > MAX_PATCH_SOURCE_BYTES)
+        {
+            return Err(format!("{} is too large to diff", key).into());
+        }
+        let bytes = object.body.collect().await?.into_bytes();
+        Ok::<_, PatchError>(bytes)
+    };
+    let bytes = tokio::time::timeout(DOWNLOAD_TIMEOUT, fetch)
+        .await
+        .map_err(|_| format!("Timed out downloading {}", key))??;
+
+    if bytes.len() > MAX_PATCH_SOURCE_BYTES {
+        return Err(format!("{} is too large to diff", key).into());
+    }
+    if format!("{:x}", Sha256::digest(&bytes)) != sha256.to_ascii_lowercase() {
+        return Err(format!("{} does not match its sha256", key).into());
+    }
+    Ok(bytes.to_vec())
+}
+
+async fn diff(old: Vec<u8>, new: Vec<u8>) -> Result<Vec<u8>, PatchError> {
+    let patch = tokio::task::spawn_blocking(move || {
+        let mut patch = Vec::new();
+        bsdiff::diff(&old, &new, &mut patch).map(|_| patch)
+    })
+    .await??;
+    Ok(patch)
+}
+
+/// S3 key of the patch turning `file_path` of `from_version` into that of `to_version`
+fn patch_key(
+    organisation: &str,
+    application: &str,
+    from_version: i32,
+    to_version: i32,
+    file_path: &str,
+) -> String {
+    format!(
+        "assets/{}/{}/{}/patches/{}/{}.bsdiff",
+        organisation, application, to_version, from_version, file_path
+    )
+}
+
+/// Generate the patches of a new package version from the configured number of previous
+/// versions. Returns how many patches were stored.
+pub async fn generate_package_patches(
+    state: &AppState,
+    organisation: &str,
+    application: &str,
+    to_version: i32,
+) -> Result<usize, PatchError> {
+    if state.env.patch_base_versions <= 0 {
+        return Ok(0);
+    }
+    // Connections are only held around queries; downloads and diffs can take minutes
+    let (new_package, base_packages) = {
+        let mut conn = state.db_pool.get()?;
+        let new_package = packages::packages
+            .filter(packages::org_id.eq(organisation))
+            .filter(packages::app_id.eq(application))
+            .filter(packages::version.eq(to_version))
+            .first::<PackageEntryRead>(&mut conn)?;
+        let base_packages = packages::packages
+            .filter(packages::org_id.eq(organisation))
+            .filter(packages::app_id.eq(application))
+            .filter(packages::version.lt(to_version))
+            .filter(packages::archived_at.is_null())
+            .order_by(packages::version.desc())
+            .limit(state.env.patch_base_versions)
+            .load::<PackageEntryRead>(&mut conn)?;
+        (new_package, base_packages)
+    };
+
+    let new_files = package_files(&new_package);
+    let mut new_contents: HashMap<String, Vec<u8>> = HashMap::new();
+    let mut stored = 0;
+
+    for base in base_packages {
+        for (file_path, old_file) in package_files(&base) {
+            let Some(new_file) = new_files.get(&file_path) else {
+                continue;
+            };
+            if !file_changed(&old_file, new_file) {
+                continue;
+            }
+
+            let new_content = match new_contents.get(&file_path) {
+                Some(content) => content.clone(),
+                None => match download(state, organisation, application, new_file).await {
+                    Ok(content) => {
+                        new_contents.insert(file_path.clone(), content.clone());
+                        content
+                    }
+                    Err(e) => {
+                        warn!("Skipping patches for {}: {}", file_path, e);
+                        continue;
+                    }
+                },
+            };
+            let old_content = match download(state, organisation, application, &old_file).await {
+                Ok(content) => content,
+                Err(e) => {
+                    warn!("Skipping patch of {} from v{}: {}", file_path, base.version, e);
+                    continue;
+                }
+            };
+
+            let new_size = new_content.len();
+            let patch = diff(old_content, new_content).await?;
+            // A patch no smaller than the file saves nothing
+            if patch.len() >= new_size {
+                continue;
+            }
+
+            let sha256 = format!("{:x}", Sha256::digest(&patch));
+            let size = patch.len() as i64;
+            let key = patch_key(organisation, application, base.version, to_version, &file_path);
+            push_bytes(&state.s3_client, state.env.bucket_name.clone(), patch, key.clone())
+                .await
+                .map_err(|e| format!("Failed to upload patch {}: {}", key, e))?;
+
+            diesel::insert_into(patches::package_patches)
+                .values(PackagePatch {
+                    org_id: organisation.to_string(),
+                    app_id: application.to_string(),
+                    from_version: base.version,
+                    to_version,
+                    file_path: file_path.clone(),
+                    url: object_url(&state.env, &key),
+                    sha256,
+                    size,
+                    created_at: Utc::now(),
+                })
+                .on_conflict_do_nothing()
+                .execute(&mut state.db_pool.get()?)?;
+            stored += 1;
+        }
+    }
+
+    Ok(stored)
+}
+
+/// Generate patches for a new package in the background, so package creation does not wait
+pub fn spawn_patch_generation(
+    state: web::Data<AppState>,
+    organisation: String,
+    application: String,
+    to_version: i32,
+) {
+    tokio::spawn(async move {
+        match generate_package_patches(&state, &organisation, &application, to_version).await {
+            Ok(count) => info!(
+                "Generated {} patches for {}/{} v{}",
+                count, organisation, application, to_version
+            ),
+            Err(e) => warn!(
+                "Failed to generate patches for {}/{} v{}: {}",
+                organisation, application, to_version, e
+            ),
+        }
+    });
+}
+
+/// Patches from one package version to another, by file path
+pub fn find_patches(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    from_version: i32,
+    to_version: i32,
+) -> Result<HashMap<String, PackagePatch>, diesel::result::Error> {
+    Ok(patches::package_patches
+        .filter(patches::org_id.eq(organisation))
+        .filter(patches::app_id.eq(application))
+        .filter(patches::from_version.eq(from_version))
+        .filter(patches::to_version.eq(to_version))
+        .select(PackagePatch::as_select())
+        .load(conn)?
+        .into_iter()
+        .map(|patch| (patch.file_path.clone(), patch))
+        .collect())
+}
@@ -0,0 +1,331 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Release health: boot and update outcomes reported by devices are counted per release,
+// and a background job rolls back releases whose failure rates exceed the thresholds
+// configured in the application settings.
+
+use std::collections::HashSet;
+
+use actix_web::web;
+use chrono::Utc;
+use diesel::{prelude::*, upsert::excluded};
+use log::{debug, error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    types::AppState,
+    utils::{
+        db::{
+            models::{ReleaseEntry, ReleaseHealth, ReleaseHealthReport, ReleaseStatus},
+            schema::hyperotaserver::{
+                release_health::dsl as health, release_health_reports::dsl as reports,
+                releases::dsl as releases,
+            },
+        },
+        release_lifecycle::{abort_release, rollback_release},
+        settings::find_settings,
+        workspace::get_workspace_name_for_application,
+    },
+};
+
+const WATCHER_INTERVAL_SECS: u64 = 60;
+
+/// Recorded as `created_by` of releases created by an automatic rollback
+const HEALTH_WATCHER_USER: &str = "system:health-watcher";
+
+/// Statuses in which a release is still spreading to devices and can be rolled back
+const WATCHED_STATUSES: [ReleaseStatus; 2] = [ReleaseStatus::Ramping, ReleaseStatus::Paused];
+
+/// Outcomes counted for one release in a telemetry batch
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HealthCounts {
+    pub boots: i64,
+    pub boot_failures: i64,
+    pub updates: i64,
+    pub package_timeouts: i64,
+}
+
+/// Outcome of a device's boot or update, counted once per device and release
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HealthOutcome {
+    Boot,
+    BootFailure,
+    Update,
+    PackageTimeout,
+}
+
+impl HealthOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HealthOutcome::Boot => "boot",
+            HealthOutcome::BootFailure => "boot_failure",
+            HealthOutcome::Update => "update",
+            HealthOutcome::PackageTimeout => "package_timeout",
+        }
+    }
+}
+
+/// Record which devices reported which outcomes for a release and add the devices not
+/// counted before to its totals, so a device repeating an outcome cannot skew the rates
+pub fn record_device_outcomes(
+    conn: &mut PgConnection,
+    release_id: Uuid,
+    outcomes: &HashSet<(String, HealthOutcome)>,
+) -> Result<HealthCounts, diesel::result::Error> {
+    conn.transaction(|conn| {
+        let now = Utc::now();
+        let mut counts = HealthCounts::default();
+        for outcome in [
+            HealthOutcome::Boot,
+            HealthOutcome::BootFailure,
+            HealthOutcome::Update,
+            HealthOutcome::PackageTimeout,
+        ] {
+            let rows: Vec<ReleaseHealthReport> = outcomes
+                .iter()
+                .filter(|(_, reported)| *reported == outcome)
+                .map(|(device_id, _)| ReleaseHealthReport {
+                    release_id,
+                    device_id: device_id.clone(),
+                    outcome: outcome.as_str().to_string(),
+                    reported_at: now,
+                })
+                .collect();
+            if rows.is_empty() {
+                continue;
+            }
+            let new_devices = diesel::insert_into(reports::release_health_reports)
+                .values(&rows)
+                .on_conflict_do_nothing()
+                .execute(conn)? as i64;
+            match outcome {
+                HealthOutcome::Boot => counts.boots = new_devices,
+                HealthOutcome::BootFailure => counts.boot_failures = new_devices,
+                HealthOutcome::Update => counts.updates = new_devices,
+                HealthOutcome::PackageTimeout => counts.package_timeouts = new_devices,
+            }
+        }
+        if counts.boots + counts.boot_failures + counts.updates > 0 {
+            record_health(conn, release_id, counts)?;
+        }
+        Ok(counts)
+    })
+}
+
+/// Add a batch of outcomes to the totals of a release
+fn record_health(
+    conn: &mut PgConnection,
+    release_id: Uuid,
+    counts: HealthCounts,
+) -> Result<usize, diesel::result::Error> {
+    let row = ReleaseHealth {
+        release_id,
+        boots: counts.boots,
+        boot_failures: counts.boot_failures,
+        updates: counts.updates,
+        package_timeouts: counts.package_timeouts,
+        updated_at: Utc::now(),
+    };
+
+    diesel::insert_into(health::release_health)
+        .values(&row)
+        .on_conflict(health::release_id)
+        .do_update()
+        .set((
+            health::boots.eq(health::boots + excluded(health::boots)),
+            health::boot_failures.eq(health::boot_failures + excluded(health::boot_failures)),
+            health::updates.eq(health::updates + excluded(health::updates)),
+            health::package_timeouts
+                .eq(health::package_timeouts + excluded(health::package_timeouts)),
+            health::updated_at.eq(excluded(health::updated_at)),
+        ))
+        .execute(conn)
+}
+
+/// Release that reports for a package version are attributed to: the latest public one of
+/// that version still being served. Reports that don't say which package they are about
+/// are not attributed at all, as they may come from any release.
+pub fn find_reporting_release(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    package_version: i32,
+) -> Result<Option<Uuid>, diesel::result::Error> {
+    releases::releases
+        .filter(releases::org_id.eq(organisation))
+        .filter(releases::app_id.eq(application))
+        .filter(releases::package_version.eq(package_version))
+        .filter(releases::tester_group_id.is_null())
+        .filter(releases::status.eq_any([
+            ReleaseStatus::Ramping.as_str(),
+            ReleaseStatus::Paused.as_str(),
+            ReleaseStatus::Live.as_str(),
+        ]))
+        .order_by(releases::created_at.desc())
+        .select(releases::id)
+        .first(conn)
+        .optional()
+}
+
+pub fn find_health(
+    conn: &mut PgConnection,
+    release_id: Uuid,
+) -> Result<Option<ReleaseHealth>, diesel::result::Error> {
+    health::release_health
+        .find(release_id)
+        .select(ReleaseHealth::as_select())
+        .first(conn)
+        .optional()
+}
+
+/// Failure rate, once enough reports have been received to trust it
+fn rate(failures: i64, total: i64, min_samples: i32) -> Option<f64> {
+    (total > 0 && total >= i64::from(min_samples)).then(|| failures as f64 / total as f64)
+}
+
+/// Reason a release should be rolled back, if any threshold of its application is exceeded
+fn unhealthy_reason(
+    conn: &mut PgConnection,
+    release: &ReleaseEntry,
+    release_health: &ReleaseHealth,
+) -> Result<Option<String>, diesel::result::Error> {
+    let Some(settings) = find_settings(&release.org_id, &release.app_id, conn)? else {
+        return Ok(None);
+    };
+
+    let boot_failure_rate = rate(
+        release_health.boot_failures,
+        release_health.boots + release_health.boot_failures,
+        settings.min_health_samples,
+    );
+    if let (Some(rate), Some(max)) = (boot_failure_rate, settings.max_boot_failure_rate) {
+        if rate > max {
+            return Ok(Some(format!(
+                "boot failure rate {:.4} exceeds {:.4}",
+                rate, max
+            )));
+        }
+    }
+
+    let package_timeout_rate = rate(
+        release_health.package_timeouts,
+        release_health.updates,
+        settings.min_health_samples,
+    );
+    if let (Some(rate), Some(max)) = (package_timeout_rate, settings.max_package_timeout_rate) {
+        if rate > max {
+            return Ok(Some(format!(
+                "package timeout rate {:.4} exceeds {:.4}",
+                rate, max
+            )));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Roll back an unhealthy release. If it cannot be rolled back, e.g. because a newer
+/// release exists or there is nothing to restore, it is only aborted.
+async fn roll_back_unhealthy(
+    state: &AppState,
+    conn: &mut PgConnection,
+    release: &ReleaseEntry,
+    reason: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let metadata = serde_json::json!({
+        "automatic_rollback": { "release_id": release.id.to_string(), "reason": reason }
+    });
+
+    let rollback = rollback_release(
+        state,
+        conn,
+        &release.org_id,
+        &release.app_id,
+        release.id,
+        HEALTH_WATCHER_USER.to_string(),
+        metadata,
+    )
+    .await
+    // actix errors are not Send, so only their message is kept across the next await
+    .map_err(|e| e.to_string());
+    let rollback_error = match rollback {
+        Ok(restored) => {
+            info!(
+                "Rolled back release {} ({}) to package version {}",
+                release.id, reason, restored.package_version
+            );
+            return Ok(());
+        }
+        Err(e) => e,
+    };
+
+    warn!(
+        "Could not roll back release {}: {}. Aborting it instead.",
+        release.id, rollback_error
+    );
+    let workspace_name =
+        get_workspace_name_for_application(&release.app_id, &release.org_id, conn)
+            .await
+            .map_err(|e| format!("Failed to get workspace name: {}", e))?;
+    abort_release(state, conn, release, &workspace_name)
+        .await
+        .map_err(|e| format!("Failed to abort release {}: {}", release.id, e))?;
+    info!("Aborted release {} ({})", release.id, reason);
+    Ok(())
+}
+
+/// Check every release still rolling out against the thresholds of its application
+pub async fn check_release_health(
+    app_state: &web::Data<AppState>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = app_state.db_pool.get()?;
+
+    let watched: Vec<ReleaseEntry> = releases::releases
+        .filter(releases::status.eq_any(WATCHED_STATUSES.map(|s| s.as_str())))
+        .select(ReleaseEntry::as_select())
+        .load(&mut conn)?;
+
+    if watched.is_empty() {
+        debug!("No rolling out releases to check");
+        return Ok(());
+    }
+
+    for release in watched {
+        let Some(release_health) = find_health(&mut conn, release.id)? else {
+            continue;
+        };
+        let Some(reason) = unhealthy_reason(&mut conn, &release, &release_health)? else {
+            continue;
+        };
+
+        warn!("Release {} is unhealthy: {}", release.id, reason);
+        if let Err(e) = roll_back_unhealthy(app_state, &mut conn, &release, &reason).await {
+            error!("{}", e);
+        }
+    }
+    Ok(())
+}
+
+pub fn start_health_watcher(app_state: web::Data<AppState>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = check_release_health(&app_state).await {
+                error!("Error checking release health: {}", e);
+            }
+
+            tokio::time::sleep(std::time::Duration::from_secs(WATCHER_INTERVAL_SECS)).await;
+        }
+    })
+}
@@ -0,0 +1,587 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::error;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use log::{error, info};
+use superposition_rust_sdk::{
+    apis::default_api::{
+        conclude_experiment, create_experiment, discard_experiment, ramp_experiment,
+    },
+    models,
+};
+use uuid::Uuid;
+
+use crate::{
+    release::invalidate_release_cache,
+    types::AppState,
+    utils::{
+        db::{
+            models::{ReleaseEntry, ReleaseStatus},
+            schema::hyperotaserver::{
+                packages,
+                releases::{self, dsl},
+            },
+        },
+        workspace::get_workspace_name_for_application,
+    },
+};
+
+/// Traffic given to the experimental variant when a release starts ramping. Both variants
+/// carry the same overrides and 50 is the most two variants allow, so this exposes the
+/// release to its whole context; which devices get it is decided by `rollout_percentage`.
+const EXPERIMENT_TRAFFIC_PERCENTAGE: i32 = 50;
+
+#[derive(AsChangeset, Default)]
+#[diesel(table_name = releases)]
+struct StatusChange {
+    status: String,
+    rollout_percentage: Option<i32>,
+    ramped_at: Option<DateTime<Utc>>,
+    paused_at: Option<DateTime<Utc>>,
+    live_at: Option<DateTime<Utc>>,
+    concluded_at: Option<DateTime<Utc>>,
+    aborted_at: Option<DateTime<Utc>>,
+}
+
+pub fn release_status(release: &ReleaseEntry) -> Result<ReleaseStatus, actix_web::Error> {
+    ReleaseStatus::parse(&release.status).ok_or_else(|| {
+        error::ErrorInternalServerError(format!("Unknown release status: {}", release.status))
+    })
+}
+
+/// Whether a release may move from one status to another
+pub fn can_transition(from: ReleaseStatus, to: ReleaseStatus) -> bool {
+    use ReleaseStatus::*;
+    matches!(
+        (from, to),
+        (Draft, Ramping | Live | Aborted)
+            | (Ramping, Ramping | Paused | Live | Aborted)
+            | (Paused, Ramping | Live | Aborted)
+            | (Live, Concluded | Aborted)
+    )
+}
+
+/// Persist a validated transition. The update only applies if the release is still in the
+/// status it was read with, so concurrent transitions cannot both succeed.
+fn apply_transition(
+    conn: &mut PgConnection,
+    release: &ReleaseEntry,
+    to: ReleaseStatus,
+    rollout_percentage: Option<i32>,
+) -> Result<ReleaseEntry, actix_web::Error> {
+    let from = release_status(release)?;
+    if !can_transition(from, to) {
+        return Err(error::ErrorConflict(format!(
+            "Release cannot move from {} to {}",
+            from.as_str(),
+            to.as_str()
+        )));
+    }
+
+    let now = Utc::now();
+    let mut change = StatusChange {
+        status: to.as_str().to_string(),
+        rollout_percentage,
+        ..Default::default()
+    };
+    match to {
+        ReleaseStatus::Ramping if release.ramped_at.is_none() => change.ramped_at = Some(now),
+        ReleaseStatus::Live => {
+            change.live_at = Some(now);
+            if release.ramped_at.is_none() {
+                change.ramped_at = Some(now);
+            }
+        }
+        ReleaseStatus::Paused => change.paused_at = Some(now),
+        ReleaseStatus::Concluded => change.concluded_at = Some(now),
+        ReleaseStatus::Aborted => change.aborted_at = Some(now),
+        _ => {}
+    }
+
+    diesel::update(
+        dsl::releases.filter(dsl::id.eq(release.id).and(dsl::status.eq(&release.status))),
+    )
+    .set(&change)
+    .returning(ReleaseEntry::as_returning())
+    .get_result(conn)
+    .optional()
+    .map_err(error::ErrorInternalServerError)?
+    .ok_or_else(|| error::ErrorConflict("Release status changed concurrently, retry"))
+}
+
+fn experiment_of(release: &ReleaseEntry) -> Result<&str, actix_web::Error> {
+    release
+        .experiment_id
+        .as_deref()
+        .ok_or_else(|| error::ErrorConflict("Release has no Superposition experiment"))
+}
+
+/// Serve the release to `rollout_percentage` of devices, ramping its experiment the first
+/// time. The release becomes live at 100%. If Superposition fails, the status is unchanged.
+pub async fn ramp_release(
+    state: &AppState,
+    conn: &mut PgConnection,
+    release: &ReleaseEntry,
+    workspace_name: &str,
+    rollout_percentage: i32,
+) -> Result<ReleaseEntry, actix_web::Error> {
+    if !(0..=100).contains(&rollout_percentage) {
+        return Err(error::ErrorBadRequest(
+            "rollout_percentage must be between 0 and 100",
+        ));
+    }
+    let to = if rollout_percentage == 100 {
+        ReleaseStatus::Live
+    } else {
+        ReleaseStatus::Ramping
+    };
+    let from = release_status(release)?;
+    if !can_transition(from, to) {
+        return Err(error::ErrorConflict(format!(
+            "Release cannot move from {} to {}",
+            from.as_str(),
+            to.as_str()
+        )));
+    }
+
+    if release.ramped_at.is_none() {
+        let experiment = experiment_of(release)?;
+        ramp_experiment(
+            &state.superposition_configuration,
+            experiment,
+            &state.env.superposition_org_id,
+            workspace_name,
+            models::RampExperimentRequestContent {
+                change_reason: format!("Ramping release {}", release.id),
+                traffic_percentage: EXPERIMENT_TRAFFIC_PERCENTAGE,
+            },
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to ramp experiment {}: {:?}", experiment, e);
+            error::ErrorBadGateway("Failed to ramp experiment in Superposition")
+        })?;
+    }
+
+    let updated = apply_transition(conn, release, to, Some(rollout_percentage))?;
+    invalidate_release_cache(state, &updated.org_id, &updated.app_id);
+    Ok(updated)
+}
+
+/// Freeze a rollout at its current percentage. The Superposition experiment keeps running,
+/// but paused rollouts are only served to devices in the rollout that already report the
+/// release's package in `x-package-version`, so no new devices get it.
+pub fn pause_release(
+    state: &AppState,
+    conn: &mut PgConnection,
+    release: &ReleaseEntry,
+) -> Result<ReleaseEntry, actix_web::Error> {
+    let updated = apply_transition(conn, release, ReleaseStatus::Paused, None)?;
+    invalidate_release_cache(state, &updated.org_id, &updated.app_id);
+    Ok(updated)
+}
+
+/// Conclude the experiment of a live release, making its overrides part of the base config
+pub async fn conclude_release(
+    state: &AppState,
+    conn: &mut PgConnection,
+    release: &ReleaseEntry,
+    workspace_name: &str,
+) -> Result<ReleaseEntry, actix_web::Error> {
+    let from = release_status(release)?;
+    if !can_transition(from, ReleaseStatus::Concluded) {
+        return Err(error::ErrorConflict(format!(
+            "Release cannot move from {} to concluded",
+            from.as_str()
+        )));
+    }
+
+    let experiment = experiment_of(release)?;
+    conclude_experiment(
+        &state.superposition_configuration,
+        experiment,
+        &state.env.superposition_org_id,
+        workspace_name,
+        models::ConcludeExperimentRequestContent {
+            // Superposition prefixes variant ids with the experiment id
+            chosen_variant: format!("{}-experimental", experiment),
+            description: None,
+            change_reason: format!("Concluding release {}", release.id),
+        },
+    )
+    .await
+    .map_err(|e| {
+        error!("Failed to conclude experiment {}: {:?}", experiment, e);
+        error::ErrorBadGateway("Failed to conclude experiment in Superposition")
+    })?;
+
+    let updated = apply_transition(conn, release, ReleaseStatus::Concluded, None)?;
+    invalidate_release_cache(state, &updated.org_id, &updated.app_id);
+    Ok(updated)
+}
+
+/// Discard the experiment of a release so none of its overrides are served any more
+pub async fn abort_release(
+    state: &AppState,
+    conn: &mut PgConnection,
+    release: &ReleaseEntry,
+    workspace_name: &str,
+) -> Result<ReleaseEntry, actix_web::Error> {
+    let from = release_status(release)?;
+    if !can_transition(from, ReleaseStatus::Aborted) {
+        return Err(error::ErrorConflict(format!(
+            "Release cannot move from {} to aborted",
+            from.as_str()
+        )));
+    }
+
+    // Releases created before experiments were recorded have nothing to discard
+    if let Some(experiment) = &release.experiment_id {
+        discard_experiment(
+            &state.superposition_configuration,
+            experiment,
+            &state.env.superposition_org_id,
+            workspace_name,
+            models::DiscardExperimentRequestContent {
+                change_reason: format!("Aborting release {}", release.id),
+            },
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to discard experiment {}: {:?}", experiment, e);
+            error::ErrorBadGateway("Failed to discard experiment in Superposition")
+        })?;
+    }
+
+    let updated = apply_transition(conn, release, ReleaseStatus::Aborted, None)?;
+    invalidate_release_cache(state, &updated.org_id, &updated.app_id);
+    Ok(updated)
+}
+
+/// Ramp a newly created release. A failed ramp leaves it in draft to be ramped again later.
+pub async fn start_release(
+    state: &AppState,
+    conn: &mut PgConnection,
+    release: ReleaseEntry,
+    workspace_name: &str,
+    rollout: i32,
+) -> ReleaseEntry {
+    match ramp_release(state, conn, &release, workspace_name, rollout).await {
+        Ok(ramped) => ramped,
+        Err(e) => {
+            error!(
+                "Failed to ramp release {}: {}. It stays in draft until ramped again.",
+                release.id, e
+            );
+            release
+        }
+    }
+}
+
+/// Create the Superposition experiment pointing `context` at a package version.
+/// It only takes effect once ramped through `ramp_release`.
+pub async fn create_release_experiment(
+    state: &AppState,
+    workspace_name: &str,
+    application: &str,
+    release_id: Uuid,
+    pkg_version: i32,
+    context_map: std::collections::HashMap<String, serde_json::Value>,
+    change_reason: String,
+) -> Result<String, actix_web::Error> {
+    let superposition_org_id_from_env = state.env.superposition_org_id.clone();
+
+    // Create control variant with release configuration
+    let mut control_overrides = std::collections::HashMap::new();
+    control_overrides.insert("package.version".to_string(), serde_json::json!(pkg_version));
+
+    // Create experimental variant with same overrides
+    let experimental_overrides = control_overrides.clone();
+
+    // Create variants
+    let control_variant = models::Variant {
+        id: "control".to_string(),
+        variant_type: models::VariantType::Control,
+        context_id: None,
+        override_id: None,
+        overrides: Some(serde_json::Value::Object(serde_json::Map::from_iter(
+            control_overrides,
+        ))),
+    };
+
+    let experimental_variant = models::Variant {
+        id: "experimental".to_string(),
+        variant_type: models::VariantType::Experimental,
+        context_id: None,
+        override_id: None,
+        overrides: Some(serde_json::Value::Object(serde_json::Map::from_iter(
+            experimental_overrides,
+        ))),
+    };
+
+    // Create experiment in Superposition
+    let experiment_content = models::CreateExperimentRequestContent::new(
+        format!("{}_release_{}", application, release_id),
+        context_map,
+        vec![control_variant, experimental_variant],
+        format!(
+            "Creating release for application '{}' with version {} and ID {}",
+            application, pkg_version, release_id
+        ),
+        change_reason,
+    );
+
+    let created_experiment_response = create_experiment(
+        &state.superposition_configuration,
+        &superposition_org_id_from_env,
+        workspace_name,
+        experiment_content,
+    )
+    .await
+    .map_err(|e| {
+        error!("Failed to create experiment: {:?}", e);
+        error::ErrorInternalServerError("Failed to create experiment in Superposition")
+    })?;
+
+    Ok(created_experiment_response.id.to_string())
+}
+
+/// Roll back the latest release of an application that reached devices: release the latest
+/// earlier one that has not itself been rolled back in the same context, then abort it.
+pub async fn rollback_release(
+    state: &AppState,
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    target_id: Uuid,
+    created_by: String,
+    metadata: serde_json::Value,
+) -> Result<ReleaseEntry, actix_web::Error> {
+    let release_entries = dsl::releases
+        .filter(dsl::org_id.eq(organisation).and(dsl::app_id.eq(application)))
+        .order_by(dsl::created_at.desc())
+        .select(ReleaseEntry::as_select())
+        .load(conn)
+        .map_err(error::ErrorInternalServerError)?;
+
+    let target = release_entries
+        .iter()
+        .find(|entry| entry.id == target_id)
+        .ok_or_else(|| error::ErrorNotFound("Release not found"))?;
+    if target.tester_group_id.is_some() {
+        return Err(error::ErrorConflict(
+            "Tester releases cannot be rolled back; abort the release instead",
+        ));
+    }
+    // Tester releases only reach their group, and drafts and aborted releases never reached
+    // devices, so none of them block or serve a rollback. Releases of other contexts serve
+    // other devices, e.g. another OS, so they are ignored too.
+    let release_entries: Vec<&ReleaseEntry> = release_entries
+        .iter()
+        .filter(|entry| {
+            entry.tester_group_id.is_none()
+                && entry.context == target.context
+                && !matches!(
+                    ReleaseStatus::parse(&entry.status),
+                    Some(ReleaseStatus::Draft | ReleaseStatus::Aborted)
+                )
+        })
+        .collect();
+
+    if release_entries.first().map(|entry| entry.id) != Some(target.id) {
+        return Err(error::ErrorConflict(
+            "Only the live (latest) release of its context can be rolled back",
+        ));
+    }
+
+    let rolled_back: std::collections::HashSet<Uuid> = release_entries
+        .iter()
+        .filter_map(|entry| entry.rolled_back_from)
+        .collect();
+    let restored = release_entries
+        .iter()
+        .find(|entry| entry.created_at < target.created_at && !rolled_back.contains(&entry.id))
+        .ok_or_else(|| error::ErrorConflict("No earlier release to roll back to"))?;
+
+    // Archived packages have had their files garbage collected
+    let restored_archived = packages::table
+        .filter(packages::org_id.eq(organisation))
+        .filter(packages::app_id.eq(application))
+        .filter(packages::version.eq(restored.package_version))
+        .select(packages::archived_at.is_not_null())
+        .first::<bool>(conn)
+        .optional()
+        .map_err(error::ErrorInternalServerError)?;
+    if restored_archived != Some(false) {
+        return Err(error::ErrorConflict(format!(
+            "Package version {} to roll back to is archived or deleted",
+            restored.package_version
+        )));
+    }
+
+    let workspace_name = get_workspace_name_for_application(application, organisation, conn)
+        .await
+        .map_err(|e| error::ErrorInternalServerError(format!("Failed to get workspace name: {}", e)))?;
+
+    let release_id = Uuid::new_v4();
+    let now = Utc::now();
+    // An unreadable context must not widen the replacement to every device
+    let context_map: std::collections::HashMap<String, serde_json::Value> =
+        serde_json::from_value(target.context.clone()).map_err(|e| {
+            error::ErrorInternalServerError(format!(
+                "Invalid context of release {}: {}",
+                target.id, e
+            ))
+        })?;
+
+    let experiment = create_release_experiment(
+        state,
+        &workspace_name,
+        application,
+        release_id,
+        restored.package_version,
+        context_map,
+        format!(
+            "Rolling back release {} to package version {}",
+            target.id, restored.package_version
+        ),
+    )
+    .await?;
+
+    let new_release = ReleaseEntry {
+        id: release_id,
+        org_id: organisation.to_string(),
+        app_id: application.to_string(),
+        package_version: restored.package_version,
+        config_version: restored.config_version.clone(),
+        created_at: now,
+        created_by,
+        metadata,
+        rollout_percentage: 100,
+        previous_package_version: None,
+        experiment_id: Some(experiment),
+        context: target.context.clone(),
+        rolled_back_from: Some(target.id),
+        status: ReleaseStatus::Draft.as_str().to_string(),
+        ramped_at: None,
+        paused_at: None,
+        live_at: None,
+        concluded_at: None,
+        aborted_at: None,
+        tester_group_id: None,
+        // The restored package comes back with the config it was released with
+        config_revision: restored.config_revision,
+    };
+
+    diesel::insert_into(dsl::releases)
+        .values(&new_release)
+        .execute(conn)
+        .map_err(error::ErrorInternalServerError)?;
+
+    // The replacement is ramped before the target is aborted, so a failure in between never
+    // leaves the context without a release; if either step fails, the replacement is
+    // discarded and the target keeps serving. Abort discards the experiment rather than
+    // concluding it, so none of the rolled back overrides survive in the base config.
+    // Concluded releases have none left.
+    // actix errors are not Send, so only their status and message are held across the
+    // cleanup below.
+    let ramped = ramp_release(state, conn, &new_release, &workspace_name, 100)
+        .await
+        .map_err(|e| (e.as_response_error().status_code(), e.to_string()));
+    let release = match ramped {
+        Ok(release) => release,
+        Err((status_code, message)) => {
+            discard_replacement(state, conn, &new_release, &workspace_name).await;
+            return Err(error::InternalError::new(message, status_code).into());
+        }
+    };
+
+    if release_status(target)? != ReleaseStatus::Concluded {
+        let aborted = abort_release(state, conn, target, &workspace_name)
+            .await
+            .map_err(|e| (e.as_response_error().status_code(), e.to_string()));
+        if let Err((status_code, message)) = aborted {
+            discard_replacement(state, conn, &release, &workspace_name).await;
+            return Err(error::InternalError::new(message, status_code).into());
+        }
+    }
+
+    info!(
+        "Rolled back release {} of {}/{} to package version {} as release {} ({})",
+        target.id, organisation, application, restored.package_version, release_id, release.status
+    );
+
+    Ok(release)
+}
+
+/// Abort the replacement of a rollback that could not complete
+async fn discard_replacement(
+    state: &AppState,
+    conn: &mut PgConnection,
+    replacement: &ReleaseEntry,
+    workspace_name: &str,
+) {
+    if let Err(e) = abort_release(state, conn, replacement, workspace_name).await {
+        error!("Failed to discard replacement release {}: {}", replacement.id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowed_transitions() {
+        use ReleaseStatus::*;
+        for (from, to) in [
+            (Draft, Ramping),
+            (Draft, Live),
+            (Draft, Aborted),
+            (Ramping, Ramping),
+            (Ramping, Paused),
+            (Ramping, Live),
+            (Ramping, Aborted),
+            (Paused, Ramping),
+            (Paused, Live),
+            (Paused, Aborted),
+            (Live, Concluded),
+            (Live, Aborted),
+        ] {
+            assert!(can_transition(from, to), "{:?} -> {:?} was refused", from, to);
+        }
+    }
+
+    #[test]
+    fn refused_transitions() {
+        use ReleaseStatus::*;
+        for (from, to) in [
+            (Draft, Paused),
+            (Draft, Concluded),
+            (Paused, Paused),
+            (Paused, Concluded),
+            (Ramping, Concluded),
+            (Live, Ramping),
+            (Live, Paused),
+            (Live, Live),
+        ] {
+            assert!(!can_transition(from, to), "{:?} -> {:?} was allowed", from, to);
+        }
+        for to in [Draft, Ramping, Paused, Live, Concluded, Aborted] {
+            assert!(!can_transition(Concluded, to));
+            assert!(!can_transition(Aborted, to));
+        }
+    }
+}
@@ -0,0 +1,297 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Staged rollouts: a release created with a schedule is ramped step by step by a
+// background job. Progress is stored per step, so a restart resumes where it left off.
+
+use actix_web::{error, web};
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    types::AppState,
+    utils::{
+        db::{
+            models::{ReleaseEntry, ReleaseScheduleStep, ReleaseStatus},
+            schema::hyperotaserver::{
+                release_schedule_steps::dsl as steps, releases::dsl as releases,
+            },
+        },
+        release_lifecycle::{conclude_release, ramp_release, release_status},
+        workspace::get_workspace_name_for_application,
+    },
+};
+
+const SCHEDULER_INTERVAL_SECS: u64 = 60;
+const MAX_RELEASES_PER_RUN: i64 = 50;
+
+/// One step of a release schedule, relative to the release creation time
+#[derive(Debug, Deserialize)]
+pub struct ScheduleStepRequest {
+    pub after_secs: i64,
+    pub rollout_percentage: Option<i32>,
+    #[serde(default)]
+    pub conclude: bool,
+}
+
+/// Check that a schedule only moves forward: offsets and percentages never decrease
+/// and concluding, if requested, is the last step.
+pub fn validate_schedule(schedule: &[ScheduleStepRequest]) -> actix_web::Result<()> {
+    if schedule.is_empty() {
+        return Err(error::ErrorBadRequest("schedule must have at least one step"));
+    }
+
+    let mut last_after = 0;
+    let mut last_percentage = 0;
+    for (index, step) in schedule.iter().enumerate() {
+        if step.after_secs < last_after {
+            return Err(error::ErrorBadRequest(
+                "schedule steps must be ordered by after_secs, starting at 0 or later",
+            ));
+        }
+        last_after = step.after_secs;
+
+        match (step.rollout_percentage, step.conclude) {
+            (Some(percentage), false) => {
+                if !(0..=100).contains(&percentage) {
+                    return Err(error::ErrorBadRequest(
+                        "rollout_percentage must be between 0 and 100",
+                    ));
+                }
+                if percentage < last_percentage {
+                    return Err(error::ErrorBadRequest(
+                        "schedule steps cannot lower rollout_percentage",
+                    ));
+                }
+                last_percentage = percentage;
+            }
+            (None, true) if index == schedule.len() - 1 => {}
+            (None, true) => {
+                return Err(error::ErrorBadRequest("conclude must be the last schedule step"))
+            }
+            _ => {
+                return Err(error::ErrorBadRequest(
+                    "Each schedule step needs either rollout_percentage or conclude",
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Store the schedule of a new release
+pub fn save_schedule(
+    conn: &mut PgConnection,
+    release_id: Uuid,
+    created_at: DateTime<Utc>,
+    schedule: &[ScheduleStepRequest],
+) -> actix_web::Result<()> {
+    let new_steps: Vec<ReleaseScheduleStep> = schedule
+        .iter()
+        .enumerate()
+        .map(|(index, step)| ReleaseScheduleStep {
+            release_id,
+            step_index: index as i32,
+            run_at: created_at + Duration::seconds(step.after_secs),
+            rollout_percentage: step.rollout_percentage,
+            conclude: step.conclude,
+            applied_at: None,
+        })
+        .collect();
+
+    diesel::insert_into(steps::release_schedule_steps)
+        .values(&new_steps)
+        .execute(conn)
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(())
+}
+
+pub fn list_schedule(
+    conn: &mut PgConnection,
+    release_id: Uuid,
+) -> Result<Vec<ReleaseScheduleStep>, diesel::result::Error> {
+    steps::release_schedule_steps
+        .filter(steps::release_id.eq(release_id))
+        .order_by(steps::step_index.asc())
+        .select(ReleaseScheduleStep::as_select())
+        .load(conn)
+}
+
+/// Mark a step as applied unless another server instance already did
+fn claim_step(
+    conn: &mut PgConnection,
+    step: &ReleaseScheduleStep,
+) -> Result<bool, diesel::result::Error> {
+    let claimed = diesel::update(
+        steps::release_schedule_steps
+            .filter(steps::release_id.eq(step.release_id))
+            .filter(steps::step_index.eq(step.step_index))
+            .filter(steps::applied_at.is_null()),
+    )
+    .set(steps::applied_at.eq(Utc::now()))
+    .execute(conn)?;
+    Ok(claimed == 1)
+}
+
+fn release_step(
+    conn: &mut PgConnection,
+    step: &ReleaseScheduleStep,
+) -> Result<usize, diesel::result::Error> {
+    diesel::update(
+        steps::release_schedule_steps
+            .filter(steps::release_id.eq(step.release_id))
+            .filter(steps::step_index.eq(step.step_index)),
+    )
+    .set(steps::applied_at.eq(None::<DateTime<Utc>>))
+    .execute(conn)
+}
+
+/// Apply one step to a release, returning the updated release
+async fn apply_step(
+    state: &AppState,
+    conn: &mut PgConnection,
+    release: ReleaseEntry,
+    workspace_name: &str,
+    step: &ReleaseScheduleStep,
+) -> actix_web::Result<ReleaseEntry> {
+    let status = release_status(&release)?;
+    match step.rollout_percentage {
+        // Steps are never applied downwards, e.g. after a manual ramp past them
+        Some(percentage)
+            if status != ReleaseStatus::Draft && percentage <= release.rollout_percentage =>
+        {
+            Ok(release)
+        }
+        Some(percentage) => ramp_release(state, conn, &release, workspace_name, percentage).await,
+        None => {
+            let release = if status == ReleaseStatus::Live {
+                release
+            } else {
+                ramp_release(state, conn, &release, workspace_name, 100).await?
+            };
+            conclude_release(state, conn, &release, workspace_name).await
+        }
+    }
+}
+
+/// Apply every due step of a release in order. Paused, concluded and aborted releases
+/// are left alone; a paused release picks up its missed steps once ramped again.
+pub async fn advance_release(
+    state: &AppState,
+    conn: &mut PgConnection,
+    release_id: Uuid,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let due_steps = steps::release_schedule_steps
+        .filter(steps::release_id.eq(release_id))
+        .filter(steps::applied_at.is_null())
+        .filter(steps::run_at.le(Utc::now()))
+        .order_by(steps::step_index.asc())
+        .select(ReleaseScheduleStep::as_select())
+        .load(conn)?;
+    if due_steps.is_empty() {
+        return Ok(());
+    }
+
+    let mut release = releases::releases
+        .find(release_id)
+        .select(ReleaseEntry::as_select())
+        .first(conn)?;
+    let workspace_name =
+        get_workspace_name_for_application(&release.app_id, &release.org_id, conn)
+            .await
+            .map_err(|e| format!("Failed to get workspace name: {}", e))?;
+
+    for step in due_steps {
+        if !matches!(
+            ReleaseStatus::parse(&release.status),
+            Some(ReleaseStatus::Draft | ReleaseStatus::Ramping | ReleaseStatus::Live)
+        ) {
+            debug!("Release {} is {}, not advancing its schedule", release.id, release.status);
+            return Ok(());
+        }
+        if !claim_step(conn, &step)? {
+            continue;
+        }
+
+        match apply_step(state, conn, release, &workspace_name, &step).await {
+            Ok(updated) => {
+                info!(
+                    "Applied schedule step {} of release {}: now {} at {}%",
+                    step.step_index, updated.id, updated.status, updated.rollout_percentage
+                );
+                release = updated;
+            }
+            Err(e) => {
+                // Leave the step pending so the next run retries it
+                release_step(conn, &step)?;
+                return Err(format!(
+                    "Failed to apply schedule step {} of release {}: {}",
+                    step.step_index, release_id, e
+                )
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Advance every release that has due steps
+pub async fn process_release_schedules(
+    app_state: &web::Data<AppState>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut conn = app_state.db_pool.get()?;
+
+    let active_releases = releases::releases
+        .filter(releases::status.eq_any([
+            ReleaseStatus::Draft.as_str(),
+            ReleaseStatus::Ramping.as_str(),
+            ReleaseStatus::Live.as_str(),
+        ]))
+        .select(releases::id);
+    let due_releases: Vec<Uuid> = steps::release_schedule_steps
+        .filter(steps::applied_at.is_null())
+        .filter(steps::run_at.le(Utc::now()))
+        .filter(steps::release_id.eq_any(active_releases))
+        .select(steps::release_id)
+        .distinct()
+        .limit(MAX_RELEASES_PER_RUN)
+        .load(&mut conn)?;
+
+    if due_releases.is_empty() {
+        debug!("No due release schedule steps found");
+        return Ok(());
+    }
+
+    for release_id in due_releases {
+        if let Err(e) = advance_release(app_state, &mut conn, release_id).await {
+            warn!("{}", e);
+        }
+    }
+    Ok(())
+}
+
+pub fn start_release_scheduler(app_state: web::Data<AppState>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = process_release_schedules(&app_state).await {
+                error!("Error processing release schedules: {}", e);
+            }
+
+            tokio::time::sleep(std::time::Duration::from_secs(SCHEDULER_INTERVAL_SECS)).await;
+        }
+    })
+}
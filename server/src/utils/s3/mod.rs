@@ -1,23 +1,390 @@
+use std::{collections::HashMap, path::Path, time::Duration};
+
 use actix_multipart::form::tempfile::TempFile;
 use actix_web::error;
-use aws_sdk_s3::{operation::put_object::PutObjectOutput, primitives::ByteStream, Client};
+use aws_sdk_s3::{
+    operation::put_object::PutObjectOutput,
+    presigning::PresigningConfig,
+    primitives::{ByteStream, Length},
+    types::{ChecksumMode, CompletedMultipartUpload, CompletedPart, Delete, ObjectIdentifier},
+    Client,
+};
+use base64::{engine::general_purpose, Engine};
+use log::warn;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncReadExt;
+
+use crate::types::Environment;
+
+/// Files larger than this are uploaded in parts instead of with a single request
+const MULTIPART_THRESHOLD: u64 = 16 * 1024 * 1024;
+/// Size of each part of a multipart upload; S3 requires at least 5 MiB for all but the last
+const MULTIPART_PART_SIZE: u64 = 8 * 1024 * 1024;
 
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Upload a file from disk without reading it into memory. Large files use a multipart
+/// upload, which is aborted if any part fails.
 pub async fn push_file(
     s3_client: &Client,
     bucket_name: String,
     file: TempFile,
     filename: String,
-) -> actix_web::Result<PutObjectOutput> {
-    // Convert file into S3 ByteStream
-    let file_data = tokio::fs::read(file.file.path()).await?;
-    let byte_stream = ByteStream::from(file_data);
+) -> actix_web::Result<()> {
+    let path = file.file.path();
+    let size = tokio::fs::metadata(path).await?.len();
+    let content_type = file
+        .content_type
+        .as_ref()
+        .map(|mime| mime.to_string())
+        .unwrap_or_else(|| DEFAULT_CONTENT_TYPE.to_string());
+
+    if size > MULTIPART_THRESHOLD {
+        return push_file_multipart(s3_client, bucket_name, path, size, content_type, filename)
+            .await;
+    }
 
+    let byte_stream = ByteStream::from_path(path)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
     s3_client
         .put_object()
         .bucket(bucket_name)
-        .key(filename.clone())
+        .key(filename)
+        .content_type(content_type)
+        .content_length(size as i64)
         .body(byte_stream)
         .send()
         .await
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(())
+}
+
+async fn push_file_multipart(
+    s3_client: &Client,
+    bucket_name: String,
+    path: &Path,
+    size: u64,
+    content_type: String,
+    filename: String,
+) -> actix_web::Result<()> {
+    let upload = s3_client
+        .create_multipart_upload()
+        .bucket(&bucket_name)
+        .key(&filename)
+        .content_type(content_type)
+        .send()
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    let upload_id = upload
+        .upload_id()
+        .ok_or_else(|| error::ErrorInternalServerError("S3 returned no multipart upload id"))?
+        .to_string();
+
+    let result =
+        upload_parts(s3_client, &bucket_name, path, size, &filename, &upload_id).await;
+    if result.is_err() {
+        // Parts of an unfinished upload are stored, and billed, until it is aborted
+        if let Err(e) = s3_client
+            .abort_multipart_upload()
+            .bucket(&bucket_name)
+            .key(&filename)
+            .upload_id(&upload_id)
+            .send()
+            .await
+        {
+            warn!("Failed to abort multipart upload of {}: {:?}", filename, e);
+        }
+    }
+    result
+}
+
+async fn upload_parts(
+    s3_client: &Client,
+    bucket_name: &str,
+    path: &Path,
+    size: u64,
+    filename: &str,
+    upload_id: &str,
+) -> actix_web::Result<()> {
+    let mut completed_parts = Vec::new();
+    let mut offset = 0;
+    let mut part_number = 1;
+
+    while offset < size {
+        let length = MULTIPART_PART_SIZE.min(size - offset);
+        let body = ByteStream::read_from()
+            .path(path)
+            .offset(offset)
+            .length(Length::Exact(length))
+            .build()
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+
+        let part = s3_client
+            .upload_part()
+            .bucket(bucket_name)
+            .key(filename)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .content_length(length as i64)
+            .body(body)
+            .send()
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+        completed_parts.push(
+            CompletedPart::builder()
+                .set_e_tag(part.e_tag().map(str::to_string))
+                .part_number(part_number)
+                .build(),
+        );
+
+        offset += length;
+        part_number += 1;
+    }
+
+    s3_client
+        .complete_multipart_upload()
+        .bucket(bucket_name)
+        .key(filename)
+        .upload_id(upload_id)
+        .multipart_upload(
+            CompletedMultipartUpload::builder()
+                .set_parts(Some(completed_parts))
+                .build(),
+        )
+        .send()
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(())
+}
+
+/// Public URL devices download the object stored under `key` from
+pub fn object_url(env: &Environment, key: &str) -> String {
+    format!("{}/{}/{}", env.public_url, env.bucket_name, key)
+}
+
+/// Key of the object a URL built by `object_url` points to, if it is one
+pub fn object_key<'a>(env: &Environment, url: &'a str) -> Option<&'a str> {
+    url.strip_prefix(&env.public_url)?
+        .strip_prefix('/')?
+        .strip_prefix(&env.bucket_name)?
+        .strip_prefix('/')
+}
+
+/// Key of a file in the content-addressed store of an application. Identical files share
+/// one object across package versions.
+pub fn content_key(organisation: &str, application: &str, sha256: &str) -> String {
+    format!("assets/{}/{}/objects/{}", organisation, application, sha256)
+}
+
+/// Upload a file with a known SHA-256 to its content-addressed key, unless it is already
+/// stored. Returns the key.
+pub async fn push_file_by_content(
+    s3_client: &Client,
+    bucket_name: String,
+    file: TempFile,
+    organisation: &str,
+    application: &str,
+    sha256: &str,
+) -> actix_web::Result<String> {
+    let key = content_key(organisation, application, sha256);
+    if object_integrity(s3_client, bucket_name.clone(), key.clone())
+        .await?
+        .is_none()
+    {
+        push_file(s3_client, bucket_name, file, key.clone()).await?;
+    }
+    Ok(key)
+}
+
+pub async fn push_bytes(
+    s3_client: &Client,
+    bucket_name: String,
+    data: Vec<u8>,
+    filename: String,
+) -> actix_web::Result<PutObjectOutput> {
+    s3_client
+        .put_object()
+        .bucket(bucket_name)
+        .key(filename)
+        .body(ByteStream::from(data))
+        .send()
+        .await
         .map_err(error::ErrorInternalServerError)
 }
+
+/// Lowercase hex SHA-256 and size in bytes of a file, read in chunks
+pub async fn file_digest(path: &Path) -> std::io::Result<(String, i64)> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut size: i64 = 0;
+
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        size += read as i64;
+    }
+
+    Ok((format!("{:x}", hasher.finalize()), size))
+}
+
+/// A presigned PUT request. The headers must be sent with the upload as they are.
+#[derive(Debug, serde::Serialize)]
+pub struct PresignedUpload {
+    pub url: String,
+    pub headers: HashMap<String, String>,
+}
+
+/// Presign the upload of a file with a known SHA-256 (lowercase hex) and size. S3 rejects
+/// the upload unless its body matches both.
+pub async fn presign_put(
+    s3_client: &Client,
+    bucket_name: String,
+    filename: String,
+    sha256: &str,
+    size: i64,
+    expires_in: Duration,
+) -> actix_web::Result<PresignedUpload> {
+    let checksum = hex_to_base64(sha256)
+        .ok_or_else(|| error::ErrorBadRequest(format!("Invalid sha256 for {}", filename)))?;
+    let config = PresigningConfig::expires_in(expires_in).map_err(error::ErrorInternalServerError)?;
+
+    let request = s3_client
+        .put_object()
+        .bucket(bucket_name)
+        .key(filename)
+        .content_length(size)
+        .checksum_sha256(checksum)
+        .presigned(config)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(PresignedUpload {
+        url: request.uri().to_string(),
+        headers: request
+            .headers()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+    })
+}
+
+/// SHA-256 (lowercase hex, when S3 has one) and size of an uploaded object, or None if the
+/// object does not exist
+pub async fn object_integrity(
+    s3_client: &Client,
+    bucket_name: String,
+    filename: String,
+) -> actix_web::Result<Option<(Option<String>, i64)>> {
+    let head = s3_client
+        .head_object()
+        .bucket(bucket_name)
+        .key(filename)
+        .checksum_mode(ChecksumMode::Enabled)
+        .send()
+        .await;
+
+    match head {
+        Ok(head) => Ok(Some((
+            head.checksum_sha256().and_then(base64_to_hex),
+            head.content_length().unwrap_or_default(),
+        ))),
+        Err(e) if e.as_service_error().is_some_and(|e| e.is_not_found()) => Ok(None),
+        Err(e) => Err(error::ErrorInternalServerError(e)),
+    }
+}
+
+/// An object found by `list_objects`
+#[derive(Debug)]
+pub struct StoredObject {
+    pub key: String,
+    pub size: i64,
+    pub last_modified: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Every object whose key starts with `prefix`
+pub async fn list_objects(
+    s3_client: &Client,
+    bucket_name: String,
+    prefix: String,
+) -> actix_web::Result<Vec<StoredObject>> {
+    let mut objects = Vec::new();
+    let mut pages = s3_client
+        .list_objects_v2()
+        .bucket(bucket_name)
+        .prefix(prefix)
+        .into_paginator()
+        .send();
+
+    while let Some(page) = pages.next().await {
+        let page = page.map_err(error::ErrorInternalServerError)?;
+        for object in page.contents() {
+            let Some(key) = object.key() else {
+                continue;
+            };
+            objects.push(StoredObject {
+                key: key.to_string(),
+                size: object.size().unwrap_or_default(),
+                last_modified: object.last_modified().and_then(|time| {
+                    chrono::DateTime::from_timestamp(time.secs(), time.subsec_nanos())
+                }),
+            });
+        }
+    }
+    Ok(objects)
+}
+
+/// Delete objects in batches of the 1000 keys S3 accepts per request
+pub async fn delete_objects(
+    s3_client: &Client,
+    bucket_name: String,
+    keys: &[String],
+) -> actix_web::Result<()> {
+    for batch in keys.chunks(1000) {
+        let objects = batch
+            .iter()
+            .map(|key| ObjectIdentifier::builder().key(key).build())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(error::ErrorInternalServerError)?;
+        let delete = Delete::builder()
+            .set_objects(Some(objects))
+            .quiet(true)
+            .build()
+            .map_err(error::ErrorInternalServerError)?;
+
+        let output = s3_client
+            .delete_objects()
+            .bucket(&bucket_name)
+            .delete(delete)
+            .send()
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+        if let Some(failed) = output.errors().first() {
+            return Err(error::ErrorInternalServerError(format!(
+                "Failed to delete {} objects, e.g. {:?}: {:?}",
+                output.errors().len(),
+                failed.key(),
+                failed.message()
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn hex_to_base64(hex: &str) -> Option<String> {
+    // An odd length leaves a one-digit tail, which `get` rejects
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some(general_purpose::STANDARD.encode(bytes))
+}
+
+fn base64_to_hex(encoded: &str) -> Option<String> {
+    let bytes = general_purpose::STANDARD.decode(encoded).ok()?;
+    Some(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
@@ -0,0 +1,54 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use diesel::prelude::*;
+
+use crate::utils::db::{
+    models::ApplicationSettings,
+    schema::hyperotaserver::application_settings::dsl::*,
+};
+
+/// Cache-Control sent with release configs when an application has not configured one.
+/// Clients may store the response but must revalidate it (using the ETag) before reuse.
+pub const DEFAULT_CACHE_CONTROL: &str = "no-cache";
+
+/// Health reports a release needs before its failure rates are compared to the thresholds
+pub const DEFAULT_MIN_HEALTH_SAMPLES: i32 = 100;
+
+/// Days for which packages of past releases are kept by garbage collection
+pub const DEFAULT_RETAIN_RELEASED_DAYS: i32 = 90;
+
+/// Read the settings row for an application, if one has been stored
+pub fn find_settings(
+    organisation: &str,
+    application: &str,
+    conn: &mut PgConnection,
+) -> Result<Option<ApplicationSettings>, diesel::result::Error> {
+    application_settings
+        .filter(org_id.eq(organisation).and(app_id.eq(application)))
+        .select(ApplicationSettings::as_select())
+        .first(conn)
+        .optional()
+}
+
+/// Cache-Control header value to serve release configs of an application with
+pub fn get_cache_control(
+    organisation: &str,
+    application: &str,
+    conn: &mut PgConnection,
+) -> Result<String, diesel::result::Error> {
+    Ok(find_settings(organisation, application, conn)?
+        .map(|settings| settings.cache_control)
+        .unwrap_or_else(|| DEFAULT_CACHE_CONTROL.to_string()))
+}
@@ -0,0 +1,340 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::error;
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use ed25519_dalek::{Signer, SigningKey, SECRET_KEY_LENGTH};
+use log::info;
+use rand::{rngs::OsRng, RngCore};
+use serde::Serialize;
+
+use crate::{
+    types::AppState,
+    utils::{
+        db::{models::SigningKeyEntry, schema::hyperotaserver::signing_keys::dsl::*},
+        kms::{encrypt_kms, try_decrypt_kms},
+    },
+};
+
+pub const SIGNATURE_ALGORITHM: &str = "ed25519";
+
+/// Decrypted signing key of an application, kept in `AppState::signing_keys`
+#[derive(Clone)]
+pub struct AppSigningKey {
+    pub key_id: String,
+    signing_key: SigningKey,
+}
+
+impl AppSigningKey {
+    pub fn from_secret(key_id: String, secret: &[u8; SECRET_KEY_LENGTH]) -> Self {
+        AppSigningKey {
+            key_id,
+            signing_key: SigningKey::from_bytes(secret),
+        }
+    }
+
+    /// Base64 encoded verifying key, as published to SDKs
+    pub fn public_key(&self) -> String {
+        general_purpose::STANDARD.encode(self.signing_key.verifying_key().to_bytes())
+    }
+
+    /// Detached Ed25519 signature over `body`, base64 encoded
+    pub fn sign(&self, body: &[u8]) -> String {
+        general_purpose::STANDARD.encode(self.signing_key.sign(body).to_bytes())
+    }
+}
+
+/// Public half of a signing key, as published for SDKs to pin
+#[derive(Serialize)]
+pub struct PublicKey {
+    pub key_id: String,
+    pub algorithm: &'static str,
+    pub public_key: String,
+    pub created_at: DateTime<Utc>,
+    /// Set once the key no longer signs new release configs
+    pub retired_at: Option<DateTime<Utc>>,
+}
+
+impl From<SigningKeyEntry> for PublicKey {
+    fn from(entry: SigningKeyEntry) -> Self {
+        PublicKey {
+            key_id: entry.id.to_string(),
+            algorithm: SIGNATURE_ALGORITHM,
+            public_key: entry.public_key,
+            created_at: entry.created_at,
+            retired_at: entry.retired_at,
+        }
+    }
+}
+
+/// All signing keys of an application, newest first
+pub fn list_signing_keys(
+    organisation: &str,
+    application: &str,
+    conn: &mut PgConnection,
+) -> Result<Vec<SigningKeyEntry>, diesel::result::Error> {
+    signing_keys
+        .filter(org_id.eq(organisation).and(app_id.eq(application)))
+        .order((created_at.desc(), id.desc()))
+        .select(SigningKeyEntry::as_select())
+        .load(conn)
+}
+
+/// The key currently signing release configs of an application
+fn active_signing_key(
+    organisation: &str,
+    application: &str,
+    conn: &mut PgConnection,
+) -> Result<Option<SigningKeyEntry>, diesel::result::Error> {
+    signing_keys
+        .filter(org_id.eq(organisation).and(app_id.eq(application)))
+        .filter(retired_at.is_null())
+        .select(SigningKeyEntry::as_select())
+        .first(conn)
+        .optional()
+}
+
+/// Active key of an application, from the cache or decrypted from its row
+async fn find_signing_key(
+    state: &AppState,
+    organisation: &str,
+    application: &str,
+    conn: &mut PgConnection,
+) -> Result<Option<AppSigningKey>, actix_web::Error> {
+    let cache_key = (organisation.to_string(), application.to_string());
+    if let Some(key) = state.signing_keys.get(&cache_key) {
+        return Ok(Some(key));
+    }
+
+    let Some(entry) = active_signing_key(organisation, application, conn)
+        .map_err(error::ErrorInternalServerError)?
+    else {
+        return Ok(None);
+    };
+    let key = decrypt_signing_key(state, entry).await?;
+    state.signing_keys.insert(cache_key, key.clone());
+    Ok(Some(key))
+}
+
+/// Key to sign release configs of an application with. Keys are provisioned when the
+/// application is created, or through the signing key endpoint for older applications.
+/// Release configs are never served unsigned, so applications without a key get an error;
+/// unauthenticated polls must not be able to create keys.
+pub async fn get_signing_key(
+    state: &AppState,
+    organisation: &str,
+    application: &str,
+    conn: &mut PgConnection,
+) -> Result<AppSigningKey, actix_web::Error> {
+    find_signing_key(state, organisation, application, conn)
+        .await?
+        .ok_or_else(|| error::ErrorServiceUnavailable("Release signing key is not provisioned"))
+}
+
+/// Key of an application, provisioning one when it has none yet
+pub async fn get_or_provision_signing_key(
+    state: &AppState,
+    organisation: &str,
+    application: &str,
+    conn: &mut PgConnection,
+) -> Result<AppSigningKey, actix_web::Error> {
+    match find_signing_key(state, organisation, application, conn).await? {
+        Some(key) => Ok(key),
+        None => provision_signing_key(state, organisation, application, conn).await,
+    }
+}
+
+async fn decrypt_signing_key(
+    state: &AppState,
+    entry: SigningKeyEntry,
+) -> Result<AppSigningKey, actix_web::Error> {
+    let encoded_secret = try_decrypt_kms(&state.kms_client, &entry.encrypted_private_key)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    let secret: [u8; SECRET_KEY_LENGTH] = general_purpose::STANDARD
+        .decode(encoded_secret)
+        .map_err(error::ErrorInternalServerError)?
+        .try_into()
+        .map_err(|_| error::ErrorInternalServerError("Stored signing key has invalid length"))?;
+
+    Ok(AppSigningKey::from_secret(entry.id.to_string(), &secret))
+}
+
+/// Generate a key and its row, with the secret encrypted by KMS
+async fn generate_signing_key(
+    state: &AppState,
+    organisation: &str,
+    application: &str,
+) -> Result<(SigningKeyEntry, AppSigningKey), actix_web::Error> {
+    let mut secret = [0u8; SECRET_KEY_LENGTH];
+    OsRng.fill_bytes(&mut secret);
+    let key_id = uuid::Uuid::new_v4();
+    let key = AppSigningKey::from_secret(key_id.to_string(), &secret);
+
+    let encrypted_secret = encrypt_kms(
+        &state.kms_client,
+        &state.env.kms_key_id,
+        &general_purpose::STANDARD.encode(secret),
+    )
+    .await
+    .map_err(error::ErrorInternalServerError)?;
+
+    let entry = SigningKeyEntry {
+        id: key_id,
+        org_id: organisation.to_string(),
+        app_id: application.to_string(),
+        public_key: key.public_key(),
+        encrypted_private_key: encrypted_secret,
+        created_at: Utc::now(),
+        retired_at: None,
+    };
+    Ok((entry, key))
+}
+
+/// Give an application its first signing key. Safe to race: only one active key can be
+/// stored per application, and every caller gets that one.
+pub async fn provision_signing_key(
+    state: &AppState,
+    organisation: &str,
+    application: &str,
+    conn: &mut PgConnection,
+) -> Result<AppSigningKey, actix_web::Error> {
+    let (entry, key) = generate_signing_key(state, organisation, application).await?;
+
+    let inserted = diesel::insert_into(signing_keys)
+        .values(&entry)
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .map_err(error::ErrorInternalServerError)?;
+    if inserted == 1 {
+        info!(
+            "Generated signing key {} for org: {}, app: {}",
+            entry.id, organisation, application
+        );
+        return Ok(key);
+    }
+
+    let active = active_signing_key(organisation, application, conn)
+        .map_err(error::ErrorInternalServerError)?
+        .ok_or_else(|| error::ErrorInternalServerError("Signing key was not stored"))?;
+    decrypt_signing_key(state, active).await
+}
+
+/// Replace the active signing key of an application. The old key is retired but stays
+/// listed, so SDKs can still verify configs signed before other instances pick up the new one.
+pub async fn rotate_signing_key(
+    state: &AppState,
+    organisation: &str,
+    application: &str,
+    conn: &mut PgConnection,
+) -> Result<SigningKeyEntry, actix_web::Error> {
+    let (entry, key) = generate_signing_key(state, organisation, application).await?;
+
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        diesel::update(
+            signing_keys
+                .filter(org_id.eq(organisation).and(app_id.eq(application)))
+                .filter(retired_at.is_null()),
+        )
+        .set(retired_at.eq(Some(entry.created_at)))
+        .execute(conn)?;
+        diesel::insert_into(signing_keys).values(&entry).execute(conn)?;
+        Ok(())
+    })
+    .map_err(|e| match e {
+        diesel::result::Error::DatabaseError(
+            diesel::result::DatabaseErrorKind::UniqueViolation,
+            _,
+        ) => error::ErrorConflict("Signing key was rotated concurrently, retry"),
+        e => error::ErrorInternalServerError(e),
+    })?;
+
+    info!(
+        "Rotated signing key of org: {}, app: {} to {}",
+        organisation, application, entry.id
+    );
+    state
+        .signing_keys
+        .insert((organisation.to_string(), application.to_string()), key);
+    Ok(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+
+    use super::*;
+
+    fn verify(published_key: &str, body: &[u8], signature: &str) -> bool {
+        let verifying_key: [u8; 32] = general_purpose::STANDARD
+            .decode(published_key)
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let signature: [u8; 64] = general_purpose::STANDARD
+            .decode(signature)
+            .unwrap()
+            .try_into()
+            .unwrap();
+        VerifyingKey::from_bytes(&verifying_key)
+            .unwrap()
+            .verify(body, &Signature::from_bytes(&signature))
+            .is_ok()
+    }
+
+    #[test]
+    fn signatures_verify_with_the_published_key() {
+        let key = AppSigningKey::from_secret("key-1".to_string(), &[7; SECRET_KEY_LENGTH]);
+        let body = br#"{"config":{},"package":{}}"#;
+
+        let signature = key.sign(body);
+        assert!(verify(&key.public_key(), body, &signature));
+        // Signing is deterministic, so identical bodies get identical signatures
+        assert_eq!(key.sign(body), signature);
+    }
+
+    #[test]
+    fn signatures_do_not_verify_other_bodies_or_keys() {
+        let key = AppSigningKey::from_secret("key-1".to_string(), &[7; SECRET_KEY_LENGTH]);
+        let other_key = AppSigningKey::from_secret("key-2".to_string(), &[8; SECRET_KEY_LENGTH]);
+        let body = br#"{"package":{"version":"2"}}"#;
+        let signature = key.sign(body);
+
+        assert!(!verify(&key.public_key(), br#"{"package":{"version":"3"}}"#, &signature));
+        assert!(!verify(&other_key.public_key(), body, &signature));
+    }
+
+    #[test]
+    fn published_keys_keep_their_id_and_retirement() {
+        let key_id = uuid::Uuid::nil();
+        let key = AppSigningKey::from_secret(key_id.to_string(), &[7; SECRET_KEY_LENGTH]);
+        let rotated_at = Utc::now();
+        let published = PublicKey::from(SigningKeyEntry {
+            id: key_id,
+            org_id: "org".to_string(),
+            app_id: "app".to_string(),
+            public_key: key.public_key(),
+            encrypted_private_key: "ciphertext".to_string(),
+            created_at: rotated_at,
+            retired_at: Some(rotated_at),
+        });
+
+        assert_eq!(published.key_id, key.key_id);
+        assert_eq!(published.algorithm, "ed25519");
+        assert_eq!(published.public_key, key.public_key());
+        assert_eq!(published.retired_at, Some(rotated_at));
+    }
+}
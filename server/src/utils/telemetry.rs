@@ -0,0 +1,246 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Storage of raw device telemetry and the adoption statistics derived from it
+
+use base64::{engine::general_purpose, Engine};
+use chrono::{DateTime, Utc};
+use diesel::{
+    prelude::*,
+    sql_types::{BigInt, Double, Int4, Nullable, Text, Timestamptz},
+};
+use rand::{rngs::OsRng, RngCore};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::utils::db::{
+    models::{DeviceState, NewTelemetryEvent, TelemetryKey},
+    schema::hyperotaserver::{device_states, telemetry_events, telemetry_keys},
+};
+
+const INGEST_KEY_BYTES: usize = 32;
+
+fn hash_ingest_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+/// Generate a telemetry ingest key of an application. Keys created before stay active until
+/// they are revoked, so devices can move to the new key first. The key itself is only
+/// returned here; just its hash is stored.
+pub fn create_ingest_key(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    created_by: &str,
+) -> Result<(String, TelemetryKey), diesel::result::Error> {
+    let mut secret = [0u8; INGEST_KEY_BYTES];
+    OsRng.fill_bytes(&mut secret);
+    let key = general_purpose::URL_SAFE_NO_PAD.encode(secret);
+
+    let entry = TelemetryKey {
+        org_id: organisation.to_string(),
+        app_id: application.to_string(),
+        key_hash: hash_ingest_key(&key),
+        created_at: Utc::now(),
+        created_by: created_by.to_string(),
+        id: Uuid::new_v4(),
+        revoked_at: None,
+        revoked_by: None,
+    };
+    diesel::insert_into(telemetry_keys::table)
+        .values(&entry)
+        .execute(conn)?;
+    Ok((key, entry))
+}
+
+/// Ingest keys of an application, newest first, including revoked ones
+pub fn list_ingest_keys(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+) -> Result<Vec<TelemetryKey>, diesel::result::Error> {
+    telemetry_keys::table
+        .filter(telemetry_keys::org_id.eq(organisation))
+        .filter(telemetry_keys::app_id.eq(application))
+        .order(telemetry_keys::created_at.desc())
+        .select(TelemetryKey::as_select())
+        .load(conn)
+}
+
+/// Revoke an active ingest key. Returns whether there was such a key.
+pub fn revoke_ingest_key(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    key_id: Uuid,
+    revoked_by: &str,
+) -> Result<bool, diesel::result::Error> {
+    let revoked = diesel::update(
+        telemetry_keys::table
+            .filter(telemetry_keys::id.eq(key_id))
+            .filter(telemetry_keys::org_id.eq(organisation))
+            .filter(telemetry_keys::app_id.eq(application))
+            .filter(telemetry_keys::revoked_at.is_null()),
+    )
+    .set((
+        telemetry_keys::revoked_at.eq(Utc::now()),
+        telemetry_keys::revoked_by.eq(revoked_by),
+    ))
+    .execute(conn)?;
+    Ok(revoked > 0)
+}
+
+/// Whether `key` is one of the application's active ingest keys. Applications without one
+/// accept no telemetry.
+pub fn verify_ingest_key(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    key: &str,
+) -> Result<bool, diesel::result::Error> {
+    diesel::select(diesel::dsl::exists(
+        telemetry_keys::table
+            .filter(telemetry_keys::org_id.eq(organisation))
+            .filter(telemetry_keys::app_id.eq(application))
+            .filter(telemetry_keys::key_hash.eq(hash_ingest_key(key)))
+            .filter(telemetry_keys::revoked_at.is_null()),
+    ))
+    .get_result(conn)
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = device_states)]
+struct DeviceStateChange {
+    package_version: Option<i32>,
+    last_update_result: Option<String>,
+    last_seen_at: DateTime<Utc>,
+}
+
+pub fn record_events(
+    conn: &mut PgConnection,
+    events: &[NewTelemetryEvent],
+) -> Result<usize, diesel::result::Error> {
+    diesel::insert_into(telemetry_events::table)
+        .values(events)
+        .execute(conn)
+}
+
+/// Record what a device last reported. Values it did not report this time are kept.
+pub fn update_device_state(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    device: &str,
+    booted_version: Option<i32>,
+    update_result: Option<String>,
+    seen_at: DateTime<Utc>,
+) -> Result<usize, diesel::result::Error> {
+    let state = DeviceState {
+        org_id: organisation.to_string(),
+        app_id: application.to_string(),
+        device_id: device.to_string(),
+        package_version: booted_version,
+        last_update_result: update_result.clone(),
+        first_seen_at: seen_at,
+        last_seen_at: seen_at,
+    };
+
+    diesel::insert_into(device_states::table)
+        .values(&state)
+        .on_conflict((
+            device_states::org_id,
+            device_states::app_id,
+            device_states::device_id,
+        ))
+        .do_update()
+        .set(&DeviceStateChange {
+            package_version: booted_version,
+            last_update_result: update_result,
+            last_seen_at: seen_at,
+        })
+        .execute(conn)
+}
+
+/// Devices that booted a package version within one time bucket
+#[derive(QueryableByName, Serialize, Debug)]
+pub struct AdoptionPoint {
+    #[diesel(sql_type = Timestamptz)]
+    pub bucket: DateTime<Utc>,
+    #[diesel(sql_type = Int4)]
+    pub package_version: i32,
+    #[diesel(sql_type = BigInt)]
+    pub devices: i64,
+}
+
+/// Distinct devices booting each package version per `granularity` ("hour" or "day")
+pub fn adoption_curve(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    granularity: &str,
+    since: DateTime<Utc>,
+) -> Result<Vec<AdoptionPoint>, diesel::result::Error> {
+    diesel::sql_query(
+        "SELECT date_trunc($3, received_at) AS bucket, package_version, \
+                COUNT(DISTINCT device_id) AS devices \
+         FROM hyperotaserver.telemetry_events \
+         WHERE org_id = $1 AND app_id = $2 AND label = 'boot' AND received_at >= $4 \
+           AND package_version IS NOT NULL AND device_id IS NOT NULL \
+         GROUP BY 1, 2 \
+         ORDER BY 1, 2",
+    )
+    .bind::<Text, _>(organisation)
+    .bind::<Text, _>(application)
+    .bind::<Text, _>(granularity)
+    .bind::<Timestamptz, _>(since)
+    .load(conn)
+}
+
+/// Devices currently on a package version and how long downloading it took them
+#[derive(QueryableByName, Serialize, Debug)]
+pub struct VersionDevices {
+    #[diesel(sql_type = Int4)]
+    pub package_version: i32,
+    #[diesel(sql_type = BigInt)]
+    pub active_devices: i64,
+    #[diesel(sql_type = Nullable<Double>)]
+    pub avg_download_duration_ms: Option<f64>,
+}
+
+/// Devices seen since `since`, grouped by the package version they last booted
+pub fn active_devices(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    since: DateTime<Utc>,
+) -> Result<Vec<VersionDevices>, diesel::result::Error> {
+    diesel::sql_query(
+        "SELECT d.package_version, COUNT(*) AS active_devices, \
+                (SELECT AVG(e.download_duration_ms)::float8 \
+                 FROM hyperotaserver.telemetry_events e \
+                 WHERE e.org_id = $1 AND e.app_id = $2 AND e.received_at >= $3 \
+                   AND e.package_version = d.package_version \
+                   AND e.download_duration_ms IS NOT NULL) AS avg_download_duration_ms \
+         FROM hyperotaserver.device_states d \
+         WHERE d.org_id = $1 AND d.app_id = $2 AND d.last_seen_at >= $3 \
+           AND d.package_version IS NOT NULL \
+         GROUP BY d.package_version \
+         ORDER BY d.package_version DESC",
+    )
+    .bind::<Text, _>(organisation)
+    .bind::<Text, _>(application)
+    .bind::<Timestamptz, _>(since)
+    .load(conn)
+}
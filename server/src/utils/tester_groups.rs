@@ -0,0 +1,185 @@
+// Copyright 2025 Juspay Technologies
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Tester groups: named lists of device ids and user ids per application. A live release
+// targeted at a group is served to its members regardless of Superposition and of the public
+// rollout. Ids are sent by the devices themselves, so members must also present the group's
+// secret token; ids alone decide nothing, and device ids should still be unguessable.
+
+use base64::{engine::general_purpose, Engine};
+use diesel::prelude::*;
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+use crate::utils::db::{
+    models::{ReleaseEntry, ReleaseStatus, TesterGroup},
+    schema::hyperotaserver::{releases::dsl as releases, tester_groups::dsl as tester_groups},
+};
+
+const TESTER_TOKEN_BYTES: usize = 32;
+
+/// Package pinned to a device because it belongs to a tester group
+#[derive(Debug, Clone)]
+pub struct PinnedRelease {
+    pub release_id: uuid::Uuid,
+    pub tester_group_id: uuid::Uuid,
+    pub package_version: i32,
+    pub config_revision: Option<i32>,
+}
+
+fn hash_tester_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Generate a tester group token. Only its hash, the second value, is stored.
+pub fn generate_tester_token() -> (String, String) {
+    let mut secret = [0u8; TESTER_TOKEN_BYTES];
+    OsRng.fill_bytes(&mut secret);
+    let token = general_purpose::URL_SAFE_NO_PAD.encode(secret);
+    let token_hash = hash_tester_token(&token);
+    (token, token_hash)
+}
+
+pub fn find_tester_group(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    group_name: &str,
+) -> Result<Option<TesterGroup>, diesel::result::Error> {
+    tester_groups::tester_groups
+        .filter(tester_groups::org_id.eq(organisation))
+        .filter(tester_groups::app_id.eq(application))
+        .filter(tester_groups::name.eq(group_name))
+        .select(TesterGroup::as_select())
+        .first(conn)
+        .optional()
+}
+
+/// Ids a device identifies itself by. It is a member of the groups listing either one.
+#[derive(Debug, Clone, Copy)]
+pub struct MemberIds<'a> {
+    pub device_id: Option<&'a str>,
+    pub user_id: Option<&'a str>,
+}
+
+/// Newest live release targeted at a tester group the device belongs to, if the device
+/// sent that group's token
+pub fn find_pinned_release(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    member: MemberIds,
+    tester_token: Option<&str>,
+) -> Result<Option<PinnedRelease>, diesel::result::Error> {
+    match tester_token {
+        Some(tester_token) => pinned_release(
+            conn,
+            organisation,
+            application,
+            member,
+            Some(hash_tester_token(tester_token)),
+        ),
+        None => Ok(None),
+    }
+}
+
+/// Newest live release targeted at a tester group the device belongs to, as if it sent the
+/// group's token. For previews, which are only available to the application's users.
+pub fn find_member_release(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    member: MemberIds,
+) -> Result<Option<PinnedRelease>, diesel::result::Error> {
+    pinned_release(conn, organisation, application, member, None)
+}
+
+fn pinned_release(
+    conn: &mut PgConnection,
+    organisation: &str,
+    application: &str,
+    member: MemberIds,
+    token_hash: Option<String>,
+) -> Result<Option<PinnedRelease>, diesel::result::Error> {
+    let member_groups = tester_groups::tester_groups
+        .filter(tester_groups::org_id.eq(organisation))
+        .filter(tester_groups::app_id.eq(application))
+        .select(tester_groups::id.nullable())
+        .into_boxed();
+    let listing = |id: &str| vec![id.to_string()];
+    let mut member_groups = match (member.device_id, member.user_id) {
+        (None, None) => return Ok(None),
+        (Some(device_id), None) => {
+            member_groups.filter(tester_groups::device_ids.contains(listing(device_id)))
+        }
+        (None, Some(user_id)) => {
+            member_groups.filter(tester_groups::user_ids.contains(listing(user_id)))
+        }
+        (Some(device_id), Some(user_id)) => member_groups.filter(
+            tester_groups::device_ids
+                .contains(listing(device_id))
+                .or(tester_groups::user_ids.contains(listing(user_id))),
+        ),
+    };
+    if let Some(token_hash) = token_hash {
+        member_groups = member_groups.filter(tester_groups::token_hash.eq(token_hash));
+    }
+
+    let release = releases::releases
+        .filter(releases::org_id.eq(organisation))
+        .filter(releases::app_id.eq(application))
+        .filter(releases::status.eq(ReleaseStatus::Live.as_str()))
+        .filter(releases::tester_group_id.eq_any(member_groups))
+        .order_by(releases::created_at.desc())
+        .select(ReleaseEntry::as_select())
+        .first(conn)
+        .optional()?;
+
+    Ok(release.and_then(|release| {
+        Some(PinnedRelease {
+            release_id: release.id,
+            tester_group_id: release.tester_group_id?,
+            package_version: release.package_version,
+            config_revision: release.config_revision,
+        })
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_stored_as_their_hash() {
+        let (token, token_hash) = generate_tester_token();
+        assert_eq!(token_hash, hash_tester_token(&token));
+        assert_ne!(token, token_hash);
+        assert_eq!(token_hash.len(), 64);
+    }
+
+    #[test]
+    fn tokens_are_unique_and_url_safe() {
+        let (first, _) = generate_tester_token();
+        let (second, _) = generate_tester_token();
+        assert_ne!(first, second);
+        let decoded = general_purpose::URL_SAFE_NO_PAD.decode(&first).unwrap();
+        assert_eq!(decoded.len(), TESTER_TOKEN_BYTES);
+    }
+
+    #[test]
+    fn hashes_tell_tokens_apart() {
+        assert_eq!(hash_tester_token("token"), hash_tester_token("token"));
+        assert_ne!(hash_tester_token("token"), hash_tester_token("token2"));
+    }
+}